@@ -0,0 +1,64 @@
+use rust_z80_emu::devices::ay38910::*;
+use rust_z80_emu::devices::wav::*;
+
+const RATE: u32 = 44_100;
+
+fn set(psg: &mut Ay38910, reg: u8, data: u8) {
+    psg.select(reg);
+    psg.write(data);
+}
+
+// Number of periods of the signal in the buffer
+fn periods(samples: &[f32]) -> usize {
+    let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+    samples
+        .windows(2)
+        .filter(|w| w[0] < mean && w[1] >= mean)
+        .count()
+}
+
+fn main() {
+    let mut psg = Ay38910::new();
+    psg.set_sample_rate(RATE);
+    let mut samples = Vec::new();
+
+    // 440 Hz on channel A: 1 MHz / (16 * 142)
+    set(&mut psg, 0, 142);
+    set(&mut psg, 7, 0x3E);
+    set(&mut psg, 8, 0x0F);
+    let mut buffer = vec![0.0_f32; RATE as usize];
+    psg.fill_buffer(&mut buffer);
+    let tone = periods(&buffer);
+    println!("Tone: {} Hz", tone);
+    assert!(tone.abs_diff(440) <= 1);
+    samples.extend_from_slice(&buffer);
+
+    // Triangle envelope on channel B: 1 MHz / (256 * 16) / 2
+    set(&mut psg, 7, 0x3F);
+    set(&mut psg, 8, 0x00);
+    set(&mut psg, 9, 0x10);
+    set(&mut psg, 11, 16);
+    set(&mut psg, 13, 0x0E);
+    psg.fill_buffer(&mut buffer);
+    let envelope = periods(&buffer);
+    println!("Envelope: {} Hz", envelope);
+    assert!(envelope.abs_diff(122) <= 1);
+    samples.extend_from_slice(&buffer);
+
+    // Noise on channel C
+    set(&mut psg, 9, 0x00);
+    set(&mut psg, 10, 0x0F);
+    set(&mut psg, 6, 0x08);
+    set(&mut psg, 7, 0x1F);
+    psg.fill_buffer(&mut buffer);
+    samples.extend_from_slice(&buffer);
+
+    // Band limited: the output stays within the levels of the chip
+    assert!(samples.iter().all(|&s| (-0.1..=1.1).contains(&s)));
+
+    let path = std::env::temp_dir().join("ay_sound.wav");
+    save_wav(&path, RATE, &samples).unwrap();
+    let size = std::fs::metadata(&path).unwrap().len();
+    assert_eq!(size, 44 + 2 * samples.len() as u64);
+    println!("{} samples written to {}", samples.len(), path.display());
+}
@@ -0,0 +1,68 @@
+use rust_z80_emu::z80::*;
+
+fn pin(level: bool) -> char {
+    if level {
+        '-'
+    } else {
+        'L'
+    }
+}
+
+fn main() {
+    let mut z80 = Z80::new();
+
+    let code = std::fs::read("resources/data_copy.bin").unwrap();
+    for (addr, opcode) in code.iter().enumerate() {
+        z80.bus.write(addr as u16, *opcode);
+    }
+
+    // Run the program T-state by T-state and show the bus activity
+    println!("   T  ADDR DATA M1 MREQ IORQ RD WR RFSH");
+    let mut t: usize = 0;
+    loop {
+        z80.tick();
+        println!(
+            "{:4}  {:04X}  {:02X}  {}   {}    {}    {}  {}  {}",
+            t,
+            z80.addr_bus,
+            z80.data_bus,
+            pin(z80.n_m1),
+            pin(z80.n_mreq),
+            pin(z80.n_iorq),
+            pin(z80.n_rd),
+            pin(z80.n_wr),
+            pin(z80.n_rfsh)
+        );
+        t += 1;
+        if z80.instruction_complete() && z80.reg.pc == 0x000B {
+            break;
+        }
+    }
+    println!("cycles: {}", t);
+
+    println!("Memory contents at stop:");
+    z80.memory_dump(0, 50);
+
+    // PUSH BC: the M1 cycle lasts 5 T-states, then SP-1 and SP-2 are written
+    let mut z80 = Z80::new();
+    z80.bus.write(0x0000, 0xC5);
+    z80.reg.sp = 0x8000;
+    z80.reg.set_bc(0x1234);
+    let mut n_wr = Vec::new();
+    let mut addr = Vec::new();
+    loop {
+        z80.tick();
+        n_wr.push(z80.n_wr);
+        addr.push(z80.addr_bus);
+        if z80.instruction_complete() {
+            break;
+        }
+    }
+    let writes: Vec<usize> = (0..n_wr.len()).filter(|&t| !n_wr[t]).collect();
+    assert_eq!(writes, [6, 7, 9, 10]);
+    assert_eq!(addr[4], addr[3]);
+    assert_eq!(addr[5..8], [0x7FFF; 3]);
+    assert_eq!(addr[8..11], [0x7FFE; 3]);
+    assert_eq!(z80.bus.read(0x7FFF), 0x12);
+    assert_eq!(z80.bus.read(0x7FFE), 0x34);
+}
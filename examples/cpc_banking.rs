@@ -0,0 +1,51 @@
+use rust_z80_emu::machines::cpc::memory::*;
+use rust_z80_emu::machines::cpc::*;
+use rust_z80_emu::z80::*;
+
+// 16 KiB blocks seen in each page for the configurations C0-C7
+const EXPECTED: [[usize; 4]; 8] = [
+    [0, 1, 2, 3],
+    [0, 1, 2, 7],
+    [4, 5, 6, 7],
+    [0, 3, 2, 7],
+    [0, 4, 2, 3],
+    [0, 5, 2, 3],
+    [0, 6, 2, 3],
+    [0, 7, 2, 3],
+];
+
+fn main() {
+    let mut firmware = vec![0xAA_u8; 0x4000];
+    firmware.extend(vec![0xBB_u8; 0x4000]);
+
+    // 6128: 128 KiB
+    let mut cpc = Cpc::new(&firmware).unwrap();
+    let bus = &mut cpc.cpu.bus;
+    for block in 0..8 {
+        bus.memory.ram[block * PAGE_SIZE] = block as u8;
+    }
+    // ROMs over RAM on reads, writes fall through to RAM
+    assert_eq!(bus.read(0x0000), 0xAA);
+    assert_eq!(bus.read(0xC000), 0xBB);
+    bus.write(0x0000, 0x10);
+    assert_eq!(bus.memory.ram[0x0000], 0x10);
+    bus.memory.ram[0x0000] = 0;
+    // Disable both ROMs
+    bus.write_io(0x7F00, 0x8C);
+    for (config, expected) in EXPECTED.iter().enumerate() {
+        bus.write_io(0x7F00, 0xC0 | config as u8);
+        for (page, &block) in expected.iter().enumerate() {
+            assert_eq!(bus.read((page * PAGE_SIZE) as u16), block as u8);
+        }
+        println!("C{}: {:?}", config, bus.memory.read_pages);
+    }
+
+    // 512 KiB expansion: bank 5, configuration C4
+    let mut cpc = Cpc::with_ram_banks(&firmware, 8).unwrap();
+    let bus = &mut cpc.cpu.bus;
+    assert_eq!(bus.memory.banks(), 8);
+    bus.write_io(0x7F00, 0xC0 | (5 << 3) | 4);
+    bus.write(0x4000, 0x55);
+    assert_eq!(bus.memory.ram[BANK_SIZE * 6], 0x55);
+    println!("Bank 5, C4: {:?}", bus.memory.read_pages);
+}
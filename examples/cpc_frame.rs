@@ -0,0 +1,39 @@
+use rust_z80_emu::machines::cpc::*;
+
+fn main() {
+    // Synthetic firmware: the OS ROM sets up IM 1 and waits for interrupts, the
+    // interrupt handler counts them at 0x4000
+    let mut firmware = vec![0_u8; 0x8000];
+    let boot = [
+        0xF3, // DI
+        0xED, 0x56, // IM 1
+        0x31, 0x00, 0xC0, // LD SP, 0xC000
+        0xFB, // EI
+        0x76, // @LOOP: HALT
+        0x18, 0xFD, // JR @LOOP
+    ];
+    let handler = [
+        0xE5, // PUSH HL
+        0x21, 0x00, 0x40, // LD HL, 0x4000
+        0x34, // INC (HL)
+        0xE1, // POP HL
+        0xFB, // EI
+        0xC9, // RET
+    ];
+    firmware[..boot.len()].copy_from_slice(&boot);
+    firmware[0x38..0x38 + handler.len()].copy_from_slice(&handler);
+
+    let mut cpc = Cpc::new(&firmware).unwrap();
+    // Synchronise on the first VSYNC
+    cpc.run_frame();
+    for frame in 0..10 {
+        let ints = cpc.cpu.bus.memory.ram[0x4000];
+        let start = cpc.cpu.bus.us;
+        cpc.run_frame();
+        let ints = cpc.cpu.bus.memory.ram[0x4000].wrapping_sub(ints);
+        let us = cpc.cpu.bus.us - start;
+        println!("Frame {}: {} us, {} interrupts", frame, us, ints);
+        assert!(us.abs_diff(FRAME_US) < 8);
+        assert_eq!(ints, 6);
+    }
+}
@@ -0,0 +1,45 @@
+use rust_z80_emu::machines::cpc::gate_array::*;
+use rust_z80_emu::machines::cpc::*;
+use rust_z80_emu::z80::*;
+
+fn main() {
+    let firmware = vec![0_u8; 0x8000];
+    let mut cpc = Cpc::new(&firmware).unwrap();
+    let bus = &mut cpc.cpu.bus;
+
+    // Pen 1 bright yellow, border blue
+    bus.write_io(0x7F00, 0x01);
+    bus.write_io(0x7F00, 0x40 | 0x0A);
+    bus.write_io(0x7F00, 0x10);
+    bus.write_io(0x7F00, 0x40 | 0x04);
+    assert_eq!(bus.gate_array.pen_rgb(1), 0xFFFF00);
+    assert_eq!(bus.gate_array.pen_rgb(BORDER), 0x000080);
+    let colours: std::collections::HashSet<u32> = HW_PALETTE.iter().copied().collect();
+    assert_eq!(colours.len(), 27);
+
+    // The mode changes at the next HSYNC
+    bus.write_io(0x7F00, 0x80 | 0x02);
+    assert_eq!(bus.gate_array.mode, 1);
+    while !bus.crtc.hsync {
+        bus.step_us();
+    }
+    assert_eq!(bus.gate_array.mode, 2);
+    println!("Mode 2 from us {}", bus.us);
+
+    // Interrupts come every 52 lines and are resynchronised on the VSYNC
+    let mut last = 0;
+    let mut lines = Vec::new();
+    let mut int = false;
+    for _ in 0..2 * FRAME_US {
+        bus.step_us();
+        if bus.gate_array.int_pending && !int {
+            lines.push((bus.us - last) / 64);
+            last = bus.us;
+            bus.int_ack();
+        }
+        int = bus.gate_array.int_pending;
+    }
+    println!("Lines between interrupts: {:?}", &lines[1..]);
+    // Once synchronised, six interrupts per frame of 312 lines
+    assert!(lines[5..].iter().all(|&l| l == 52));
+}
@@ -0,0 +1,77 @@
+use rust_z80_emu::machines::cpc::keyboard::*;
+use rust_z80_emu::machines::cpc::*;
+
+fn main() {
+    // Scans the 10 rows of the matrix into &4000-&4009 forever
+    let program = [
+        0xF3, //             DI
+        0x01, 0x82, 0xF7, // LD BC, &F782    port A as output
+        0xED, 0x49, //       OUT (C), C
+        0x01, 0x0E, 0xF4, // LD BC, &F40E    PSG register 14
+        0xED, 0x49, //       OUT (C), C
+        0x01, 0xC0, 0xF6, // LD BC, &F6C0    select register
+        0xED, 0x49, //       OUT (C), C
+        0x01, 0x00, 0xF6, // LD BC, &F600    inactive
+        0xED, 0x49, //       OUT (C), C
+        0x01, 0x92, 0xF7, // LD BC, &F792    port A as input
+        0xED, 0x49, //       OUT (C), C
+        0x21, 0x00, 0x40, // @LOOP: LD HL, &4000
+        0x16, 0x40, //       LD D, &40       PSG read, row 0
+        0x06, 0xF6, //       @ROW: LD B, &F6
+        0xED, 0x51, //       OUT (C), D
+        0x06, 0xF4, //       LD B, &F4
+        0xED, 0x78, //       IN A, (C)
+        0x77, //             LD (HL), A
+        0x23, //             INC HL
+        0x14, //             INC D
+        0x7A, //             LD A, D
+        0xFE, 0x4A, //       CP &4A
+        0x20, 0xF0, //       JR NZ, @ROW
+        0x18, 0xE9, //       JR @LOOP
+    ];
+    let mut firmware = vec![0_u8; 0x8000];
+    firmware[..program.len()].copy_from_slice(&program);
+    let mut cpc = Cpc::new(&firmware).unwrap();
+
+    // Keys seen pressed by the program, frame after frame. Capitals are typed with SHIFT.
+    let end = cpc.type_text_at(2, "RUN\"DISC\n");
+    let mut typed = Vec::new();
+    let mut previous = [0xFF_u8; 10];
+    while cpc.frames <= end {
+        cpc.run_frame();
+        let rows: [u8; 10] = cpc.cpu.bus.memory.ram[0x4000..0x400A].try_into().unwrap();
+        for (key, _) in KEYS.iter() {
+            let pressed = rows[key.row()] & key.mask() == 0;
+            let was_pressed = previous[key.row()] & key.mask() == 0;
+            if pressed && !was_pressed {
+                typed.push(key.name());
+            }
+        }
+        previous = rows;
+    }
+    println!("Keys pressed: {:?}", typed);
+    let expected: Vec<&str> = "RUN2DISC"
+        .chars()
+        .flat_map(|c| ["SHIFT", Key::from_char(c).unwrap().0.name()])
+        .chain(["RETURN"])
+        .collect();
+    assert_eq!(typed, expected);
+
+    // Host keys and joystick
+    assert!(cpc.host_key("KeyA", true));
+    assert!(!cpc.host_key("LaunchMail", true));
+    cpc.set_joystick(
+        0,
+        Joystick {
+            up: true,
+            fire1: true,
+            ..Default::default()
+        },
+    );
+    cpc.run_frame();
+    let ram = &cpc.cpu.bus.memory.ram;
+    assert_eq!(ram[0x4000 + Key::A.row()] & Key::A.mask(), 0);
+    assert_eq!(ram[0x4009], !(Key::JoyUp.mask() | Key::JoyFire1.mask()));
+    assert_eq!(Key::from_char('?'), Some((Key::Slash, true)));
+    assert_eq!(Key::from_name("copy"), Some(Key::Copy));
+}
@@ -0,0 +1,64 @@
+use rust_z80_emu::machines::cpc::*;
+use rust_z80_emu::z80::*;
+
+fn main() {
+    // Reads keyboard row 5 through the PSG as the firmware does
+    let program = [
+        0x01, 0x82, 0xF7, // LD BC, &F782    port A as output, port B as input
+        0xED, 0x49, //       OUT (C), C
+        0x01, 0x0E, 0xF4, // LD BC, &F40E    PSG register 14
+        0xED, 0x49, //       OUT (C), C
+        0x01, 0xC0, 0xF6, // LD BC, &F6C0    select register
+        0xED, 0x49, //       OUT (C), C
+        0x01, 0x00, 0xF6, // LD BC, &F600    inactive
+        0xED, 0x49, //       OUT (C), C
+        0x01, 0x92, 0xF7, // LD BC, &F792    port A as input
+        0xED, 0x49, //       OUT (C), C
+        0x01, 0x45, 0xF6, // LD BC, &F645    read row 5
+        0xED, 0x49, //       OUT (C), C
+        0x06, 0xF4, //       LD B, &F4
+        0xED, 0x78, //       IN A, (C)
+        0x01, 0x82, 0xF7, // LD BC, &F782    port A as output
+        0xED, 0x49, //       OUT (C), C
+        0x01, 0x09, 0xF7, // LD BC, &F709    cassette motor on (bit set)
+        0xED, 0x49, //       OUT (C), C
+        0x76, //             HALT
+    ];
+    let mut firmware = vec![0_u8; 0x8000];
+    firmware[..program.len()].copy_from_slice(&program);
+    let mut cpc = Cpc::new(&firmware).unwrap();
+
+    // Space is on row 5 bit 7
+    cpc.cpu.bus.keyboard[5] = 0x7F;
+    while cpc.cpu.n_halt {
+        // Internal cycles are not stretched, step() returns the T-states of `m_cycles`
+        let t_states = cpc.step();
+        let m_cycles = &cpc.cpu.m_cycles;
+        assert_eq!(
+            t_states,
+            m_cycles.iter().map(|m| m.t_states as u32 + m.wait).sum()
+        );
+    }
+    println!(
+        "Row 5: {:#04X}, motor: {}",
+        cpc.cpu.reg.a,
+        cpc.cpu.bus.cassette_motor()
+    );
+    assert_eq!(cpc.cpu.reg.a, 0x7F);
+    assert!(cpc.cpu.bus.cassette_motor());
+    assert_eq!(cpc.cpu.bus.psg.selected, 14);
+
+    // Run T-state by T-state, the program takes as long as with step()
+    let mut ticked = Cpc::new(&firmware).unwrap();
+    while ticked.cpu.n_halt || !ticked.cpu.instruction_complete() {
+        ticked.cpu.tick();
+    }
+    assert_eq!(ticked.cpu.clock, cpc.cpu.clock);
+
+    // Port B: VSYNC and the Amstrad/50 Hz links
+    let bus = &mut cpc.cpu.bus;
+    while !bus.crtc.vsync {
+        bus.step_us();
+    }
+    assert_eq!(bus.read_io(0xF500), 0x7F);
+}
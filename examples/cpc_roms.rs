@@ -0,0 +1,61 @@
+use rust_z80_emu::machines::cpc::*;
+
+fn main() {
+    // Reads &C000 with the upper ROMs 7 and 5 selected
+    let program = [
+        0x01, 0x07, 0xDF, // LD BC, &DF07
+        0xED, 0x49, //       OUT (C), C
+        0x3A, 0x00, 0xC0, // LD A, (&C000)
+        0x32, 0x00, 0x40, // LD (&4000), A
+        0x0E, 0x05, //       LD C, &05
+        0xED, 0x49, //       OUT (C), C
+        0x3A, 0x00, 0xC0, // LD A, (&C000)
+        0x32, 0x01, 0x40, // LD (&4001), A
+        0x76, //             HALT
+    ];
+    let mut firmware = vec![0_u8; 0x8000];
+    firmware[..program.len()].copy_from_slice(&program);
+    // BASIC starts with &80
+    firmware[0x4000] = 0x80;
+    let mut cpc = Cpc::new(&firmware).unwrap();
+
+    // AMSDOS-like ROM in slot 7, a short one in slot 12, one with an AMSDOS header
+    let mut amsdos = vec![0x01_u8; 0x4000];
+    amsdos[0] = 0x07;
+    cpc.set_rom(7, &amsdos).unwrap();
+    cpc.set_rom(12, &[0x0C; 0x100]).unwrap();
+    let mut with_header = vec![0_u8; 128];
+    with_header.extend(vec![0x42_u8; 0x4000]);
+    cpc.set_rom(200, &with_header).unwrap();
+    assert!(cpc.set_rom(3, &[0; 0x8000]).is_err());
+    assert_eq!(cpc.rom_slots(), [0, 7, 12, 200]);
+
+    while cpc.cpu.n_halt {
+        cpc.step();
+    }
+    let ram = &cpc.cpu.bus.memory.ram;
+    println!("ROM 7: {:#04X}, ROM 5: {:#04X}", ram[0x4000], ram[0x4001]);
+    assert_eq!(ram[0x4000], 0x07);
+    // No ROM in slot 5: BASIC is selected
+    assert_eq!(ram[0x4001], 0x80);
+    assert_eq!(cpc.cpu.bus.memory.upper_rom_select, 5);
+    assert_eq!(cpc.cpu.bus.memory.upper_rom_slot(), 0);
+
+    // Padded with &FF
+    cpc.cpu.bus.memory.select_upper_rom(12);
+    assert_eq!(cpc.cpu.bus.memory.read(0xC0FF), 0x0C);
+    assert_eq!(cpc.cpu.bus.memory.read(0xC100), 0xFF);
+    cpc.cpu.bus.memory.select_upper_rom(200);
+    assert_eq!(cpc.cpu.bus.memory.read(0xC000), 0x42);
+
+    // Removing the selected ROM falls back to BASIC
+    assert!(cpc.remove_rom(200).is_some());
+    assert_eq!(cpc.cpu.bus.memory.read(0xC000), 0x80);
+
+    // The selection is kept in snapshots
+    cpc.cpu.bus.memory.select_upper_rom(7);
+    let sna = cpc.save_sna(3).unwrap();
+    cpc.cpu.bus.memory.select_upper_rom(0);
+    cpc.load_sna(&sna).unwrap();
+    assert_eq!(cpc.cpu.bus.memory.read(0xC000), 0x07);
+}
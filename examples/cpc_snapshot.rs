@@ -0,0 +1,103 @@
+use rust_z80_emu::machines::cpc::*;
+use rust_z80_emu::z80::*;
+use std::io::ErrorKind;
+
+fn firmware() -> Vec<u8> {
+    // Counts the interrupts at &4000 and copies R to the screen
+    let mut firmware = vec![0_u8; 0x8000];
+    let boot = [
+        0xF3, //             DI
+        0xED, 0x56, //       IM 1
+        0x31, 0x00, 0xC0, // LD SP, &C000
+        0xFB, //             EI
+        0xED, 0x5F, //       @LOOP: LD A, R
+        0x32, 0x00, 0xC0, // LD (&C000), A
+        0x18, 0xF9, //       JR @LOOP
+    ];
+    let handler = [0xE5, 0x21, 0x00, 0x40, 0x34, 0xE1, 0xFB, 0xC9];
+    firmware[..boot.len()].copy_from_slice(&boot);
+    firmware[0x38..0x38 + handler.len()].copy_from_slice(&handler);
+    firmware
+}
+
+fn main() {
+    let mut cpc = Cpc::new(&firmware()).unwrap();
+    cpc.cpu.bus.write_io(0x7F00, 0xC4);
+    cpc.cpu.bus.memory.write(0x4100, 0x99);
+    for _ in 0..5 {
+        cpc.run_frame();
+    }
+    for _ in 0..1234 {
+        cpc.step();
+    }
+
+    for version in 1..=3 {
+        let sna = cpc.save_sna(version).unwrap();
+        let mut copy = Cpc::new(&firmware()).unwrap();
+        copy.load_sna(&sna).unwrap();
+        assert_eq!(copy.cpu.reg.pc, cpc.cpu.reg.pc);
+        assert_eq!(copy.cpu.reg.get_af(), cpc.cpu.reg.get_af());
+        assert_eq!(copy.cpu.reg.sp, cpc.cpu.reg.sp);
+        assert_eq!(copy.cpu.iff1, cpc.cpu.iff1);
+        assert_eq!(copy.cpu.bus.memory.ram, cpc.cpu.bus.memory.ram);
+        assert_eq!(copy.cpu.bus.memory.read_pages, cpc.cpu.bus.memory.read_pages);
+        assert_eq!(copy.cpu.bus.gate_array.pens, cpc.cpu.bus.gate_array.pens);
+        assert_eq!(copy.cpu.bus.crtc.regs, cpc.cpu.bus.crtc.regs);
+        println!("Version {}: {} bytes", version, sna.len());
+
+        // Version 3 also restores the counters: both machines run the same
+        if version == 3 {
+            let mut original = Cpc::new(&firmware()).unwrap();
+            original.load_sna(&sna).unwrap();
+            for _ in 0..3 {
+                original.run_frame();
+                copy.run_frame();
+            }
+            assert_eq!(copy.cpu.bus.memory.ram, original.cpu.bus.memory.ram);
+            assert_eq!(copy.cpu.clock, original.cpu.clock);
+        }
+    }
+
+    // A bank that does not compress is saved uncompressed: with a lone &E5 and a run of
+    // 4 bytes its compressed size would be exactly 64 KiB, read back as uncompressed
+    let mut bank = Cpc::new(&firmware()).unwrap();
+    let ram = &mut bank.cpu.bus.memory.ram;
+    for (i, byte) in ram[..0x10000].iter_mut().enumerate() {
+        *byte = (i % 0xE5) as u8;
+    }
+    ram[0x100..0x104].fill(0x11);
+    ram[0x200] = 0xE5;
+    let mut copy = Cpc::new(&firmware()).unwrap();
+    copy.load_sna(&bank.save_sna(3).unwrap()).unwrap();
+    assert_eq!(copy.cpu.bus.memory.ram, bank.cpu.bus.memory.ram);
+
+    // Validation errors
+    let sna = cpc.save_sna(2).unwrap();
+    let mut copy = Cpc::new(&firmware()).unwrap();
+    let errors = [
+        copy.load_sna(&sna[..0x80]),
+        copy.load_sna(&sna[..0x8000]),
+        copy.load_sna(b"NOT A SNAPSHOT").map(|_| ()),
+        {
+            let mut bad = sna.clone();
+            bad[0x10] = 4;
+            copy.load_sna(&bad)
+        },
+        {
+            let mut bad = sna.clone();
+            bad[0x6B] = 100;
+            copy.load_sna(&bad)
+        },
+        {
+            let mut bad = cpc.save_sna(3).unwrap();
+            bad.extend(b"MEM5\x03\x00\x00\x00\xE5\x00\x01");
+            copy.load_sna(&bad)
+        },
+    ];
+    for error in errors {
+        let error = error.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        println!("Rejected: {}", error);
+    }
+    assert_eq!(cpc.save_sna(4).unwrap_err().kind(), ErrorKind::InvalidInput);
+}
@@ -0,0 +1,95 @@
+use rust_z80_emu::devices::tape::*;
+use rust_z80_emu::machines::cpc::*;
+use rust_z80_emu::z80::*;
+
+fn tzx() -> Vec<u8> {
+    let mut tzx = b"ZXTape!\x1A\x01\x14".to_vec();
+    // Text description
+    tzx.extend([0x30, 4]);
+    tzx.extend(b"Test");
+    // Emulation info, and an unknown block skipped with its length
+    tzx.extend([0x34, 0, 0, 0, 0, 0, 0, 0, 0]);
+    tzx.extend([0x4F, 3, 0, 0, 0, 1, 2, 3]);
+    // Standard speed block of 2 bytes, 10 ms pause
+    tzx.extend([0x10, 10, 0, 2, 0, 0x00, 0xFF]);
+    // Turbo block: CPC firmware speed, 1 byte with 4 bits used
+    tzx.extend([0x11]);
+    for value in [1000_u16, 500, 500, 700, 1400, 100] {
+        tzx.extend(value.to_le_bytes());
+    }
+    tzx.extend([4, 0, 0, 1, 0, 0, 0xA0]);
+    // Pure tone of 5 pulses, pulse sequence of 3
+    tzx.extend([0x12, 0xE8, 0x03, 5, 0]);
+    tzx.extend([0x13, 3, 0x64, 0, 0xC8, 0, 0x2C, 0x01]);
+    // Stop the tape
+    tzx.extend([0x20, 0, 0]);
+    // Direct recording: 16 samples of 79 T-states, 1100 1100 1111 0000
+    tzx.extend([0x15, 79, 0, 0, 0, 8, 2, 0, 0, 0xCC, 0xF0]);
+    tzx
+}
+
+fn main() {
+    let tape = Tape::from_tzx(&tzx()).unwrap();
+    // Standard block: pilot, sync, 2 pulses per bit and the pause. Turbo block without
+    // pause.
+    let standard = 8063 + 2 + 32 + 1;
+    let turbo = 100 + 2 + 4 * 2;
+    let stop = standard + turbo + 5 + 3;
+    assert_eq!(tape.stops, [stop]);
+    // Direct recording merges the samples of the same level
+    let levels: Vec<(u32, bool)> = tape.pulses[stop..]
+        .iter()
+        .map(|p| (p.length, p.level))
+        .collect();
+    assert_eq!(
+        levels,
+        [(158, true), (158, false), (158, true), (158, false), (316, true), (316, false)]
+    );
+    let first_part: u64 = tape.pulses[..stop].iter().map(|p| p.length as u64).sum();
+
+    let mut cpc = Cpc::new(&vec![0_u8; 0x8000]).unwrap();
+    cpc.insert_tape(tape);
+    let bus = &mut cpc.cpu.bus;
+    // Port C as output, the motor is off: the tape does not move
+    bus.write_io(0xF700, 0x82);
+    for _ in 0..1000 {
+        bus.step_us();
+    }
+    assert!(!bus.tape.running());
+    // Motor on through the PPI, until the tape stops by itself
+    bus.write_io(0xF700, 0x09);
+    let (mut us, mut edges, mut level) = (0_u64, 0, bus.cassette_in);
+    loop {
+        bus.step_us();
+        us += 1;
+        if bus.cassette_in != level {
+            edges += 1;
+            level = bus.cassette_in;
+        }
+        if !bus.tape.running() {
+            break;
+        }
+    }
+    println!("Played {} edges in {} us", edges, us);
+    assert_eq!(edges, stop);
+    // Microseconds for T-states of 3.5 MHz
+    assert!(us.abs_diff(first_part * 2 / 7) <= 1);
+
+    // Record a square wave of 100 us half periods with the motor on
+    bus.tape.recording = true;
+    for i in 0..100 {
+        bus.write_io(0xF700, 0x0A | (i & 1));
+        for _ in 0..100 {
+            bus.step_us();
+        }
+    }
+    assert_eq!(bus.tape.recorded.len(), 99);
+    assert!(bus.tape.recorded.iter().all(|&p| p == 400));
+    let dir = std::env::temp_dir();
+    bus.tape.save_csw(dir.join("cpc_tape.csw"), 44_100).unwrap();
+    bus.tape.save_wav(dir.join("cpc_tape.wav"), 44_100).unwrap();
+    let csw = std::fs::read(dir.join("cpc_tape.csw")).unwrap();
+    assert!(csw.starts_with(b"Compressed Square Wave\x1A"));
+    assert_eq!(csw.len(), 0x20 + 100);
+    println!("Recorded {} pulses", bus.tape.recorded.len() + 1);
+}
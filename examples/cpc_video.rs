@@ -0,0 +1,53 @@
+use rust_z80_emu::machines::cpc::video::*;
+use rust_z80_emu::machines::cpc::*;
+use rust_z80_emu::z80::*;
+
+fn pixel(cpc: &Cpc, x: usize, y: usize) -> u32 {
+    cpc.frame()[y * WIDTH + x]
+}
+
+fn main() {
+    // The Z80 only runs NOPs
+    let mut cpc = Cpc::new(&vec![0_u8; 0x8000]).unwrap();
+    let bus = &mut cpc.cpu.bus;
+    // Border blue, pen 0 black, pen 1 bright yellow, pen 2 bright cyan, pen 3 bright red
+    for (pen, colour) in [(0x10, 0x04), (0x00, 0x14), (0x01, 0x0A), (0x02, 0x13), (0x03, 0x0C)] {
+        bus.write_io(0x7F00, pen);
+        bus.write_io(0x7F00, 0x40 | colour);
+    }
+    // Mode 1: the first line of the screen at &C000 shows pens 1, 2, 3, 0
+    bus.write_io(0x7F00, 0x8D);
+    bus.memory.ram[0xC000] = 0b1010_0110;
+    bus.memory.ram[0xC001] = 0xF0;
+    cpc.run_frame();
+    cpc.run_frame();
+
+    // 4 characters of left border, 40 of top border
+    assert_eq!(pixel(&cpc, 0, 0), 0x000080);
+    let (x, y) = (4 * 16, 40);
+    let line: Vec<u32> = (0..4).map(|p| pixel(&cpc, x + p * 2, y)).collect();
+    println!("First pixels: {:06X?}", line);
+    assert_eq!(line, [0xFFFF00, 0x00FFFF, 0xFF0000, 0x000000]);
+    assert_eq!(pixel(&cpc, x + 8, y), 0xFFFF00);
+
+    // The border changes colour in the middle of a line
+    let bus = &mut cpc.cpu.bus;
+    while !(bus.crtc.vcc == 28 && bus.crtc.hcc == 20) {
+        bus.step_us();
+    }
+    bus.write_io(0x7F00, 0x10);
+    bus.write_io(0x7F00, 0x4C);
+    cpc.run_frame();
+    let y = 40 + 28 * 8;
+    assert_eq!(pixel(&cpc, 10 * 16, y), 0x000080);
+    assert_eq!(pixel(&cpc, 30 * 16, y), 0xFF0000);
+    assert_eq!(pixel(&cpc, 30 * 16, y - 1), 0x000080);
+    println!("Border split on line {}", y);
+
+    let dir = std::env::temp_dir();
+    cpc.cpu.bus.video.save_ppm(dir.join("cpc_video.ppm")).unwrap();
+    cpc.cpu.bus.video.save_png(dir.join("cpc_video.png")).unwrap();
+    let png = std::fs::read(dir.join("cpc_video.png")).unwrap();
+    assert!(png.starts_with(b"\x89PNG"));
+    println!("Screenshots saved in {}", dir.display());
+}
@@ -0,0 +1,112 @@
+use rust_z80_emu::machines::cpm::disk::*;
+use rust_z80_emu::machines::cpm::*;
+
+fn main() {
+    // Disk parameter blocks of the standard formats
+    assert_eq!(
+        IBM_3740.dpb(),
+        [26, 0, 3, 7, 0, 242, 0, 63, 0, 0xC0, 0x00, 16, 0, 2, 0]
+    );
+    assert_eq!(
+        HD_4MB.dpb(),
+        [128, 0, 4, 15, 0, 0xF7, 0x07, 0xFF, 0x03, 0xFF, 0xFF, 0, 0, 0, 0]
+    );
+    assert_eq!(&IBM_3740.translation()[..6], &[1, 7, 13, 19, 25, 5]);
+    assert_eq!(DiskFormat::from_size(256_256), Some(IBM_3740));
+
+    // A stand-in for the CCP at &E400 of a 64K system: it prompts, reads a key, reads
+    // logical sector 1 of track 2 through the BIOS, writes it back changed and warm boots
+    let bios: u16 = 0xFA00;
+    let call = |entry: u16| {
+        let [lo, hi] = (bios + 3 * entry).to_le_bytes();
+        [0xCD, lo, hi]
+    };
+    let mut ccp = vec![0x0E, b'A'];
+    ccp.extend(call(4)); //                 CALL CONOUT
+    ccp.extend([0x0E, b'>']);
+    ccp.extend(call(4));
+    ccp.extend(call(3)); //                 CALL CONIN
+    ccp.extend([0x32, 0x40, 0x00]); //      LD (&0040), A
+    ccp.extend([0x0E, 0x00]); //            LD C, 0
+    ccp.extend(call(9)); //                 CALL SELDSK
+    ccp.extend([0x5E, 0x23, 0x56]); //      LD DE, (HL): translation table
+    ccp.extend([0x01, 0x02, 0x00]); //      LD BC, 2
+    ccp.extend(call(10)); //                CALL SETTRK
+    ccp.extend([0x01, 0x01, 0x00]); //      LD BC, 1
+    ccp.extend(call(16)); //                CALL SECTRAN
+    ccp.extend([0x44, 0x4D]); //            LD BC, HL
+    ccp.extend(call(11)); //                CALL SETSEC
+    ccp.extend([0x01, 0x00, 0x01]); //      LD BC, &0100
+    ccp.extend(call(12)); //                CALL SETDMA
+    ccp.extend(call(13)); //                CALL READ
+    ccp.extend([0x32, 0x41, 0x00]); //      LD (&0041), A
+    ccp.extend([0x21, 0x00, 0x01, 0x34]); // INC (&0100)
+    ccp.extend([0x0E, 0x00]);
+    ccp.extend(call(14)); //                CALL WRITE
+    ccp.extend([0x32, 0x42, 0x00]); //      LD (&0042), A
+    ccp.extend([0xC3, 0x00, 0x00]); //      JP 0: warm boot
+    let mut system = vec![0_u8; SYSTEM_SIZE];
+    system[..3].copy_from_slice(&[0xC3, 0x10, 0xE4]);
+    system[0x10..0x10 + ccp.len()].copy_from_slice(&ccp);
+    // BDOS entry
+    system[0x806..0x809].copy_from_slice(&[0xC3, 0x11, 0xEC]);
+    assert_eq!(system_base(&system), Some(0xE400));
+
+    // On the system tracks after the boot sector, as SYSGEN puts it
+    let mut disk = CpmDisk::new(IBM_3740);
+    for (n, data) in system.chunks(SECTOR_SIZE).enumerate() {
+        let n = n + 1;
+        assert!(disk.write_sector(n / 26, n % 26 + 1, data));
+    }
+    // Logical sector 1 is physical sector 7
+    let mut sector = [0_u8; SECTOR_SIZE];
+    sector[0] = 0x41;
+    disk.write_sector(2, 7, &sector);
+
+    let mut cpm = Cpm::new();
+    cpm.insert_disk(0, disk).unwrap();
+    cpm.insert_disk(1, CpmDisk::new(HD_4MB)).unwrap();
+    cpm.boot().unwrap();
+    assert_eq!((cpm.ccp(), cpm.bdos(), cpm.bios()), (0xE400, 0xEC00, bios));
+    cpm.run(10_000);
+    assert!(cpm.waiting);
+    assert_eq!(cpm.take_output(), b"A>");
+    let memory = &cpm.cpu.bus.memory;
+    assert_eq!(&memory[0..3], &[0xC3, 0x03, 0xFA]);
+    assert_eq!(&memory[5..8], &[0xC3, 0x06, 0xEC]);
+
+    cpm.type_text("x");
+    cpm.run(10_000);
+    assert!(cpm.waiting);
+    assert_eq!(cpm.take_output(), b"A>");
+    let memory = &cpm.cpu.bus.memory;
+    assert_eq!(&memory[0x40..0x43], &[b'x', 0, 0]);
+    assert_eq!(memory[0x100], 0x42);
+    let disk = cpm.disks[0].as_ref().unwrap();
+    assert_eq!(disk.read_sector(2, 7).unwrap()[0], 0x42);
+
+    // Images are saved and loaded as raw sectors
+    let path = std::env::temp_dir().join("cpm_a.dsk");
+    disk.save(&path).unwrap();
+    let disk = CpmDisk::load(&path).unwrap();
+    assert_eq!(disk.format, IBM_3740);
+
+    // Drives past P: do not exist
+    assert!(cpm.insert_disk(DRIVES, CpmDisk::new(IBM_3740)).is_err());
+    assert!(cpm.eject_disk(DRIVES).is_err());
+    // The tables of 16 hard disks do not fit above the BIOS, the drive is left empty
+    let full = (2..DRIVES).find(|&drive| cpm.insert_disk(drive, CpmDisk::new(HD_4MB)).is_err());
+    assert_eq!(full, Some(5));
+    assert!(cpm.disks[5].is_none());
+    // BOOT halts when a disk put in `disks` leaves no room for the tables
+    cpm.disks[5] = Some(CpmDisk::new(HD_4MB));
+    cpm.cpu.reg.pc = cpm.bios();
+    assert!(cpm.run(10) < 10);
+    assert!(cpm.boot_error.is_some() && !cpm.cpu.n_halt);
+
+    // A hard disk has no system tracks to boot from
+    let mut cpm = Cpm::new();
+    cpm.insert_disk(0, CpmDisk::new(HD_4MB)).unwrap();
+    assert!(cpm.boot().is_err());
+    println!("CP/M BIOS calls trapped");
+}
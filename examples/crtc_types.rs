@@ -0,0 +1,81 @@
+use rust_z80_emu::devices::crtc6845::*;
+
+fn write(crtc: &mut Crtc6845, reg: u8, data: u8) {
+    crtc.select(reg);
+    crtc.write(data);
+}
+
+// Runs a frame and returns the number of characters displayed, the HSYNCs and the
+// VSYNC lines
+fn frame(crtc: &mut Crtc6845) -> (u32, u32, u32) {
+    let (mut chars, mut hsyncs, mut vsync_lines) = (0, 0, 0);
+    for _ in 0..312 {
+        let hsync = crtc.hsync;
+        for _ in 0..64 {
+            crtc.step();
+            chars += crtc.disp_en as u32;
+        }
+        hsyncs += (crtc.hsync || hsync) as u32;
+        vsync_lines += crtc.vsync as u32;
+    }
+    (chars, hsyncs, vsync_lines)
+}
+
+fn main() {
+    for crtc_type in [
+        CrtcType::Type0,
+        CrtcType::Type1,
+        CrtcType::Type2,
+        CrtcType::Type3,
+        CrtcType::Type4,
+    ] {
+        let mut crtc = Crtc6845::with_type(crtc_type);
+        // Standard screen: 40 x 25 characters of 8 lines
+        let (chars, _, vsync_lines) = frame(&mut crtc);
+        assert_eq!(chars, 40 * 200);
+        assert_eq!(vsync_lines, if matches!(crtc_type, CrtcType::Type1 | CrtcType::Type2) { 16 } else { 8 });
+
+        // HSYNC width of 0
+        write(&mut crtc, 3, 0x80);
+        let mut hsync = false;
+        for _ in 0..64 * 312 {
+            crtc.step();
+            hsync |= crtc.hsync;
+        }
+        assert_eq!(hsync, !matches!(crtc_type, CrtcType::Type0 | CrtcType::Type1));
+        write(&mut crtc, 3, 0x8E);
+
+        // Registers read back
+        crtc.select(12);
+        let r12 = crtc.read();
+        crtc.select(14);
+        let r14 = crtc.read();
+        println!(
+            "{:?}: VSYNC {} lines, R12 reads {:#04X}, R14 reads {:#04X}, status {:#04X}",
+            crtc_type, vsync_lines, r12, r14, crtc.read_status()
+        );
+    }
+
+    // MA and RA of the first characters of the second row
+    let mut crtc = Crtc6845::new();
+    while !(crtc.vcc == 1 && crtc.vlc == 0 && crtc.hcc == 0) {
+        crtc.step();
+    }
+    assert_eq!((crtc.ma, crtc.ra), (0x3000 + 40, 0));
+    crtc.step();
+    assert_eq!(crtc.ma, 0x3000 + 41);
+
+    // Type 1 takes a new start address while on the first row
+    let mut crtc = Crtc6845::with_type(CrtcType::Type1);
+    for _ in 0..64 * 2 {
+        crtc.step();
+    }
+    write(&mut crtc, 12, 0x20);
+    write(&mut crtc, 13, 0x00);
+    crtc.step();
+    while crtc.hcc != 0 {
+        crtc.step();
+    }
+    assert_eq!(crtc.ma, 0x2000);
+    println!("Type 1 split at line {}: MA {:#06X}", crtc.vlc, crtc.ma);
+}
@@ -0,0 +1,122 @@
+use rust_z80_emu::devices::z80ctc::*;
+use rust_z80_emu::z80::*;
+
+// RAM and a CTC on ports 0x00-0x03. The CPU tells the CTC about RETI through
+// `Bus::reti()`, there is no need to watch the opcode fetches.
+struct CtcBus {
+    ram: FlatBus,
+    ctc: Z80Ctc,
+}
+
+impl Bus for CtcBus {
+    fn read(&self, addr: u16) -> u8 {
+        self.ram.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ram.write(addr, data);
+    }
+
+    fn read_io(&mut self, addr: u16) -> u8 {
+        self.ctc.read(addr as usize & 0x03)
+    }
+
+    fn write_io(&mut self, addr: u16, data: u8) {
+        self.ctc.write(addr as usize & 0x03, data);
+    }
+
+    fn reti(&mut self) {
+        self.ctc.reti();
+    }
+
+    fn n_int(&mut self) -> bool {
+        self.ctc.n_int()
+    }
+
+    fn int_ack(&mut self) -> Option<u8> {
+        self.ctc.int_ack()
+    }
+}
+
+fn main() {
+    let program = [
+        0x31, 0x00, 0x80, // LD SP, &8000
+        0xED, 0x5E, //       IM 2
+        0x3E, 0x10, //       LD A, &10
+        0xED, 0x47, //       LD I, A
+        0xD3, 0x00, //       OUT (0), A: vectors from &10
+        0x3E, 0xA7, //       LD A, &A7: timer, prescaler 256, interrupt
+        0xD3, 0x00, //       OUT (0), A
+        0x3E, 0x64, //       LD A, 100
+        0xD3, 0x00, //       OUT (0), A
+        0x3E, 0xC7, //       LD A, &C7: counter, falling edge, interrupt
+        0xD3, 0x03, //       OUT (3), A
+        0x3E, 0x03, //       LD A, 3
+        0xD3, 0x03, //       OUT (3), A
+        0xFB, //             EI
+        0x76, //             loop: HALT
+        0x18, 0xFD, //       JR loop
+    ];
+    // Count the interrupts of channels 0 and 3
+    let isr0 = [0x21, 0x00, 0x90, 0x34, 0xFB, 0xED, 0x4D]; // INC (&9000) ; EI ; RETI
+    let isr3 = [0x21, 0x01, 0x90, 0x34, 0xFB, 0xED, 0x4D]; // INC (&9001) ; EI ; RETI
+    let mut bus = CtcBus {
+        ram: FlatBus::new(),
+        ctc: Z80Ctc::new(),
+    };
+    for (i, &b) in program.iter().enumerate() {
+        bus.ram.write(i as u16, b);
+    }
+    for (i, (&b0, &b3)) in isr0.iter().zip(isr3.iter()).enumerate() {
+        bus.ram.write(0x0100 + i as u16, b0);
+        bus.ram.write(0x0120 + i as u16, b3);
+    }
+    bus.ram.write(0x1011, 0x01);
+    bus.ram.write(0x1017, 0x01);
+    bus.ram.write(0x1016, 0x20);
+    let mut z80 = Z80::with_bus(bus);
+
+    // 10 periods of 256 x 100 clocks, and 9 pulses on CLK/TRG 3
+    let mut pulses = 0;
+    while z80.clock < 10 * 25_600 + 200 {
+        let start = z80.clock;
+        z80.execute();
+        z80.bus.ctc.run((z80.clock - start) as u32);
+        if z80.clock > 20_000 * (pulses + 1) && pulses < 9 {
+            z80.bus.ctc.trigger(3, true);
+            z80.bus.ctc.trigger(3, false);
+            pulses += 1;
+        }
+    }
+    let ctc = &z80.bus.ctc;
+    println!(
+        "{} timer and {} counter interrupts, counter 0 at {}",
+        z80.bus.ram.read(0x9000),
+        z80.bus.ram.read(0x9001),
+        ctc.read(0)
+    );
+    assert_eq!(z80.bus.ram.read(0x9000), 10);
+    assert_eq!(z80.bus.ram.read(0x9001), 3);
+    assert_eq!(ctc.zc_to[0], 10);
+    assert!(ctc.read(0) <= 100 && ctc.read(0) > 90);
+    assert!(ctc.channels.iter().all(|ch| !ch.in_service));
+
+    // Channel 1 has priority over channel 2, whose vector is given after RETI
+    let mut ctc = Z80Ctc::new();
+    ctc.write(0, 0x40);
+    for channel in [1, 2] {
+        ctc.write(channel, INT_ENABLE | COUNTER_MODE | CONSTANT_FOLLOWS | CONTROL_WORD);
+        ctc.write(channel, 1);
+        ctc.trigger(channel, true);
+        ctc.trigger(channel, false);
+    }
+    assert!(!ctc.n_int());
+    assert!(!ctc.ieo());
+    assert_eq!(ctc.int_ack(), Some(0x42));
+    assert!(ctc.n_int());
+    ctc.reti();
+    assert_eq!(ctc.int_ack(), Some(0x44));
+    ctc.reti();
+    assert!(ctc.ieo());
+    assert_eq!(ctc.zc_to, [0, 1, 1]);
+}
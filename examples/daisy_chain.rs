@@ -0,0 +1,196 @@
+use rust_z80_emu::devices::daisy_chain::{self, DaisyDevice};
+use rust_z80_emu::devices::z80ctc::Z80Ctc;
+use rust_z80_emu::devices::z80pio::{Z80Pio, PORT_A};
+use rust_z80_emu::devices::z80sio::{Z80Sio, CHANNEL_A};
+use rust_z80_emu::z80::*;
+
+// RAM, a CTC on ports 0x00-0x03, a PIO on 0x10-0x13 and a SIO/2 on 0x80-0x83, chained in
+// this order of priority
+struct BoardBus {
+    ram: FlatBus,
+    ctc: Z80Ctc,
+    pio: Z80Pio,
+    sio: Z80Sio,
+}
+
+impl BoardBus {
+    fn chain(&mut self) -> [&mut dyn DaisyDevice; 3] {
+        [&mut self.ctc, &mut self.pio, &mut self.sio]
+    }
+}
+
+impl Bus for BoardBus {
+    fn read(&self, addr: u16) -> u8 {
+        self.ram.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ram.write(addr, data);
+    }
+
+    fn read_io(&mut self, addr: u16) -> u8 {
+        match addr & 0xFC {
+            0x00 => self.ctc.read(addr as usize & 0x03),
+            0x10 => self.pio.read(addr as u8),
+            0x80 => self.sio.read(addr as u8),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_io(&mut self, addr: u16, data: u8) {
+        match addr & 0xFC {
+            0x00 => self.ctc.write(addr as usize & 0x03, data),
+            0x10 => self.pio.write(addr as u8, data),
+            0x80 => self.sio.write(addr as u8, data),
+            _ => {}
+        }
+    }
+
+    fn n_int(&mut self) -> bool {
+        daisy_chain::n_int(&mut self.chain())
+    }
+
+    fn int_ack(&mut self) -> Option<u8> {
+        daisy_chain::int_ack(&mut self.chain())
+    }
+
+    fn reti(&mut self) {
+        daisy_chain::reti(&mut self.chain());
+    }
+}
+
+fn main() {
+    let program = [
+        0x31, 0x00, 0x80, // LD SP, &8000
+        0xED, 0x5E, //       IM 2
+        0x3E, 0x10, //       LD A, &10
+        0xED, 0x47, //       LD I, A
+        0xAF, //             XOR A
+        0xD3, 0x00, //       OUT (0), A: CTC vectors from &00
+        0x3E, 0xA7, //       LD A, &A7
+        0xD3, 0x00, //       OUT (0), A: timer 0 every 2560 T-states
+        0x3E, 0x0A, //       LD A, 10
+        0xD3, 0x00, //       OUT (0), A
+        0x3E, 0x20, //       LD A, &20
+        0xD3, 0x12, //       OUT (&12), A: PIO A vector
+        0x3E, 0x4F, //       LD A, &4F
+        0xD3, 0x12, //       OUT (&12), A: input mode
+        0x3E, 0x87, //       LD A, &87
+        0xD3, 0x12, //       OUT (&12), A: interrupt enabled
+        0x3E, 0x03, //       LD A, 3
+        0xD3, 0x80, //       OUT (&80), A: SIO A WR3
+        0x3E, 0xC1, //       LD A, &C1
+        0xD3, 0x80, //       OUT (&80), A: receive enabled
+        0x3E, 0x01, //       LD A, 1
+        0xD3, 0x80, //       OUT (&80), A: WR1
+        0x3E, 0x18, //       LD A, &18
+        0xD3, 0x80, //       OUT (&80), A: interrupt on each character
+        0x3E, 0x02, //       LD A, 2
+        0xD3, 0x82, //       OUT (&82), A: SIO B WR2
+        0x3E, 0x40, //       LD A, &40
+        0xD3, 0x82, //       OUT (&82), A: vector
+        0x3E, 0x01, //       LD A, 1
+        0xD3, 0x82, //       OUT (&82), A: WR1
+        0x3E, 0x04, //       LD A, 4
+        0xD3, 0x82, //       OUT (&82), A: status affects vector
+        0xFB, //             EI
+        0x76, //             loop: HALT
+        0x18, 0xFD, //       JR loop
+    ];
+    // CTC channel 0: count the ticks
+    let isr_ctc = [0x21, 0x00, 0x90, 0x34, 0xFB, 0xED, 0x4D];
+    // PIO port A: let 3 ticks of the CTC nest, note the SIO interrupts seen meanwhile,
+    // then read the byte
+    let isr_pio = [
+        0x3A, 0x00, 0x90, // LD A, (&9000)
+        0xC6, 0x03, //       ADD A, 3
+        0x47, //             LD B, A
+        0xFB, //             EI
+        0x3A, 0x00, 0x90, // wait: LD A, (&9000)
+        0xB8, //             CP B
+        0x38, 0xFA, //       JR C, wait
+        0x3A, 0x02, 0x90, // LD A, (&9002)
+        0x32, 0x03, 0x90, // LD (&9003), A
+        0xDB, 0x10, //       IN A, (&10)
+        0x32, 0x04, 0x90, // LD (&9004), A
+        0xED, 0x4D, //       RETI
+    ];
+    // SIO channel A receive: count the characters
+    let isr_sio = [0xDB, 0x81, 0x21, 0x02, 0x90, 0x34, 0xFB, 0xED, 0x4D];
+    let mut bus = BoardBus {
+        ram: FlatBus::new(),
+        ctc: Z80Ctc::new(),
+        pio: Z80Pio::new(),
+        sio: Z80Sio::new(),
+    };
+    let routines: [(u16, &[u8]); 4] = [
+        (0x0000, &program),
+        (0x0300, &isr_ctc),
+        (0x0320, &isr_pio),
+        (0x0340, &isr_sio),
+    ];
+    for (addr, code) in routines {
+        for (i, &b) in code.iter().enumerate() {
+            bus.ram.write(addr + i as u16, b);
+        }
+    }
+    for (vector, routine) in [(0x00, 0x0300_u16), (0x20, 0x0320), (0x4C, 0x0340)] {
+        let [lo, hi] = routine.to_le_bytes();
+        bus.ram.write(0x1000 + vector, lo);
+        bus.ram.write(0x1001 + vector, hi);
+    }
+    let mut z80 = Z80::with_bus(bus);
+    let run = |z80: &mut Z80<BoardBus>, instructions| {
+        for _ in 0..instructions {
+            let start = z80.clock;
+            z80.execute();
+            z80.bus.ctc.run((z80.clock - start) as u32);
+        }
+    };
+    run(&mut z80, 100);
+
+    // The PIO and the SIO request together: the PIO comes first in the chain
+    z80.bus.pio.strobe(PORT_A, 0x5A);
+    z80.bus.sio.channels[CHANNEL_A].receive(b'k');
+    assert!(!z80.bus.pio.ieo());
+    run(&mut z80, 5000);
+    let ram = &z80.bus.ram;
+    println!(
+        "{} CTC ticks, SIO served {} times during the PIO routine and {} times after",
+        ram.read(0x9000),
+        ram.read(0x9003),
+        ram.read(0x9002)
+    );
+    assert!(ram.read(0x9000) >= 3);
+    assert_eq!(ram.read(0x9003), 0);
+    assert_eq!(ram.read(0x9002), 1);
+    assert_eq!(ram.read(0x9004), 0x5A);
+    let bus = &mut z80.bus;
+    assert!(bus.chain().iter().all(|device| !device.under_service()));
+
+    // A device down the chain under service still sees RETI while one before it waits
+    // for its acknowledge
+    bus.sio.channels[CHANNEL_A].receive(b'l');
+    assert_eq!(daisy_chain::int_ack(&mut bus.chain()), Some(0x4C));
+    bus.pio.strobe(PORT_A, 0x00);
+    assert!(!daisy_chain::n_int(&mut bus.chain()));
+    daisy_chain::reti(&mut bus.chain());
+    assert!(!bus.sio.under_service());
+
+    // Driven T-state by T-state, RETI reaches the devices when its 4D opcode is latched,
+    // on the third T-state of its second M1 cycle
+    assert!(daisy_chain::int_ack(&mut bus.chain()).is_some());
+    bus.ram.write(0x7000, 0xED);
+    bus.ram.write(0x7001, 0x4D);
+    z80.reset();
+    z80.reg.pc = 0x7000;
+    let under_service = |z80: &mut Z80<BoardBus>| {
+        z80.bus.chain().iter().any(|device| device.under_service())
+    };
+    for _ in 0..6 {
+        z80.tick();
+        assert!(under_service(&mut z80));
+    }
+    z80.tick();
+    assert!(!under_service(&mut z80));
+}
@@ -11,13 +11,13 @@ fn main() {
     let mut cycles: usize = 0;
     println!("Memory contents at start:");
     z80.memory_dump(0, 50);
-    println!("");
+    println!();
     z80.display_regs();
-    println!("");
+    println!();
     loop {
         cycles += z80.execute() as usize;
         z80.display_regs();
-        println!("");
+        println!();
         if z80.reg.pc == 0x0013 {
             break;
         }
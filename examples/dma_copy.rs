@@ -0,0 +1,118 @@
+use rust_z80_emu::z80::*;
+
+// Minimal DMA controller: writing the number of bytes to port 0x00 copies that many
+// bytes from 0x1000 to 0x2000, one byte every 6 T-states while holding the buses.
+struct DmaBus {
+    ram: FlatBus,
+    count: u16,
+    done: u16,
+    wait: u8,
+}
+
+impl Bus for DmaBus {
+    fn read(&self, addr: u16) -> u8 {
+        self.ram.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ram.write(addr, data);
+    }
+
+    fn read_io(&mut self, addr: u16) -> u8 {
+        self.ram.read_io(addr)
+    }
+
+    fn write_io(&mut self, addr: u16, data: u8) {
+        if addr as u8 == 0x00 {
+            self.count = data as u16;
+            self.done = 0;
+        }
+    }
+
+    fn n_busrq(&mut self) -> bool {
+        self.done >= self.count
+    }
+
+    fn bus_released(&mut self, _t_state: u64) {
+        self.wait += 1;
+        if self.wait == 6 {
+            self.wait = 0;
+            let data = self.ram.read(0x1000 + self.done);
+            self.ram.write(0x2000 + self.done, data);
+            self.done += 1;
+        }
+    }
+}
+
+// Asserts /BUSRQ from its second poll on
+struct LateRequestBus {
+    ram: FlatBus,
+    polls: u32,
+}
+
+impl Bus for LateRequestBus {
+    fn read(&self, addr: u16) -> u8 {
+        self.ram.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ram.write(addr, data);
+    }
+
+    fn read_io(&mut self, addr: u16) -> u8 {
+        self.ram.read_io(addr)
+    }
+
+    fn write_io(&mut self, _addr: u16, _data: u8) {}
+
+    fn n_busrq(&mut self) -> bool {
+        self.polls += 1;
+        self.polls < 2
+    }
+}
+
+fn main() {
+    let mut z80 = Z80::with_bus(DmaBus {
+        ram: FlatBus::new(),
+        count: 0,
+        done: 0,
+        wait: 0,
+    });
+
+    // LD A, 0x10 ; OUT (0x00), A ; NOP ; HALT
+    for (addr, opcode) in [0x3E, 0x10, 0xD3, 0x00, 0x00, 0x76].iter().enumerate() {
+        z80.bus.write(addr as u16, *opcode);
+    }
+    for i in 0..16 {
+        z80.bus.write(0x1000 + i, 0xA0 + i as u8);
+    }
+
+    let mut cycles: usize = 0;
+    while z80.n_halt {
+        z80.tick();
+        cycles += 1;
+        if !z80.n_busack {
+            print!("B");
+        } else if z80.instruction_complete() {
+            print!("|");
+        } else {
+            print!(".");
+        }
+    }
+    println!();
+    println!("cycles: {}", cycles);
+    z80.memory_dump(0x2000, 0x200F);
+
+    // tick() samples /BUSRQ once per machine cycle: the NOP it has decoded runs to
+    // its end before the buses are released
+    let mut z80 = Z80::with_bus(LateRequestBus {
+        ram: FlatBus::new(),
+        polls: 0,
+    });
+    for _ in 0..10 {
+        z80.tick();
+    }
+    assert_eq!(z80.reg.pc, 0x0001);
+    assert!(!z80.n_busack);
+    assert_eq!(z80.clock, 10);
+}
@@ -0,0 +1,179 @@
+use rust_z80_emu::devices::dsk::*;
+use rust_z80_emu::devices::upd765::*;
+use rust_z80_emu::machines::cpc::*;
+use rust_z80_emu::z80::*;
+
+// Standard DSK image in DATA format: 40 tracks of 9 sectors &C1-&C9 of 512 bytes
+fn data_format_image() -> Vec<u8> {
+    let mut image = vec![0_u8; 0x100];
+    image[..34].copy_from_slice(b"MV - CPCEMU Disk-File\r\nDisk-Info\r\n");
+    image[0x30] = 40;
+    image[0x31] = 1;
+    image[0x32..0x34].copy_from_slice(&0x1300_u16.to_le_bytes());
+    for track in 0..40_u8 {
+        let mut block = vec![0_u8; 0x100];
+        block[..12].copy_from_slice(b"Track-Info\r\n");
+        block[0x10] = track;
+        block[0x14] = 2;
+        block[0x15] = 9;
+        block[0x16] = 0x4E;
+        block[0x17] = 0xE5;
+        for s in 0..9_u8 {
+            let info = 0x18 + s as usize * 8;
+            block[info..info + 4].copy_from_slice(&[track, 0, 0xC1 + s, 2]);
+        }
+        for s in 0..9_u8 {
+            block.extend((0..512).map(|i| track ^ s ^ i as u8));
+        }
+        image.extend(block);
+    }
+    image
+}
+
+// Sends a command and runs the FDC until the result phase, reading or writing the
+// execution phase data when it is requested
+fn command(fdc: &mut Upd765, bytes: &[u8], data: &mut Vec<u8>) -> Vec<u8> {
+    for &b in bytes {
+        assert_eq!(fdc.read_status() & 0xC0, 0x80);
+        fdc.write_data(b);
+    }
+    let mut written = 0;
+    while fdc.phase == Phase::Execution || fdc.read_status() & 0x0F != 0 {
+        let status = fdc.read_status();
+        if status & 0xA0 == 0xA0 {
+            match status & 0x40 {
+                0 => {
+                    fdc.write_data(data[written]);
+                    written += 1;
+                }
+                _ => data.push(fdc.read_data()),
+            }
+        }
+        fdc.run(4);
+    }
+    let mut result = Vec::new();
+    while fdc.phase == Phase::Result {
+        result.push(fdc.read_data());
+    }
+    result
+}
+
+fn main() {
+    let disk = Disk::from_bytes(&data_format_image()).unwrap();
+    let mut fdc = Upd765::new();
+    fdc.insert(0, disk);
+    fdc.motor = true;
+    let mut data = Vec::new();
+
+    // Seek to track 3 and wait for the end
+    command(&mut fdc, &[0x03, 0xA1, 0x03], &mut data);
+    command(&mut fdc, &[0x0F, 0x00, 3], &mut data);
+    let result = command(&mut fdc, &[0x08], &mut data);
+    assert_eq!(result, [0x20, 3]);
+    assert_eq!(command(&mut fdc, &[0x08], &mut data), [0x80]);
+
+    // Read sectors &C2-&C3, the command ends on EOT with End of Cylinder
+    let result = command(&mut fdc, &[0x46, 0x00, 3, 0, 0xC2, 2, 0xC3, 0x2A, 0xFF], &mut data);
+    println!("Read data: {:02X?}", result);
+    assert_eq!(result, [0x40, 0x80, 0x00, 4, 0, 1, 2]);
+    assert_eq!(data.len(), 1024);
+    assert!(data[..512].iter().enumerate().all(|(i, &b)| b == 3 ^ 1 ^ i as u8));
+
+    // Missing sector
+    data.clear();
+    let result = command(&mut fdc, &[0x46, 0x00, 3, 0, 0x41, 2, 0x41, 0x2A, 0xFF], &mut data);
+    assert_eq!(result[..3], [0x40, 0x04, 0x00]);
+
+    // Write a sector, then save and reload the disk
+    let mut sector = vec![0x5A_u8; 512];
+    let result = command(&mut fdc, &[0x45, 0x00, 3, 0, 0xC5, 2, 0xC5, 0x2A, 0xFF], &mut sector);
+    assert_eq!(result[..2], [0x40, 0x80]);
+    let path = std::env::temp_dir().join("fdc_dsk.dsk");
+    fdc.eject(0).unwrap().save(&path).unwrap();
+    let mut disk = Disk::load(&path).unwrap();
+    let track = disk.track_mut(3, 0).unwrap();
+    assert_eq!(track.sectors[4].read(), &sector[..]);
+
+    // Weak sector: each read gives the next copy
+    let mut copies = vec![0x11_u8; 512];
+    copies.extend(vec![0x22_u8; 512]);
+    track.sectors[0].copies = copies.chunks(512).map(|c| c.to_vec()).collect();
+    disk.save(&path).unwrap();
+    let disk = Disk::load(&path).unwrap();
+    assert!(disk.track(3, 0).unwrap().sectors[0].is_weak());
+    fdc.insert(0, disk);
+    for expected in [0x11, 0x22, 0x11] {
+        data.clear();
+        command(&mut fdc, &[0x46, 0x00, 3, 0, 0xC1, 2, 0xC1, 0x2A, 0xFF], &mut data);
+        assert_eq!(data[0], expected);
+    }
+
+    // Format track 3 with 8 sectors &01-&08 and read their IDs back
+    let mut ids: Vec<u8> = (1..=8).flat_map(|r| [3, 0, r, 2]).collect();
+    let result = command(&mut fdc, &[0x4D, 0x00, 2, 8, 0x50, 0xE5], &mut ids);
+    assert_eq!(result[0], 0x00);
+    for r in 1..=8 {
+        let result = command(&mut fdc, &[0x4A, 0x00], &mut data);
+        assert_eq!(result[3..], [3, 0, r, 2]);
+    }
+
+    // A DSK track holds 29 sectors at most, the format leaves out the IDs past them
+    let mut blank = Upd765::new();
+    blank.insert(0, Disk::new(40, 1));
+    blank.motor = true;
+    let mut ids: Vec<u8> = (1..=40).flat_map(|r| [0, 0, r, 0]).collect();
+    let result = command(&mut blank, &[0x4D, 0x00, 0, 40, 0x10, 0xE5], &mut ids);
+    assert_eq!(result[0], 0x00);
+    let mut disk = blank.eject(0).unwrap();
+    let track = disk.track_mut(0, 0).unwrap();
+    assert_eq!(track.sectors.len(), MAX_SECTORS);
+    track.sectors.push(Sector::new(0, 0, 30, 0, vec![0xE5; 128]));
+    assert!(disk.to_bytes().is_err());
+    disk.track_mut(0, 0).unwrap().sectors.pop();
+    assert!(Disk::from_bytes(&disk.to_bytes().unwrap()).is_ok());
+    // With N=0 the sector length is DTL, kept within 1 to 128
+    blank.insert(0, disk);
+    for (dtl, length) in [(0x00, 1), (0x40, 64), (0xFF, 128)] {
+        data.clear();
+        let result = command(&mut blank, &[0x46, 0x00, 0, 0, 0x01, 0, 0x01, 0x2A, dtl], &mut data);
+        assert_eq!(result[..3], [0x40, 0x80, 0x00]);
+        assert_eq!(data.len(), length);
+    }
+    // The extended header has room for 204 track sizes
+    let mut image = Disk::new(102, 2).to_bytes().unwrap();
+    assert!(Disk::from_bytes(&image).is_ok());
+    image[0x30] = 103;
+    assert!(Disk::from_bytes(&image).is_err());
+    assert!(Disk::new(103, 2).to_bytes().is_err());
+
+    // Overrun when the CPU does not read the data in time
+    fdc.write_data(0x46);
+    for &b in &[0x00, 3, 0, 0x01, 2, 0x01, 0x2A, 0xFF] {
+        fdc.write_data(b);
+    }
+    fdc.run(200);
+    assert_eq!(fdc.phase, Phase::Result);
+    assert_eq!(fdc.read_data(), 0x40);
+    assert_eq!(fdc.read_data(), 0x10);
+    while fdc.phase == Phase::Result {
+        fdc.read_data();
+    }
+    println!("Overrun detected");
+
+    // Drive B is not ready, its head is on track 0
+    let result = command(&mut fdc, &[0x04, 0x01], &mut data);
+    assert_eq!(result, [0x11]);
+
+    // On the CPC: motor at &FA7E, main status register at &FB7E, data at &FB7F
+    let mut cpc = Cpc::new(&vec![0_u8; 0x8000]).unwrap();
+    cpc.insert_disk(0, Disk::load(&path).unwrap());
+    let bus = &mut cpc.cpu.bus;
+    bus.write_io(0xFA7E, 0x01);
+    assert!(bus.fdc.motor);
+    assert_eq!(bus.read_io(0xFB7E), 0x80);
+    bus.write_io(0xFB7F, 0x04);
+    bus.write_io(0xFB7F, 0x00);
+    assert_eq!(bus.read_io(0xFB7E), 0xD0);
+    assert_eq!(bus.read_io(0xFB7F), 0x30);
+    std::fs::remove_file(path).unwrap();
+}
@@ -10,11 +10,11 @@ fn main() {
 
     let mut cycles: usize = 0;
     z80.display_regs();
-    println!("");
+    println!();
     loop {
         cycles += z80.execute() as usize;
         z80.display_regs();
-        println!("");
+        println!();
         if z80.reg.pc == 0x0019 {
             break;
         }
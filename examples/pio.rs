@@ -0,0 +1,148 @@
+use rust_z80_emu::devices::z80pio::*;
+use rust_z80_emu::z80::*;
+
+// RAM and a PIO on ports 0x00-0x03: A data, B data, A control, B control
+struct PioBus {
+    ram: FlatBus,
+    pio: Z80Pio,
+}
+
+impl Bus for PioBus {
+    fn read(&self, addr: u16) -> u8 {
+        self.ram.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ram.write(addr, data);
+    }
+
+    fn read_io(&mut self, addr: u16) -> u8 {
+        match addr & 0xFC {
+            0x00 => self.pio.read(addr as u8),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_io(&mut self, addr: u16, data: u8) {
+        if addr & 0xFC == 0x00 {
+            self.pio.write(addr as u8, data);
+        }
+    }
+
+    fn reti(&mut self) {
+        self.pio.reti();
+    }
+
+    fn n_int(&mut self) -> bool {
+        self.pio.n_int()
+    }
+
+    fn int_ack(&mut self) -> Option<u8> {
+        self.pio.int_ack()
+    }
+}
+
+fn main() {
+    let program = [
+        0x31, 0x00, 0x80, // LD SP, &8000
+        0xED, 0x5E, //       IM 2
+        0x3E, 0x10, //       LD A, &10
+        0xED, 0x47, //       LD I, A
+        // Port A: output, interrupt when the peripheral takes the byte
+        0x3E, 0x20, //       LD A, &20
+        0xD3, 0x02, //       OUT (2), A: vector
+        0x3E, 0x0F, //       LD A, &0F
+        0xD3, 0x02, //       OUT (2), A: output mode
+        0x3E, 0x87, //       LD A, &87
+        0xD3, 0x02, //       OUT (2), A: interrupt enabled
+        // Port B: bit control, lines 7-4 inputs, interrupt when line 4 goes low
+        0x3E, 0x22, //       LD A, &22
+        0xD3, 0x03, //       OUT (3), A: vector
+        0x3E, 0xCF, //       LD A, &CF
+        0xD3, 0x03, //       OUT (3), A: bit control mode
+        0x3E, 0xF0, //       LD A, &F0
+        0xD3, 0x03, //       OUT (3), A: IO mask
+        0x3E, 0x97, //       LD A, &97
+        0xD3, 0x03, //       OUT (3), A: OR, active low, mask follows
+        0x3E, 0xEF, //       LD A, &EF
+        0xD3, 0x03, //       OUT (3), A: line 4 only
+        0x3E, 0x05, //       LD A, &05
+        0xD3, 0x01, //       OUT (1), A: lines 3-0
+        0x3E, 0x55, //       LD A, &55
+        0xD3, 0x00, //       OUT (0), A
+        0xFB, //             EI
+        0x76, //             loop: HALT
+        0x18, 0xFD, //       JR loop
+    ];
+    // Port A: count the bytes taken. Port B: keep its lines.
+    let isr_a = [0x21, 0x00, 0x90, 0x34, 0xFB, 0xED, 0x4D]; // INC (&9000) ; EI ; RETI
+    let isr_b = [0xDB, 0x01, 0x32, 0x01, 0x90, 0xFB, 0xED, 0x4D]; // IN A, (1) ; LD (&9001), A
+    let mut bus = PioBus {
+        ram: FlatBus::new(),
+        pio: Z80Pio::new(),
+    };
+    for (i, &b) in program.iter().enumerate() {
+        bus.ram.write(i as u16, b);
+    }
+    for (i, &b) in isr_a.iter().enumerate() {
+        bus.ram.write(0x0100 + i as u16, b);
+    }
+    for (i, &b) in isr_b.iter().enumerate() {
+        bus.ram.write(0x0120 + i as u16, b);
+    }
+    for (addr, data) in [(0x1020, 0x00), (0x1021, 0x01), (0x1022, 0x20), (0x1023, 0x01)] {
+        bus.ram.write(addr, data);
+    }
+    let mut z80 = Z80::with_bus(bus);
+    let run = |z80: &mut Z80<PioBus>| {
+        for _ in 0..100 {
+            z80.execute();
+        }
+    };
+    run(&mut z80);
+
+    // The byte is ready on port A until the peripheral strobes it
+    let pio = &mut z80.bus.pio;
+    assert!(pio.rdy(PORT_A));
+    assert_eq!(pio.ports[PORT_A].output_lines(), 0x55);
+    assert_eq!(pio.ports[PORT_B].output_lines(), 0xF5);
+    pio.strobe(PORT_A, 0x00);
+    assert!(!pio.rdy(PORT_A));
+    run(&mut z80);
+    assert_eq!(z80.bus.ram.read(0x9000), 1);
+
+    // Line 4 of port B goes low: the other inputs do not interrupt
+    z80.bus.pio.set_lines(PORT_B, 0x7F);
+    run(&mut z80);
+    assert_eq!(z80.bus.ram.read(0x9001), 0x00);
+    z80.bus.pio.set_lines(PORT_B, 0x6F);
+    run(&mut z80);
+    assert_eq!(z80.bus.ram.read(0x9001), 0x65);
+    // Again only once line 4 went back high
+    z80.bus.pio.set_lines(PORT_B, 0x2F);
+    run(&mut z80);
+    assert_eq!(z80.bus.ram.read(0x9001), 0x65);
+    assert!(z80.bus.pio.ieo());
+
+    // Input handshake: /STB latches the byte, RDY is back high once the CPU read it
+    let mut pio = Z80Pio::new();
+    pio.write(0x02, 0x4F);
+    assert!(pio.rdy(PORT_A));
+    pio.strobe(PORT_A, 0x3C);
+    assert!(!pio.rdy(PORT_A));
+    assert_eq!(pio.read(0x00), 0x3C);
+    assert!(pio.rdy(PORT_A));
+
+    // Bidirectional port A uses the handshake of port B for its input
+    pio.write(0x02, 0x8F);
+    pio.write(0x02, 0x83);
+    pio.write(0x02, 0x30);
+    pio.strobe(PORT_B, 0xA5);
+    assert!(!pio.rdy(PORT_B));
+    assert_eq!(pio.int_ack(), Some(0x30));
+    assert!(pio.n_int());
+    pio.reti();
+    assert_eq!(pio.read(0x00), 0xA5);
+    assert!(pio.rdy(PORT_B));
+    println!("PIO handshakes and interrupts done");
+}
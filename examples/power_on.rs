@@ -0,0 +1,81 @@
+use rust_z80_emu::power_on::*;
+use rust_z80_emu::z80::*;
+
+// Registers and a sample of the memory, to compare two power ons
+fn snapshot(z80: &Z80) -> Vec<u8> {
+    let reg = &z80.reg;
+    let mut state = vec![
+        reg.a, reg.b, reg.c, reg.d, reg.e, reg.h, reg.l, reg.i, reg.r,
+    ];
+    state.extend_from_slice(&reg.sp.to_le_bytes());
+    state.extend_from_slice(&reg.get_ix().to_le_bytes());
+    state.extend((0..0x100).map(|i| z80.bus.read(i * 0x100 + i)));
+    state
+}
+
+fn main() {
+    // Fixed values: PC, I and R are cleared as on reset
+    let mut z80 = Z80::new();
+    z80.power_on(&PowerOnState {
+        registers: PowerOnFill::Value(0xAA),
+        memory: PowerOnFill::Value(0x55),
+    });
+    assert_eq!((z80.reg.pc, z80.reg.i, z80.reg.r), (0, 0, 0));
+    assert_eq!(
+        (z80.reg.get_bc(), z80.reg.sp, z80.reg.get_iy()),
+        (0xAAAA, 0xAAAA, 0xAAAA)
+    );
+    assert!((0..=0xFFFF).all(|addr| z80.bus.read(addr) == 0x55));
+
+    // The same seed gives the same machine, another seed a different one
+    let random = |seed| {
+        let mut z80 = Z80::new();
+        z80.power_on(&PowerOnState {
+            registers: PowerOnFill::Random(seed),
+            memory: PowerOnFill::Random(seed),
+        });
+        snapshot(&z80)
+    };
+    assert_eq!(random(1234), random(1234));
+    assert_ne!(random(1234), random(5678));
+    println!(
+        "Power on with seed 1234: A={:02X} B={:02X}",
+        random(1234)[0],
+        random(1234)[1]
+    );
+
+    // Run a bit, then hold /RESET low: the CPU does nothing but clear PC, I and R
+    let program = [
+        0x3E, 0x12, //       LD A, &12
+        0x01, 0x34, 0x56, // LD BC, &5634
+        0xED, 0x47, //       LD I, A
+        0x32, 0x00, 0x80, // LD (&8000), A
+        0x18, 0xFE, //       JR -2
+    ];
+    for (addr, &byte) in program.iter().enumerate() {
+        z80.bus.write(addr as u16, byte);
+    }
+    for _ in 0..5 {
+        z80.execute();
+    }
+    assert_eq!((z80.reg.pc, z80.reg.i), (0x000A, 0x12));
+    z80.n_reset = false;
+    let clock = z80.clock;
+    for _ in 0..10 {
+        assert_eq!(z80.execute(), 1);
+        assert_eq!((z80.reg.pc, z80.reg.i, z80.reg.r), (0, 0, 0));
+    }
+    assert_eq!(z80.clock, clock + 10);
+    assert_eq!(
+        (z80.reg.a, z80.reg.get_bc(), z80.reg.sp),
+        (0x12, 0x5634, 0xAAAA)
+    );
+    assert_eq!(z80.bus.read(0x8000), 0x12);
+    assert_eq!(z80.bus.read(0x8001), 0x55);
+
+    // Released, the CPU starts again from address 0
+    z80.n_reset = true;
+    z80.execute();
+    assert_eq!(z80.reg.pc, 0x0002);
+    println!("Reset held for 10 T-states, registers and memory kept");
+}
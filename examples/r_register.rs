@@ -0,0 +1,76 @@
+use rust_z80_emu::z80::*;
+
+// Load the code at 0x0000 and run `count` instructions
+fn run(code: &[u8], count: usize) -> Z80 {
+    let mut z80 = Z80::new();
+    for (addr, opcode) in code.iter().enumerate() {
+        z80.bus.write(addr as u16, *opcode);
+    }
+    for _ in 0..count {
+        z80.execute();
+    }
+    z80
+}
+
+fn main() {
+    // NOP ; NOP ; NOP
+    let z80 = run(&[0x00, 0x00, 0x00], 3);
+    assert_eq!(z80.reg.r, 3);
+
+    // RLC B (CB 00), CB prefix counts as an M1 cycle
+    let z80 = run(&[0xCB, 0x00], 1);
+    assert_eq!(z80.reg.r, 2);
+
+    // NEG (ED 44)
+    let z80 = run(&[0xED, 0x44], 1);
+    assert_eq!(z80.reg.r, 2);
+
+    // LD IX, 0x0100 (DD 21 00 01)
+    let z80 = run(&[0xDD, 0x21, 0x00, 0x01], 2);
+    assert_eq!(z80.reg.r, 2);
+
+    // LD IX, 0x0100 ; LD HL, 0x0200 ; RLC (IX+2) (DD CB 02 06)
+    // Only DD and CB are M1 cycles, the displacement and the opcode are memory reads
+    let mut z80 = run(&[0xDD, 0x21, 0x00, 0x01, 0x21, 0x00, 0x02], 3);
+    z80.bus.write(0x0102, 0x81);
+    z80.bus.write(0x0200, 0x55);
+    z80.bus.write(0x0007, 0xDD);
+    z80.bus.write(0x0008, 0xCB);
+    z80.bus.write(0x0009, 0x02);
+    z80.bus.write(0x000A, 0x06);
+    let r = z80.reg.r;
+    z80.execute();
+    z80.execute();
+    assert_eq!(z80.reg.r, r + 2);
+    assert_eq!(z80.bus.read(0x0102), 0x03);
+    assert_eq!(z80.bus.read(0x0200), 0x55);
+    assert_eq!(z80.reg.pc, 0x000B);
+
+    // LD A, 0xFE ; LD R, A (ED 4F) keeps bit 7 and the 7 bit counter wraps
+    let mut z80 = run(&[0x3E, 0xFE, 0xED, 0x4F], 2);
+    assert_eq!(z80.reg.r, 0xFE);
+    z80.execute();
+    assert_eq!(z80.reg.r, 0xFF);
+    z80.execute();
+    assert_eq!(z80.reg.r, 0x80);
+
+    // LD A, 0x7F ; LD R, A ; LD A, R (ED 5F) reads R after its two M1 cycles
+    let z80 = run(&[0x3E, 0x7F, 0xED, 0x4F, 0xED, 0x5F], 3);
+    assert_eq!(z80.reg.a, 0x01);
+    assert!(!z80.reg.flags.s);
+
+    // HALT keeps on fetching and incrementing R
+    let z80 = run(&[0x76], 10);
+    assert_eq!(z80.reg.r, 10);
+
+    // IM 1 ; EI ; NOP, then the interrupt acknowledge
+    let mut z80 = run(&[0xED, 0x56, 0xFB, 0x00], 3);
+    assert_eq!(z80.reg.r, 4);
+    z80.reg.sp = 0x8000;
+    z80.n_int = false;
+    z80.execute();
+    assert_eq!(z80.reg.pc, 0x0038);
+    assert_eq!(z80.reg.r, 5);
+
+    println!("R register tests passed");
+}
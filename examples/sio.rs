@@ -0,0 +1,157 @@
+use rust_z80_emu::devices::serial::SerialLink;
+use rust_z80_emu::devices::z80sio::*;
+use rust_z80_emu::z80::*;
+use std::io::{Cursor, Read, Write};
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+// RAM and a SIO/2 on ports 0x80-0x83 as on the RC2014
+struct SioBus {
+    ram: FlatBus,
+    sio: Z80Sio,
+}
+
+impl Bus for SioBus {
+    fn read(&self, addr: u16) -> u8 {
+        self.ram.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ram.write(addr, data);
+    }
+
+    fn read_io(&mut self, addr: u16) -> u8 {
+        match addr & 0xFC {
+            0x80 => self.sio.read(addr as u8),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_io(&mut self, addr: u16, data: u8) {
+        if addr & 0xFC == 0x80 {
+            self.sio.write(addr as u8, data);
+        }
+    }
+
+    fn reti(&mut self) {
+        self.sio.reti();
+    }
+
+    fn n_int(&mut self) -> bool {
+        self.sio.n_int()
+    }
+
+    fn int_ack(&mut self) -> Option<u8> {
+        self.sio.int_ack()
+    }
+}
+
+fn main() {
+    let mut program = vec![
+        0x31, 0x00, 0x80, // LD SP, &8000
+        0xED, 0x5E, //       IM 2
+        0x3E, 0x10, //       LD A, &10
+        0xED, 0x47, //       LD I, A
+    ];
+    // Register and value written to the control port of a channel
+    let setup = [
+        (0x80, 0x00, 0x18), // A: channel reset
+        (0x80, 0x04, 0x44), // x16 clock, 1 stop bit
+        (0x80, 0x03, 0xC1), // 8 bits, receive enabled
+        (0x80, 0x05, 0x68), // 8 bits, transmit enabled
+        (0x80, 0x01, RX_INT_ALL),
+        (0x82, 0x02, 0x40), // vector
+        (0x82, 0x01, STATUS_AFFECTS_VECTOR),
+    ];
+    for (port, register, value) in setup {
+        if register != 0 {
+            program.extend([0x3E, register, 0xD3, port]);
+        }
+        program.extend([0x3E, value, 0xD3, port]);
+    }
+    program.extend([
+        0xFB, //             EI
+        0x76, //             loop: HALT
+        0x18, 0xFD, //       JR loop
+    ]);
+    // Receive interrupt of channel A, vector &4C: the letters are echoed in upper case
+    let isr = [
+        0xDB, 0x81, //       IN A, (&81)
+        0xEE, 0x20, //       XOR &20
+        0xD3, 0x81, //       OUT (&81), A
+        0xFB, //             EI
+        0xED, 0x4D, //       RETI
+    ];
+    let mut bus = SioBus {
+        ram: FlatBus::new(),
+        sio: Z80Sio::new(),
+    };
+    for (i, &b) in program.iter().enumerate() {
+        bus.ram.write(i as u16, b);
+    }
+    for (i, &b) in isr.iter().enumerate() {
+        bus.ram.write(0x0200 + i as u16, b);
+    }
+    bus.ram.write(0x104D, 0x02);
+
+    // Channel A is connected to a terminal through a local TCP socket
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let link = SerialLink::tcp_connect(listener.local_addr().unwrap()).unwrap();
+    bus.sio.connect(CHANNEL_A, link);
+    let (mut terminal, _) = listener.accept().unwrap();
+    terminal.set_nonblocking(true).unwrap();
+    let mut z80 = Z80::with_bus(bus);
+    for _ in 0..100 {
+        z80.execute();
+    }
+    terminal.write_all(b"hello").unwrap();
+    let mut echo = Vec::new();
+    let start = Instant::now();
+    while echo.len() < 5 && start.elapsed() < Duration::from_secs(5) {
+        for _ in 0..100 {
+            z80.execute();
+            z80.bus.sio.run();
+        }
+        let mut buf = [0_u8; 16];
+        if let Ok(n) = terminal.read(&mut buf) {
+            echo.extend_from_slice(&buf[..n]);
+        }
+    }
+    println!("Echoed {:?}", String::from_utf8_lossy(&echo));
+    assert_eq!(echo, b"HELLO");
+    assert!(z80.bus.sio.ieo());
+
+    // Channel B fed through a pipe: only 3 characters fit in the FIFO
+    let sio = &mut z80.bus.sio;
+    sio.connect(CHANNEL_B, SerialLink::from_streams(Cursor::new(b"pipe"), std::io::sink()));
+    sio.write(0x82, 0x03);
+    sio.write(0x82, 0xC1);
+    std::thread::sleep(Duration::from_millis(100));
+    sio.run();
+    assert_eq!(sio.read(0x82) & RX_AVAILABLE, RX_AVAILABLE);
+    assert_eq!(sio.channels[CHANNEL_B].rx_fifo, b"pip");
+    // A fourth character overruns, the special receive condition changes the vector
+    sio.write(0x82, 0x01);
+    sio.write(0x82, RX_INT_ALL | STATUS_AFFECTS_VECTOR);
+    sio.channels[CHANNEL_B].receive(b'!');
+    sio.write(0x82, 0x01);
+    assert_eq!(sio.read(0x82) & RX_OVERRUN, RX_OVERRUN);
+    assert_eq!(sio.int_ack(), Some(0x46));
+    assert_eq!([sio.read(0x83), sio.read(0x83), sio.read(0x83)], *b"pip");
+    sio.write(0x82, 0x30);
+    sio.reti();
+    assert!(sio.n_int());
+    sio.write(0x82, 0x02);
+    assert_eq!(sio.read(0x82), 0x46);
+
+    // The DART has no sync character registers
+    let mut dart = Z80Sio::new_dart();
+    dart.write(0x00, 0x06);
+    dart.write(0x00, 0x7E);
+    assert_eq!(dart.channels[CHANNEL_A].wr[6], 0x00);
+    dart.write(0x01, b'x');
+    dart.write(0x00, 0x05);
+    dart.write(0x00, TX_ENABLE);
+    dart.run();
+    assert_eq!(dart.channels[CHANNEL_A].output, b"x");
+}
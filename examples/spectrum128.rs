@@ -0,0 +1,131 @@
+use rust_z80_emu::m_cycles::{MCycle, MCycleKind};
+use rust_z80_emu::machines::spectrum128::*;
+use rust_z80_emu::machines::spectrum48::ula::*;
+use rust_z80_emu::z80::*;
+
+fn main() {
+    let program = [
+        0xF3, //             DI
+        0x31, 0x00, 0x80, // LD SP, &8000
+        0x01, 0xFD, 0x7F, // LD BC, &7FFD
+        0x3E, 0x01, //       LD A, 1
+        0xED, 0x79, //       OUT (C), A
+        0x3E, 0x11, //       LD A, &11
+        0x32, 0x00, 0xC0, // LD (&C000), A
+        0x3E, 0x03, //       LD A, 3
+        0xED, 0x79, //       OUT (C), A
+        0x3E, 0x33, //       LD A, &33
+        0x32, 0x00, 0xC0, // LD (&C000), A
+        0x01, 0xFD, 0xFF, // LD BC, &FFFD
+        0x3E, 0x07, //       LD A, 7
+        0xED, 0x79, //       OUT (C), A
+        0x06, 0xBF, //       LD B, &BF
+        0x3E, 0x3E, //       LD A, &3E
+        0xED, 0x79, //       OUT (C), A
+        0x06, 0xFF, //       LD B, &FF
+        0xED, 0x78, //       IN A, (C)
+        0x32, 0x00, 0x80, // LD (&8000), A
+        0x76, //             HALT
+    ];
+    let mut roms = vec![0_u8; 2 * ROM_SIZE];
+    roms[..program.len()].copy_from_slice(&program);
+    roms[ROM_SIZE] = 0x48;
+    let mut spectrum = Spectrum128::new(&roms).unwrap();
+    while spectrum.cpu.n_halt {
+        spectrum.step();
+    }
+    let bus = &mut spectrum.cpu.bus;
+    // Banks 1 and 3 were paged at 0xC000, bank 2 is at 0x8000
+    assert_eq!(bus.ram[BANK_SIZE], 0x11);
+    assert_eq!(bus.ram[3 * BANK_SIZE], 0x33);
+    // Mixer register of the PSG read back
+    assert_eq!(bus.ram[2 * BANK_SIZE], 0x3E);
+    assert_eq!(bus.psg.regs[7], 0x3E);
+
+    // ROM 1 and the lock
+    bus.write_io(0x7FFD, 0x30);
+    assert_eq!(bus.read(0x0000), 0x48);
+    bus.write_io(0x7FFD, 0x07);
+    assert_eq!(bus.paging, 0x30);
+    spectrum.reset();
+    assert!(!spectrum.cpu.bus.paging_locked());
+
+    // Odd banks are contended
+    let fetch = |addr| MCycle::new(MCycleKind::OpcodeFetch, addr, 0);
+    let bus = &mut spectrum.cpu.bus;
+    let t = bus.core.frame_start + TIMING_128K.contention_start as u64;
+    for (paging, expected) in [(0x01, 6), (0x02, 0), (0x07, 6)] {
+        bus.write_io(0x7FFD, paging);
+        assert_eq!(bus.wait_states(&fetch(0xC000), t), expected);
+    }
+    assert_eq!(bus.wait_states(&fetch(0x4000), t), 6);
+    assert_eq!(bus.wait_states(&fetch(0x8000), t), 0);
+
+    // The shadow screen in bank 7
+    bus.ram[7 * BANK_SIZE] = 0xFF;
+    bus.ram[7 * BANK_SIZE + 0x1800] = 0x0A;
+    spectrum.run_frame();
+    spectrum.cpu.bus.write_io(0x7FFD, 0x08);
+    spectrum.run_frame();
+    let frame_t = TIMING_128K.frame_t() as u64;
+    println!("Frames of {} T-states", frame_t);
+    assert_eq!(spectrum.cpu.bus.core.frame_start % frame_t, 0);
+    assert_eq!(spectrum.frame()[48 * WIDTH + 48], 0xD70000);
+
+    // 128K SNA with bank 3 paged, PC after the 48 KiB
+    let mut sna = vec![0_u8; 27 + 3 * BANK_SIZE + 4 + 5 * BANK_SIZE];
+    sna[23..25].copy_from_slice(&0x8000_u16.to_le_bytes());
+    sna[25] = 1;
+    sna[26] = 4;
+    sna[27 + 2 * BANK_SIZE] = 0x33;
+    sna[27 + 3 * BANK_SIZE..27 + 3 * BANK_SIZE + 3].copy_from_slice(&[0x34, 0x12, 0x13]);
+    // Banks 0, 1, 4, 6, 7
+    sna[27 + 3 * BANK_SIZE + 4 + 4 * BANK_SIZE] = 0x77;
+    spectrum.load_sna(&sna).unwrap();
+    let bus = &spectrum.cpu.bus;
+    assert_eq!(spectrum.cpu.reg.pc, 0x1234);
+    assert_eq!((bus.paging, bus.core.ula.border), (0x13, 4));
+    assert_eq!((bus.ram[3 * BANK_SIZE], bus.ram[7 * BANK_SIZE]), (0x33, 0x77));
+    assert!(spectrum.load_sna(&sna[..sna.len() - 1]).is_err());
+
+    // 48K SNA: the PC is popped from the stack
+    let mut sna = vec![0_u8; 27 + 3 * BANK_SIZE];
+    sna[23..25].copy_from_slice(&0xFFFE_u16.to_le_bytes());
+    sna[27 + 3 * BANK_SIZE - 2..].copy_from_slice(&[0x78, 0x56]);
+    spectrum.load_sna(&sna).unwrap();
+    assert_eq!((spectrum.cpu.reg.pc, spectrum.cpu.reg.sp), (0x5678, 0x0000));
+    assert!(spectrum.cpu.bus.paging_locked());
+
+    // Version 3 Z80 of a 128K: bank 7 compressed, bank 0 stored
+    let mut z80 = vec![0_u8; 30];
+    z80[0] = 0xAA;
+    z80[8..10].copy_from_slice(&0x9000_u16.to_le_bytes());
+    z80[29] = 2;
+    let mut extra = vec![0_u8; 54];
+    extra[0..2].copy_from_slice(&0x4321_u16.to_le_bytes());
+    extra[2] = 4;
+    extra[3] = 0x17;
+    extra[6] = 8;
+    extra[7 + 8] = 0x0F;
+    z80.extend_from_slice(&54_u16.to_le_bytes());
+    z80.extend_from_slice(&extra);
+    let mut compressed = vec![0xED, 0xED, 0xFF, 0x99];
+    for _ in 0..(BANK_SIZE - 0xFF) / 0xFF {
+        compressed.extend_from_slice(&[0xED, 0xED, 0xFF, 0x00]);
+    }
+    compressed.extend(vec![0x00; (BANK_SIZE - 0xFF) % 0xFF]);
+    z80.extend_from_slice(&(compressed.len() as u16).to_le_bytes());
+    z80.push(10);
+    z80.extend_from_slice(&compressed);
+    z80.extend_from_slice(&[0xFF, 0xFF, 3]);
+    z80.extend(vec![0x55; BANK_SIZE]);
+    spectrum.load_z80(&z80).unwrap();
+    let cpu = &spectrum.cpu;
+    assert_eq!((cpu.reg.a, cpu.reg.pc, cpu.reg.sp), (0xAA, 0x4321, 0x9000));
+    assert!(matches!(cpu.im, InterruptMode::IM_2));
+    assert_eq!(cpu.bus.paging, 0x17);
+    assert_eq!((cpu.bus.ram[7 * BANK_SIZE + 0xFE], cpu.bus.ram[7 * BANK_SIZE + 0xFF]), (0x99, 0));
+    assert_eq!(cpu.bus.ram[0], 0x55);
+    assert_eq!((cpu.bus.psg.selected, cpu.bus.psg.regs[8]), (8, 0x0F));
+    println!("128K snapshots loaded");
+}
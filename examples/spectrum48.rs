@@ -0,0 +1,119 @@
+use rust_z80_emu::machines::spectrum48::keyboard::Key;
+use rust_z80_emu::machines::spectrum48::ula::*;
+use rust_z80_emu::machines::spectrum48::*;
+use rust_z80_emu::z80::*;
+
+fn main() {
+    let mut rom = vec![0_u8; ROM_SIZE];
+    let start = [
+        0xF3, //             DI
+        0x31, 0x00, 0x80, // LD SP, &8000
+        0x3E, 0x02, //       LD A, 2 (red border)
+        0xD3, 0xFE, //       OUT (&FE), A
+        0xED, 0x56, //       IM 1
+        0xFB, //             EI
+        0x76, //             HALT
+        0x18, 0xFD, //       JR -3
+    ];
+    // Counts the frames, reads the A-G half-row and toggles the speaker
+    let interrupt = [
+        0x2A, 0x00, 0x80, // LD HL, (&8000)
+        0x23, //             INC HL
+        0x22, 0x00, 0x80, // LD (&8000), HL
+        0x3E, 0xFD, //       LD A, &FD
+        0xDB, 0xFE, //       IN A, (&FE)
+        0x32, 0x02, 0x80, // LD (&8002), A
+        0x3A, 0x03, 0x80, // LD A, (&8003)
+        0xEE, 0x10, //       XOR &10
+        0x32, 0x03, 0x80, // LD (&8003), A
+        0xF6, 0x02, //       OR 2
+        0xD3, 0xFE, //       OUT (&FE), A
+        0xFB, //             EI
+        0xC9, //             RET
+    ];
+    rom[..start.len()].copy_from_slice(&start);
+    rom[0x38..0x38 + interrupt.len()].copy_from_slice(&interrupt);
+    let mut spectrum = Spectrum48::new(&rom).unwrap();
+    assert!(Spectrum48::new(&rom[..0x2000]).is_err());
+    spectrum.cpu.bus.core.beeper.set_sample_rate(44_100);
+
+    // Top left cell: 4 pixels of bright white ink on black paper
+    spectrum.cpu.bus.memory[0x4000] = 0xF0;
+    spectrum.cpu.bus.memory[0x5800] = 0x47;
+    spectrum.press_key(Key::A);
+
+    for _ in 0..10 {
+        spectrum.run_frame();
+    }
+    let bus = &spectrum.cpu.bus;
+    let frames = u16::from_le_bytes([bus.memory[0x8000], bus.memory[0x8001]]);
+    println!(
+        "{} interrupts in 10 frames of {} T-states",
+        frames,
+        TIMING_48K.frame_t()
+    );
+    // Interrupts are still disabled at the start of the first frame
+    assert_eq!(frames, 9);
+    assert_eq!(bus.core.frame_start, 10 * 69_888);
+    // A is bit 0 of the half-row
+    assert_eq!(bus.memory[0x8002] & 0x1F, 0x1E);
+
+    let pixel = |x: usize, y: usize| spectrum.frame()[y * WIDTH + x];
+    assert_eq!(pixel(0, 0), 0xD70000);
+    assert_eq!(pixel(48, 48), 0xFFFFFF);
+    assert_eq!(pixel(52, 48), 0x000000);
+    let path = std::env::temp_dir().join("spectrum48.png");
+    spectrum.cpu.bus.core.ula.save_png(&path).unwrap();
+    println!("Frame saved to {}", path.display());
+
+    // Contention 6, 5, 4, 3, 2, 1, 0, 0 during the 128 T-states of each screen line
+    let ula = &spectrum.cpu.bus.core.ula;
+    let delays: Vec<u8> = (14335..14343).map(|t| ula.contention(t)).collect();
+    assert_eq!(delays, [6, 5, 4, 3, 2, 1, 0, 0]);
+    assert_eq!(ula.contention(14335 + 128), 0);
+    assert_eq!(ula.contention(14335 + 224), 6);
+    assert_eq!(ula.contention(14335 + 192 * 224), 0);
+    assert_eq!(ula.contention(1000), 0);
+    // IO: N:4, N:1 C:3, C:1 C:3 and C:1 C:1 C:1 C:1
+    assert_eq!(ula.io_contention(0x00FF, false, 14335), 0);
+    assert_eq!(ula.io_contention(0x00FE, false, 14335), 5);
+    assert_eq!(ula.io_contention(0x40FE, true, 14335), 6);
+    assert_eq!(ula.io_contention(0x40FF, true, 14335), 6 + 6);
+
+    // Instructions started at T-state 14335, where the ULA starts reading the screen,
+    // against the contention patterns of FUSE and the Sinclair wiki. Internal T-states
+    // wait too when their address is contended.
+    let timed = |code: &[u8], at: u16, setup: fn(&mut Z80<Spectrum48Bus>)| {
+        let mut spectrum = Spectrum48::new(&rom).unwrap();
+        let cpu = &mut spectrum.cpu;
+        cpu.bus.memory[at as usize..at as usize + code.len()].copy_from_slice(code);
+        cpu.reg.pc = at;
+        setup(cpu);
+        cpu.clock = 14335;
+        spectrum.step()
+    };
+    // INC (HL): pc:4 hl:3 hl:1 hl:3, 11 T-states uncontended
+    let inc_hl = |cpu: &mut Z80<Spectrum48Bus>| cpu.reg.set_hl(0x4000);
+    assert_eq!(timed(&[0x34], 0x8000, inc_hl), 18);
+    // PUSH BC: pc:4 ir:1 sp-1:3 sp-2:3, 11 T-states
+    let push_bc = |cpu: &mut Z80<Spectrum48Bus>| {
+        cpu.reg.i = 0x40;
+        cpu.reg.sp = 0x4002;
+    };
+    assert_eq!(timed(&[0xC5], 0x8000, push_bc), 17);
+    // JR: pc:4 pc+1:3 pc+1:1 x5, 12 T-states
+    assert_eq!(timed(&[0x18, 0x00], 0x4000, |_| {}), 39);
+    // LDIR repeating: pc:4 pc+1:4 hl:3 de:3 de:1 x2 de:1 x5, 21 T-states
+    let ldir = |cpu: &mut Z80<Spectrum48Bus>| {
+        cpu.reg.set_hl(0x8100);
+        cpu.reg.set_de(0x4000);
+        cpu.reg.set_bc(2);
+    };
+    assert_eq!(timed(&[0xED, 0xB0], 0x8000, ldir), 47);
+
+    // The speaker toggles every frame
+    let mut samples = vec![0.0; spectrum.cpu.bus.core.beeper.samples_ready()];
+    spectrum.cpu.bus.core.beeper.read_samples(&mut samples);
+    println!("{} samples", samples.len());
+    assert!(samples.iter().any(|&s| s > 0.9) && samples.iter().any(|&s| s < 0.1));
+}
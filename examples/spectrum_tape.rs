@@ -0,0 +1,109 @@
+use rust_z80_emu::devices::tape::Tape;
+use rust_z80_emu::machines::spectrum48::tape::LD_BYTES;
+use rust_z80_emu::machines::spectrum48::*;
+
+// Flag, bytes and checksum as saved by the ROM
+fn tap_block(flag: u8, bytes: &[u8]) -> Vec<u8> {
+    let mut block = vec![flag];
+    block.extend_from_slice(bytes);
+    block.push(block.iter().fold(0, |parity, &b| parity ^ b));
+    let mut tap = (block.len() as u16).to_le_bytes().to_vec();
+    tap.extend(block);
+    tap
+}
+
+fn main() {
+    // A header for "demo" and 256 bytes of code
+    let mut header = vec![0x03];
+    header.extend_from_slice(b"demo      ");
+    header.extend_from_slice(&[0x00, 0x01, 0x00, 0xA0, 0x00, 0x80]);
+    let code: Vec<u8> = (0..=255).collect();
+    let mut tap = tap_block(0x00, &header);
+    tap.extend(tap_block(0xFF, &code));
+    let path = std::env::temp_dir().join("demo.tap");
+    std::fs::write(&path, &tap).unwrap();
+    let tape = Tape::load(&path).unwrap();
+    assert_eq!(tape.blocks.len(), 2);
+    assert_eq!(tape.blocks[1].data.len(), 258);
+    // Pilot, sync and two pulses per bit
+    let data_block = &tape.blocks[1];
+    assert_eq!(data_block.end - data_block.start, 3223 + 2 + 258 * 16);
+
+    // Played in real time: a loop counts the edges seen on bit 6 of port &FE
+    let counter = [
+        0xF3, //             DI
+        0x21, 0x00, 0x00, // LD HL, 0
+        0x1E, 0x00, //       LD E, 0
+        0x3E, 0x7F, //       loop: LD A, &7F
+        0xDB, 0xFE, //       IN A, (&FE)
+        0xE6, 0x40, //       AND &40
+        0xBB, //             CP E
+        0x28, 0xF7, //       JR Z, loop
+        0x5F, //             LD E, A
+        0x23, //             INC HL
+        0x22, 0x00, 0x80, // LD (&8000), HL
+        0x18, 0xF0, //       JR loop
+    ];
+    let mut rom = vec![0_u8; ROM_SIZE];
+    rom[..counter.len()].copy_from_slice(&counter);
+    let mut spectrum = Spectrum48::new(&rom).unwrap();
+    spectrum.insert_tape(Tape::load(&path).unwrap());
+    for _ in 0..20 {
+        spectrum.run_frame();
+    }
+    let bus = &spectrum.cpu.bus;
+    let edges = u16::from_le_bytes([bus.memory[0x8000], bus.memory[0x8001]]) as usize;
+    println!(
+        "{} edges seen, {} pulses played",
+        edges,
+        bus.core.tape.position()
+    );
+    assert!(edges.abs_diff(bus.core.tape.position()) <= 1);
+
+    // Fast load: the ROM calls LD-BYTES for the header and the code, the trap loads them
+    let loader = [
+        0x31, 0x00, 0x80, // LD SP, &8000
+        0xDD, 0x21, 0x00, 0x90, // LD IX, &9000
+        0x11, 0x11, 0x00, // LD DE, 17
+        0xAF, //             XOR A
+        0x37, //             SCF
+        0xCD, 0x56, 0x05, // CALL LD-BYTES
+        0xF5, //             PUSH AF
+        0xDD, 0x21, 0x00, 0xA0, // LD IX, &A000
+        0x11, 0x00, 0x01, // LD DE, 256
+        0x3E, 0xFF, //       LD A, &FF
+        0x37, //             SCF
+        0xCD, 0x56, 0x05, // CALL LD-BYTES
+        0xF5, //             PUSH AF
+        0x76, //             HALT
+    ];
+    let mut rom = vec![0_u8; ROM_SIZE];
+    rom[..loader.len()].copy_from_slice(&loader);
+    // SA/LD-RET only returns here
+    rom[0x053F] = 0xC9;
+    let mut spectrum = Spectrum48::new(&rom).unwrap();
+    spectrum.insert_tape(Tape::load(&path).unwrap());
+    spectrum.cpu.bus.core.tape.playing = false;
+    while spectrum.cpu.n_halt {
+        spectrum.step();
+    }
+    let bus = &spectrum.cpu.bus;
+    assert_eq!(&bus.memory[0x9000..0x9011], &header[..]);
+    assert_eq!(&bus.memory[0xA000..0xA100], &code[..]);
+    // Both carries set
+    assert_eq!(bus.memory[0x7FFC] & 0x01, 0x01);
+    assert_eq!(bus.memory[0x7FFE] & 0x01, 0x01);
+    assert_eq!(spectrum.cpu.reg.get_de(), 0);
+    println!("Blocks loaded at once in {} T-states", spectrum.cpu.clock);
+    assert!(spectrum.cpu.clock < 1000);
+
+    // No trap: LD-BYTES at 0x0556 runs, here an infinite loop
+    let mut spectrum = Spectrum48::new(&rom).unwrap();
+    spectrum.fast_load = false;
+    spectrum.cpu.bus.memory[LD_BYTES as usize] = 0x18;
+    spectrum.cpu.bus.memory[LD_BYTES as usize + 1] = 0xFE;
+    for _ in 0..1000 {
+        spectrum.step();
+    }
+    assert_eq!(spectrum.cpu.reg.pc, LD_BYTES);
+}
@@ -0,0 +1,69 @@
+use rust_z80_emu::m_cycles::{MCycle, MCycleKind};
+use rust_z80_emu::z80::*;
+
+// Like on the Amstrad CPC, /WAIT is only released on one T-state out of 4: bus cycles
+// are stretched until they sample it there
+struct StretchedBus {
+    ram: FlatBus,
+}
+
+impl Bus for StretchedBus {
+    fn read(&self, addr: u16) -> u8 {
+        self.ram.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.ram.write(addr, data);
+    }
+
+    fn read_io(&mut self, addr: u16) -> u8 {
+        self.ram.read_io(addr)
+    }
+
+    fn write_io(&mut self, addr: u16, data: u8) {
+        self.ram.write_io(addr, data);
+    }
+
+    fn wait_states(&mut self, m: &MCycle, t_state: u64) -> u8 {
+        match m.wait_sample() {
+            Some(sample) => ((5 - (t_state + sample as u64) % 4) % 4) as u8,
+            None => 0,
+        }
+    }
+}
+
+fn main() {
+    let mut z80 = Z80::with_bus(StretchedBus {
+        ram: FlatBus::new(),
+    });
+
+    let code = std::fs::read("resources/multiply_u16.bin").unwrap();
+    for (addr, opcode) in code.iter().enumerate() {
+        z80.bus.write(addr as u16, *opcode);
+    }
+
+    let mut cycles: usize = 0;
+    loop {
+        let pc = z80.reg.pc;
+        let t = z80.execute();
+        cycles += t as usize;
+        print!("{:04X}: {:2} T-states |", pc, t);
+        for m in z80.m_cycles.iter() {
+            let kind = match m.kind {
+                MCycleKind::OpcodeFetch => "M1",
+                MCycleKind::MemoryRead => "MR",
+                MCycleKind::MemoryWrite => "MW",
+                MCycleKind::IoRead => "IR",
+                MCycleKind::IoWrite => "IW",
+                MCycleKind::InterruptAck => "INTA",
+                MCycleKind::Internal => "--",
+            };
+            print!(" {}:{}+{}", kind, m.t_states, m.wait);
+        }
+        println!();
+        if z80.reg.pc == 0x0019 {
+            break;
+        }
+    }
+    println!("cycles: {}", cycles);
+}
@@ -18,13 +18,13 @@ fn main() {
             println!("\nCPU restarted!");
             break;
         }
-        if z80.n_halt == false {
+        if !z80.n_halt {
             println!("\nCPU halted!");
             break;
         }
 
         z80.memory_dump(0x0000, 0x22ff);
-        println!("");
+        println!();
         z80.display_regs();
 
         let mut input = String::new();
@@ -1,33 +1,88 @@
+use crate::m_cycles::MCycle;
+
 const MEMORY_SIZE: usize = 65_536;
 
-pub struct Bus {
+// Everything the CPU talks to: memory, IO devices and wait state generation
+pub trait Bus {
+    fn read(&self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, data: u8);
+    fn read_io(&mut self, addr: u16) -> u8;
+    fn write_io(&mut self, addr: u16, data: u8);
+
+    // Number of wait states a device inserts in the machine cycle `m` starting at
+    // T-state `t_state`. They are added after T2 (after the automatic wait state for IO).
+    // Internal cycles, where /WAIT is not sampled, may be delayed too as the ULA of the
+    // ZX Spectrum does when their address is contended.
+    fn wait_states(&mut self, _m: &MCycle, _t_state: u64) -> u8 {
+        0
+    }
+
+    // Level of /INT driven by the devices
+    fn n_int(&mut self) -> bool {
+        true
+    }
+
+    // The CPU acknowledges a maskable interrupt. A device may put a byte on the data
+    // bus, `int_vector` of the CPU is used otherwise.
+    fn int_ack(&mut self) -> Option<u8> {
+        None
+    }
+
+    // The CPU executed RETI (ED 4D), which the Zilog peripherals decode to end the
+    // service of their interrupt
+    fn reti(&mut self) {}
+
+    // Level of /BUSRQ driven by the devices, e.g. a DMA controller
+    fn n_busrq(&mut self) -> bool {
+        true
+    }
+
+    // Called for each T-state while the CPU has released the buses (/BUSACK low).
+    // The device which requested the buses performs its memory and IO cycles here.
+    fn bus_released(&mut self, _t_state: u64) {}
+
+    // The devices see /RESET going low
+    fn reset(&mut self) {}
+
+    // Power on: RAM holds whatever `fill` gives
+    fn power_on(&mut self, _fill: &mut dyn FnMut() -> u8) {}
+}
+
+// 64 KiB of RAM with nothing connected to the IO ports
+pub struct FlatBus {
     memory: [u8; MEMORY_SIZE],
 }
 
-impl Bus {
+impl Default for FlatBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlatBus {
     pub fn new() -> Self {
         Self {
             memory: [0_u8; MEMORY_SIZE],
         }
     }
+}
 
-    pub fn read(&self, addr: u16) -> u8 {
+impl Bus for FlatBus {
+    fn read(&self, addr: u16) -> u8 {
         self.memory[addr as usize]
     }
 
-    pub fn write(&mut self, addr: u16, data: u8) {
+    fn write(&mut self, addr: u16, data: u8) {
         self.memory[addr as usize] = data;
     }
 
-    pub fn reset(&mut self) {
-        self.memory.fill(0_u8);
+    fn read_io(&mut self, addr: u16) -> u8 {
+        addr as u8
     }
-}
 
-pub fn read_io(addr: u16) -> u8 {
-    addr as u8
-}
+    fn write_io(&mut self, _addr: u16, _data: u8) {}
 
-pub fn write_io(addr: u16, data: u8) {
-    let _d = addr + data as u16;
+    fn power_on(&mut self, fill: &mut dyn FnMut() -> u8) {
+        self.memory.iter_mut().for_each(|m| *m = fill());
+    }
 }
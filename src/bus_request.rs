@@ -0,0 +1,38 @@
+use crate::z80::*;
+
+// Maximum number of T-states spent with the buses released in a single call to execute()
+const MAX_RELEASE: u8 = 255;
+
+impl<B: Bus> Z80<B> {
+    // /BUSRQ can be driven by the host through `n_busrq` or by a device on the bus
+    pub fn bus_requested(&mut self) -> bool {
+        !self.n_busrq || !self.bus.n_busrq()
+    }
+
+    // The buses are released: control pins are floating (seen inactive) and /BUSACK is low
+    pub(crate) fn release_bus(&mut self) {
+        self.n_m1 = true;
+        self.n_mreq = true;
+        self.n_iorq = true;
+        self.n_rd = true;
+        self.n_wr = true;
+        self.n_rfsh = true;
+        self.n_busack = false;
+        self.bus.bus_released(self.clock);
+        self.clock += 1;
+    }
+
+    // Bus request seen at the end of an instruction: the buses stay released as long as
+    // it is asserted. Returns the number of T-states spent.
+    pub(crate) fn bus_release_cycles(&mut self) -> u8 {
+        let mut cycles = 0_u8;
+        while cycles < MAX_RELEASE && self.bus_requested() {
+            self.release_bus();
+            cycles += 1;
+        }
+        if cycles < MAX_RELEASE {
+            self.n_busack = true;
+        }
+        cycles
+    }
+}
@@ -1,16 +1,25 @@
 use crate::{cycles::{CYCLES_CB, CYCLES_DD_FD_CB}, z80::*};
 
-impl Z80 {
+impl<B: Bus> Z80<B> {
+    // Address of the (IX+d) (IY+d) operand, the result is computed during an internal
+    // T-state before it is written back
+    fn index_addr(&self, d: u8) -> Option<u16> {
+        match self.p_inst {
+            0xDD => Some(self.reg.get_ix().wrapping_add((d as i8) as u16)),
+            0xFD => Some(self.reg.get_iy().wrapping_add((d as i8) as u16)),
+            _ => None,
+        }
+    }
+
     fn rlc_r(&mut self, reg: u8, d: u8) -> u8 {
         let data = match self.p_inst {
-            0xDD => self
-                .bus
-                .read(self.reg.get_ix().wrapping_add((d as i8) as u16)),
-            0xFD => self
-                .bus
-                .read(self.reg.get_iy().wrapping_add((d as i8) as u16)),
+            0xDD => self.read_mem(self.reg.get_ix().wrapping_add((d as i8) as u16)),
+            0xFD => self.read_mem(self.reg.get_iy().wrapping_add((d as i8) as u16)),
             _ => reg,
         };
+        if let Some(addr) = self.index_addr(d) {
+            self.record_internal(addr, 1);
+        }
         let r = data.rotate_left(1);
         self.reg.flags.s = (r as i8) < 0;
         self.reg.flags.z = r == 0;
@@ -19,12 +28,8 @@ impl Z80 {
         self.reg.flags.n = false;
         self.reg.flags.c = (data & 0x80) == 0x80;
         match self.p_inst {
-            0xDD => self
-                .bus
-                .write(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
-            0xFD => self
-                .bus
-                .write(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
+            0xDD => self.write_mem(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
+            0xFD => self.write_mem(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
             _ => {}
         }
         r
@@ -32,14 +37,13 @@ impl Z80 {
 
     fn rrc_r(&mut self, reg: u8, d: u8) -> u8 {
         let data = match self.p_inst {
-            0xDD => self
-                .bus
-                .read(self.reg.get_ix().wrapping_add((d as i8) as u16)),
-            0xFD => self
-                .bus
-                .read(self.reg.get_iy().wrapping_add((d as i8) as u16)),
+            0xDD => self.read_mem(self.reg.get_ix().wrapping_add((d as i8) as u16)),
+            0xFD => self.read_mem(self.reg.get_iy().wrapping_add((d as i8) as u16)),
             _ => reg,
         };
+        if let Some(addr) = self.index_addr(d) {
+            self.record_internal(addr, 1);
+        }
         let r = data.rotate_right(1);
         self.reg.flags.s = (r as i8) < 0;
         self.reg.flags.z = r == 0;
@@ -48,12 +52,8 @@ impl Z80 {
         self.reg.flags.n = false;
         self.reg.flags.c = (data & 0x01) == 0x01;
         match self.p_inst {
-            0xDD => self
-                .bus
-                .write(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
-            0xFD => self
-                .bus
-                .write(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
+            0xDD => self.write_mem(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
+            0xFD => self.write_mem(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
             _ => {}
         }
         r
@@ -61,14 +61,13 @@ impl Z80 {
 
     fn rl_r(&mut self, reg: u8, d: u8) -> u8 {
         let data = match self.p_inst {
-            0xDD => self
-                .bus
-                .read(self.reg.get_ix().wrapping_add((d as i8) as u16)),
-            0xFD => self
-                .bus
-                .read(self.reg.get_iy().wrapping_add((d as i8) as u16)),
+            0xDD => self.read_mem(self.reg.get_ix().wrapping_add((d as i8) as u16)),
+            0xFD => self.read_mem(self.reg.get_iy().wrapping_add((d as i8) as u16)),
             _ => reg,
         };
+        if let Some(addr) = self.index_addr(d) {
+            self.record_internal(addr, 1);
+        }
         let c = self.reg.flags.c as u8;
         let r = (data.rotate_left(1) & 0xFE) | c;
         self.reg.flags.s = (r as i8) < 0;
@@ -78,12 +77,8 @@ impl Z80 {
         self.reg.flags.n = false;
         self.reg.flags.c = (data & 0x80) == 0x80;
         match self.p_inst {
-            0xDD => self
-                .bus
-                .write(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
-            0xFD => self
-                .bus
-                .write(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
+            0xDD => self.write_mem(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
+            0xFD => self.write_mem(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
             _ => {}
         }
         r
@@ -91,14 +86,13 @@ impl Z80 {
 
     fn rr_r(&mut self, reg: u8, d: u8) -> u8 {
         let data = match self.p_inst {
-            0xDD => self
-                .bus
-                .read(self.reg.get_ix().wrapping_add((d as i8) as u16)),
-            0xFD => self
-                .bus
-                .read(self.reg.get_iy().wrapping_add((d as i8) as u16)),
+            0xDD => self.read_mem(self.reg.get_ix().wrapping_add((d as i8) as u16)),
+            0xFD => self.read_mem(self.reg.get_iy().wrapping_add((d as i8) as u16)),
             _ => reg,
         };
+        if let Some(addr) = self.index_addr(d) {
+            self.record_internal(addr, 1);
+        }
         let c = self.reg.flags.c as u8;
         let r = (data.rotate_right(1) & 0x7F) | c;
         self.reg.flags.s = (r as i8) < 0;
@@ -108,12 +102,8 @@ impl Z80 {
         self.reg.flags.n = false;
         self.reg.flags.c = (data & 0x01) == 0x01;
         match self.p_inst {
-            0xDD => self
-                .bus
-                .write(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
-            0xFD => self
-                .bus
-                .write(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
+            0xDD => self.write_mem(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
+            0xFD => self.write_mem(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
             _ => {}
         }
         r
@@ -121,14 +111,13 @@ impl Z80 {
 
     fn sla_r(&mut self, reg: u8, d: u8) -> u8 {
         let data = match self.p_inst {
-            0xDD => self
-                .bus
-                .read(self.reg.get_ix().wrapping_add((d as i8) as u16)),
-            0xFD => self
-                .bus
-                .read(self.reg.get_iy().wrapping_add((d as i8) as u16)),
+            0xDD => self.read_mem(self.reg.get_ix().wrapping_add((d as i8) as u16)),
+            0xFD => self.read_mem(self.reg.get_iy().wrapping_add((d as i8) as u16)),
             _ => reg,
         };
+        if let Some(addr) = self.index_addr(d) {
+            self.record_internal(addr, 1);
+        }
         let r = data << 1;
         self.reg.flags.s = (r as i8) < 0;
         self.reg.flags.z = r == 0;
@@ -137,12 +126,8 @@ impl Z80 {
         self.reg.flags.n = false;
         self.reg.flags.c = (data & 0x80) == 0x80;
         match self.p_inst {
-            0xDD => self
-                .bus
-                .write(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
-            0xFD => self
-                .bus
-                .write(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
+            0xDD => self.write_mem(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
+            0xFD => self.write_mem(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
             _ => {}
         }
         r
@@ -150,14 +135,13 @@ impl Z80 {
 
     fn sra_r(&mut self, reg: u8, d: u8) -> u8 {
         let data = match self.p_inst {
-            0xDD => self
-                .bus
-                .read(self.reg.get_ix().wrapping_add((d as i8) as u16)),
-            0xFD => self
-                .bus
-                .read(self.reg.get_iy().wrapping_add((d as i8) as u16)),
+            0xDD => self.read_mem(self.reg.get_ix().wrapping_add((d as i8) as u16)),
+            0xFD => self.read_mem(self.reg.get_iy().wrapping_add((d as i8) as u16)),
             _ => reg,
         };
+        if let Some(addr) = self.index_addr(d) {
+            self.record_internal(addr, 1);
+        }
         let r = ((data as i8) >> 1) as u8;
         self.reg.flags.s = (r as i8) < 0;
         self.reg.flags.z = r == 0;
@@ -166,12 +150,8 @@ impl Z80 {
         self.reg.flags.n = false;
         self.reg.flags.c = (data & 0x01) == 0x01;
         match self.p_inst {
-            0xDD => self
-                .bus
-                .write(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
-            0xFD => self
-                .bus
-                .write(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
+            0xDD => self.write_mem(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
+            0xFD => self.write_mem(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
             _ => {}
         }
         r
@@ -179,14 +159,13 @@ impl Z80 {
 
     fn sll_r(&mut self, reg: u8, d: u8) -> u8 {
         let data = match self.p_inst {
-            0xDD => self
-                .bus
-                .read(self.reg.get_ix().wrapping_add((d as i8) as u16)),
-            0xFD => self
-                .bus
-                .read(self.reg.get_iy().wrapping_add((d as i8) as u16)),
+            0xDD => self.read_mem(self.reg.get_ix().wrapping_add((d as i8) as u16)),
+            0xFD => self.read_mem(self.reg.get_iy().wrapping_add((d as i8) as u16)),
             _ => reg,
         };
+        if let Some(addr) = self.index_addr(d) {
+            self.record_internal(addr, 1);
+        }
         let r = (data << 1) | 0x01;
         self.reg.flags.s = (r as i8) < 0;
         self.reg.flags.z = r == 0;
@@ -195,12 +174,8 @@ impl Z80 {
         self.reg.flags.n = false;
         self.reg.flags.c = (data & 0x80) == 0x80;
         match self.p_inst {
-            0xDD => self
-                .bus
-                .write(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
-            0xFD => self
-                .bus
-                .write(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
+            0xDD => self.write_mem(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
+            0xFD => self.write_mem(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
             _ => {}
         }
         r
@@ -208,14 +183,13 @@ impl Z80 {
 
     fn srl_r(&mut self, reg: u8, d: u8) -> u8 {
         let data = match self.p_inst {
-            0xDD => self
-                .bus
-                .read(self.reg.get_ix().wrapping_add((d as i8) as u16)),
-            0xFD => self
-                .bus
-                .read(self.reg.get_iy().wrapping_add((d as i8) as u16)),
+            0xDD => self.read_mem(self.reg.get_ix().wrapping_add((d as i8) as u16)),
+            0xFD => self.read_mem(self.reg.get_iy().wrapping_add((d as i8) as u16)),
             _ => reg,
         };
+        if let Some(addr) = self.index_addr(d) {
+            self.record_internal(addr, 1);
+        }
         let r = data >> 1;
         self.reg.flags.s = false;
         self.reg.flags.z = r == 0;
@@ -224,12 +198,8 @@ impl Z80 {
         self.reg.flags.n = false;
         self.reg.flags.c = (data & 0x01) == 0x01;
         match self.p_inst {
-            0xDD => self
-                .bus
-                .write(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
-            0xFD => self
-                .bus
-                .write(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
+            0xDD => self.write_mem(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
+            0xFD => self.write_mem(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
             _ => {}
         }
         r
@@ -242,13 +212,17 @@ impl Z80 {
                 let addr = self.reg.get_ix().wrapping_add((d as i8) as u16);
                 self.reg.flags.b5 = addr & 0b00100000_00000000 == 0b00100000_00000000;
                 self.reg.flags.b3 = addr & 0b00001000_00000000 == 0b00001000_00000000;
-                self.bus.read(addr)
+                let data = self.read_mem(addr);
+                self.record_internal(addr, 1);
+                data
             }
             0xFD => {
                 let addr = self.reg.get_iy().wrapping_add((d as i8) as u16);
                 self.reg.flags.b5 = addr & 0b00100000_00000000 == 0b00100000_00000000;
                 self.reg.flags.b3 = addr & 0b00001000_00000000 == 0b00001000_00000000;
-                self.bus.read(addr)
+                let data = self.read_mem(addr);
+                self.record_internal(addr, 1);
+                data
             }
             _ => {
                 match bit {
@@ -270,23 +244,18 @@ impl Z80 {
 
     fn res_b_r(&mut self, bit: u8, reg: u8, d: u8) -> u8 {
         let data = match self.p_inst {
-            0xDD => self
-                .bus
-                .read(self.reg.get_ix().wrapping_add((d as i8) as u16)),
-            0xFD => self
-                .bus
-                .read(self.reg.get_iy().wrapping_add((d as i8) as u16)),
+            0xDD => self.read_mem(self.reg.get_ix().wrapping_add((d as i8) as u16)),
+            0xFD => self.read_mem(self.reg.get_iy().wrapping_add((d as i8) as u16)),
             _ => reg,
         };
+        if let Some(addr) = self.index_addr(d) {
+            self.record_internal(addr, 1);
+        }
         let mask = 0xFE_u8 << bit;
         let r = data & mask;
         match self.p_inst {
-            0xDD => self
-                .bus
-                .write(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
-            0xFD => self
-                .bus
-                .write(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
+            0xDD => self.write_mem(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
+            0xFD => self.write_mem(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
             _ => {}
         }
         r
@@ -294,23 +263,18 @@ impl Z80 {
 
     fn set_b_r(&mut self, bit: u8, reg: u8, d: u8) -> u8 {
         let data = match self.p_inst {
-            0xDD => self
-                .bus
-                .read(self.reg.get_ix().wrapping_add((d as i8) as u16)),
-            0xFD => self
-                .bus
-                .read(self.reg.get_iy().wrapping_add((d as i8) as u16)),
+            0xDD => self.read_mem(self.reg.get_ix().wrapping_add((d as i8) as u16)),
+            0xFD => self.read_mem(self.reg.get_iy().wrapping_add((d as i8) as u16)),
             _ => reg,
         };
+        if let Some(addr) = self.index_addr(d) {
+            self.record_internal(addr, 1);
+        }
         let mask = 0x01_u8 << bit;
         let r = data | mask;
         match self.p_inst {
-            0xDD => self
-                .bus
-                .write(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
-            0xFD => self
-                .bus
-                .write(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
+            0xDD => self.write_mem(self.reg.get_ix().wrapping_add((d as i8) as u16), r),
+            0xFD => self.write_mem(self.reg.get_iy().wrapping_add((d as i8) as u16), r),
             _ => {}
         }
         r
@@ -319,12 +283,20 @@ impl Z80 {
     pub fn cb_instructions(&mut self) -> u8 {
         let d = if self.p_inst == 0xDD || self.p_inst == 0xFD {
             self.reg.inc_pc();
-            self.bus.read(self.reg.pc)
+            self.read_mem(self.reg.pc)
         } else {
             0_u8
         };
         self.reg.inc_pc();
-        let opcode = self.bus.read(self.reg.pc);
+        // With a DD/FD prefix the opcode follows the displacement as a plain memory read
+        let opcode = if self.p_inst == 0xDD || self.p_inst == 0xFD {
+            let opcode = self.read_mem(self.reg.pc);
+            // The displacement is added while the opcode is read
+            self.record_internal(self.reg.pc, 2);
+            opcode
+        } else {
+            self.fetch_opcode()
+        };
         let mut cycles = CYCLES_CB[opcode as usize];
 
         match opcode {
@@ -336,10 +308,11 @@ impl Z80 {
             0x04 => self.reg.h = self.rlc_r(self.reg.h, d),
             0x05 => self.reg.l = self.rlc_r(self.reg.l, d),
             0x06 => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.rlc_r(data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.rlc_r(0, d);
                 }
@@ -353,10 +326,11 @@ impl Z80 {
             0x0C => self.reg.h = self.rrc_r(self.reg.h, d),
             0x0D => self.reg.l = self.rrc_r(self.reg.l, d),
             0x0E => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.rrc_r(data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.rrc_r(0, d);
                 }
@@ -370,10 +344,11 @@ impl Z80 {
             0x14 => self.reg.h = self.rl_r(self.reg.h, d),
             0x15 => self.reg.l = self.rl_r(self.reg.l, d),
             0x16 => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.rl_r(data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.rl_r(0, d);
                 }
@@ -387,10 +362,11 @@ impl Z80 {
             0x1C => self.reg.h = self.rr_r(self.reg.h, d),
             0x1D => self.reg.l = self.rr_r(self.reg.l, d),
             0x1E => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.rr_r(data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.rr_r(0, d);
                 }
@@ -404,10 +380,11 @@ impl Z80 {
             0x24 => self.reg.h = self.sla_r(self.reg.h, d),
             0x25 => self.reg.l = self.sla_r(self.reg.l, d),
             0x26 => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.sla_r(data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.sla_r(0, d);
                 }
@@ -421,10 +398,11 @@ impl Z80 {
             0x2C => self.reg.h = self.sra_r(self.reg.h, d),
             0x2D => self.reg.l = self.sra_r(self.reg.l, d),
             0x2E => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.sra_r(data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.sra_r(0, d);
                 }
@@ -438,10 +416,11 @@ impl Z80 {
             0x34 => self.reg.h = self.sll_r(self.reg.h, d),
             0x35 => self.reg.l = self.sll_r(self.reg.l, d),
             0x36 => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.sll_r(data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.sll_r(0, d);
                 }
@@ -455,10 +434,11 @@ impl Z80 {
             0x3C => self.reg.h = self.srl_r(self.reg.h, d),
             0x3D => self.reg.l = self.srl_r(self.reg.l, d),
             0x3E => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.srl_r(data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.srl_r(0, d);
                 }
@@ -472,8 +452,13 @@ impl Z80 {
             0x44 => self.bit_b_r(0, self.reg.h, d),
             0x45 => self.bit_b_r(0, self.reg.l, d),
             0x46 => {
-                let data = self.bus.read(self.reg.get_hl());
-                self.bit_b_r(0, data, d);
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
+                    self.bit_b_r(0, data, d);
+                } else {
+                    self.bit_b_r(0, 0, d);
+                }
             }
             0x47 => self.bit_b_r(0, self.reg.a, d),
             0x48 => self.bit_b_r(1, self.reg.b, d),
@@ -483,8 +468,13 @@ impl Z80 {
             0x4C => self.bit_b_r(1, self.reg.h, d),
             0x4D => self.bit_b_r(1, self.reg.l, d),
             0x4E => {
-                let data = self.bus.read(self.reg.get_hl());
-                self.bit_b_r(1, data, d);
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
+                    self.bit_b_r(1, data, d);
+                } else {
+                    self.bit_b_r(1, 0, d);
+                }
             }
             0x4F => self.bit_b_r(1, self.reg.a, d),
             0x50 => self.bit_b_r(2, self.reg.b, d),
@@ -494,8 +484,13 @@ impl Z80 {
             0x54 => self.bit_b_r(2, self.reg.h, d),
             0x55 => self.bit_b_r(2, self.reg.l, d),
             0x56 => {
-                let data = self.bus.read(self.reg.get_hl());
-                self.bit_b_r(2, data, d);
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
+                    self.bit_b_r(2, data, d);
+                } else {
+                    self.bit_b_r(2, 0, d);
+                }
             }
             0x57 => self.bit_b_r(2, self.reg.a, d),
             0x58 => self.bit_b_r(3, self.reg.b, d),
@@ -505,8 +500,13 @@ impl Z80 {
             0x5C => self.bit_b_r(3, self.reg.h, d),
             0x5D => self.bit_b_r(3, self.reg.l, d),
             0x5E => {
-                let data = self.bus.read(self.reg.get_hl());
-                self.bit_b_r(3, data, d);
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
+                    self.bit_b_r(3, data, d);
+                } else {
+                    self.bit_b_r(3, 0, d);
+                }
             }
             0x5F => self.bit_b_r(3, self.reg.a, d),
             0x60 => self.bit_b_r(4, self.reg.b, d),
@@ -516,8 +516,13 @@ impl Z80 {
             0x64 => self.bit_b_r(4, self.reg.h, d),
             0x65 => self.bit_b_r(4, self.reg.l, d),
             0x66 => {
-                let data = self.bus.read(self.reg.get_hl());
-                self.bit_b_r(4, data, d);
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
+                    self.bit_b_r(4, data, d);
+                } else {
+                    self.bit_b_r(4, 0, d);
+                }
             }
             0x67 => self.bit_b_r(4, self.reg.a, d),
             0x68 => self.bit_b_r(5, self.reg.b, d),
@@ -527,8 +532,13 @@ impl Z80 {
             0x6C => self.bit_b_r(5, self.reg.h, d),
             0x6D => self.bit_b_r(5, self.reg.l, d),
             0x6E => {
-                let data = self.bus.read(self.reg.get_hl());
-                self.bit_b_r(5, data, d);
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
+                    self.bit_b_r(5, data, d);
+                } else {
+                    self.bit_b_r(5, 0, d);
+                }
             }
             0x6F => self.bit_b_r(5, self.reg.a, d),
             0x70 => self.bit_b_r(6, self.reg.b, d),
@@ -538,8 +548,13 @@ impl Z80 {
             0x74 => self.bit_b_r(6, self.reg.h, d),
             0x75 => self.bit_b_r(6, self.reg.l, d),
             0x76 => {
-                let data = self.bus.read(self.reg.get_hl());
-                self.bit_b_r(6, data, d);
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
+                    self.bit_b_r(6, data, d);
+                } else {
+                    self.bit_b_r(6, 0, d);
+                }
             }
             0x77 => self.bit_b_r(6, self.reg.a, d),
             0x78 => self.bit_b_r(7, self.reg.b, d),
@@ -549,8 +564,13 @@ impl Z80 {
             0x7C => self.bit_b_r(7, self.reg.h, d),
             0x7D => self.bit_b_r(7, self.reg.l, d),
             0x7E => {
-                let data = self.bus.read(self.reg.get_hl());
-                self.bit_b_r(7, data, d);
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
+                    self.bit_b_r(7, data, d);
+                } else {
+                    self.bit_b_r(7, 0, d);
+                }
             }
             0x7F => self.bit_b_r(7, self.reg.a, d),
             // RES b, r
@@ -561,10 +581,11 @@ impl Z80 {
             0x84 => self.reg.h = self.res_b_r(0, self.reg.h, d),
             0x85 => self.reg.l = self.res_b_r(0, self.reg.l, d),
             0x86 => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.res_b_r(0, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.res_b_r(0, 0, d);
                 }
@@ -577,10 +598,11 @@ impl Z80 {
             0x8C => self.reg.h = self.res_b_r(1, self.reg.h, d),
             0x8D => self.reg.l = self.res_b_r(1, self.reg.l, d),
             0x8E => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.res_b_r(1, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.res_b_r(1, 0, d);
                 }
@@ -593,10 +615,11 @@ impl Z80 {
             0x94 => self.reg.h = self.res_b_r(2, self.reg.h, d),
             0x95 => self.reg.l = self.res_b_r(2, self.reg.l, d),
             0x96 => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.res_b_r(2, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.res_b_r(2, 0, d);
                 }
@@ -609,10 +632,11 @@ impl Z80 {
             0x9C => self.reg.h = self.res_b_r(3, self.reg.h, d),
             0x9D => self.reg.l = self.res_b_r(3, self.reg.l, d),
             0x9E => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.res_b_r(3, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.res_b_r(3, 0, d);
                 }
@@ -625,10 +649,11 @@ impl Z80 {
             0xA4 => self.reg.h = self.res_b_r(4, self.reg.h, d),
             0xA5 => self.reg.l = self.res_b_r(4, self.reg.l, d),
             0xA6 => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.res_b_r(4, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.res_b_r(4, 0, d);
                 }
@@ -641,10 +666,11 @@ impl Z80 {
             0xAC => self.reg.h = self.res_b_r(5, self.reg.h, d),
             0xAD => self.reg.l = self.res_b_r(5, self.reg.l, d),
             0xAE => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.res_b_r(5, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.res_b_r(5, 0, d);
                 }
@@ -657,10 +683,11 @@ impl Z80 {
             0xB4 => self.reg.h = self.res_b_r(6, self.reg.h, d),
             0xB5 => self.reg.l = self.res_b_r(6, self.reg.l, d),
             0xB6 => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.res_b_r(6, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.res_b_r(6, 0, d);
                 }
@@ -673,10 +700,11 @@ impl Z80 {
             0xBC => self.reg.h = self.res_b_r(7, self.reg.h, d),
             0xBD => self.reg.l = self.res_b_r(7, self.reg.l, d),
             0xBE => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.res_b_r(7, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.res_b_r(7, 0, d);
                 }
@@ -690,10 +718,11 @@ impl Z80 {
             0xC4 => self.reg.h = self.set_b_r(0, self.reg.h, d),
             0xC5 => self.reg.l = self.set_b_r(0, self.reg.l, d),
             0xC6 => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.set_b_r(0, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.set_b_r(0, 0, d);
                 }
@@ -706,10 +735,11 @@ impl Z80 {
             0xCC => self.reg.h = self.set_b_r(1, self.reg.h, d),
             0xCD => self.reg.l = self.set_b_r(1, self.reg.l, d),
             0xCE => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.set_b_r(1, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.set_b_r(1, 0, d);
                 }
@@ -722,10 +752,11 @@ impl Z80 {
             0xD4 => self.reg.h = self.set_b_r(2, self.reg.h, d),
             0xD5 => self.reg.l = self.set_b_r(2, self.reg.l, d),
             0xD6 => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.set_b_r(2, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.set_b_r(2, 0, d);
                 }
@@ -738,10 +769,11 @@ impl Z80 {
             0xDC => self.reg.h = self.set_b_r(3, self.reg.h, d),
             0xDD => self.reg.l = self.set_b_r(3, self.reg.l, d),
             0xDE => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.set_b_r(3, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.set_b_r(3, 0, d);
                 }
@@ -754,10 +786,11 @@ impl Z80 {
             0xE4 => self.reg.h = self.set_b_r(4, self.reg.h, d),
             0xE5 => self.reg.l = self.set_b_r(4, self.reg.l, d),
             0xE6 => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.set_b_r(4, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.set_b_r(4, 0, d);
                 }
@@ -770,10 +803,11 @@ impl Z80 {
             0xEC => self.reg.h = self.set_b_r(5, self.reg.h, d),
             0xED => self.reg.l = self.set_b_r(5, self.reg.l, d),
             0xEE => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.set_b_r(5, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.set_b_r(5, 0, d);
                 }
@@ -786,10 +820,11 @@ impl Z80 {
             0xF4 => self.reg.h = self.set_b_r(6, self.reg.h, d),
             0xF5 => self.reg.l = self.set_b_r(6, self.reg.l, d),
             0xF6 => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.set_b_r(6, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.set_b_r(6, 0, d);
                 }
@@ -802,10 +837,11 @@ impl Z80 {
             0xFC => self.reg.h = self.set_b_r(7, self.reg.h, d),
             0xFD => self.reg.l = self.set_b_r(7, self.reg.l, d),
             0xFE => {
-                if self.p_inst != 0xDD || self.p_inst != 0xFD {
-                    let mut data = self.bus.read(self.reg.get_hl());
+                if self.p_inst != 0xDD && self.p_inst != 0xFD {
+                    let mut data = self.read_mem(self.reg.get_hl());
+                    self.record_internal(self.reg.get_hl(), 1);
                     data = self.set_b_r(7, data, d);
-                    self.bus.write(self.reg.get_hl(), data);
+                    self.write_mem(self.reg.get_hl(), data);
                 } else {
                     _ = self.set_b_r(7, 0, d);
                 }
@@ -36,59 +36,63 @@ pub const CYCLES_CB: [u8; 256] = [
     8, 8, 8, 8, 8, 8, 15, 8, 8, 8, 8, 8, 8, 8, 15, 8,
 ];
 
+// T-states after the M1 cycle of the ED prefix
 pub const CYCLES_ED: [u8; 256] = [
-     0,  0,  0,  0, 0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
-     0,  0,  0,  0, 0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
-     0,  0,  0,  0, 0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
-     0,  0,  0,  0, 0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
-     0,  0, 11, 16, 4, 10,  4,  5,  8,  8, 11, 16,  0, 10,  0,  5,
-     0,  0, 11, 16, 0,  0,  4,  5,  8,  8, 11, 16,  0,  0,  8,  5,
-     0,  0, 11, 16, 0,  0,  0, 14,  8,  8, 11, 16,  0,  0,  0, 14,
-     8,  8, 11, 16, 0,  0,  0,  0,  8,  8, 11, 16,  0,  0,  0,  0,
-     0,  0,  0,  0, 0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
-     0,  0,  0,  0, 0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
-    12, 12, 12, 12, 0,  0,  0,  0, 12, 12, 12, 12,  0,  0,  0,  0,
-    12, 12, 12, 12, 0,  0,  0,  0, 12, 12, 12, 12,  0,  0,  0,  0,
-     0,  0,  0,  0, 0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
-     0,  0,  0,  0, 0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
-     0,  0,  0,  0, 0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
-     0,  0,  0,  0, 0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
-];
-
-pub const CYCLES_DD_FD: [u8; 256] = [
      4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,
      4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,
      4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,
-     4,  4,  4,  4, 12, 12,  9,  4,  4,  4,  4,  4,  4,  4,  4,  4,
-     4,  4,  4,  4,  4,  4, 12,  4,  4,  4,  4,  4,  4,  4, 12,  4,
-     4,  4,  4,  4,  4,  4, 12,  4,  4,  4,  4,  4,  4,  4, 12,  4,
-     4,  4,  4,  4,  4,  4, 12,  4,  4,  4,  4,  4,  4,  4, 12,  4,
-    12, 12, 12, 12, 12, 12,  4, 12,  4,  4,  4,  4,  4,  4, 12,  4,
-     4,  4,  4,  4,  4,  4, 12,  4,  4,  4,  4,  4,  4,  4, 12,  4,
-     4,  4,  4,  4,  4,  4, 12,  4,  4,  4,  4,  4,  4,  4, 12,  4,
-     4,  4,  4,  4,  4,  4, 12,  4,  4,  4,  4,  4,  4,  4, 12,  4,
-     4,  4,  4,  4,  4,  4, 12,  4,  4,  4,  4,  4,  4,  4, 12,  4,
-     4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  0,  4,  4,  4,  4,
      4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,
+     8,  8, 11, 16,  4, 10,  4,  5,  8,  8, 11, 16,  4, 10,  4,  5,
+     8,  8, 11, 16,  4, 10,  4,  5,  8,  8, 11, 16,  4, 10,  4,  5,
+     8,  8, 11, 16,  4, 10,  4, 14,  8,  8, 11, 16,  4, 10,  4, 14,
+     8,  8, 11, 16,  4, 10,  4,  4,  8,  8, 11, 16,  4, 10,  4,  4,
+     4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,
+     4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,
+    12, 12, 12, 12,  4,  4,  4,  4, 12, 12, 12, 12,  4,  4,  4,  4,
+    12, 12, 12, 12,  4,  4,  4,  4, 12, 12, 12, 12,  4,  4,  4,  4,
      4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,
      4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,
+     4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,
+     4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,  4,
+];
+
+// T-states added to the unprefixed instruction, the DD/FD prefix being an M1 cycle of
+// its own
+pub const CYCLES_DD_FD: [u8; 256] = [
+     0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  8,  8,  5,  0,  0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  8,  0,  0,  0,  0,  0,  0,  0,  8,  0,
+     0,  0,  0,  0,  0,  0,  8,  0,  0,  0,  0,  0,  0,  0,  8,  0,
+     0,  0,  0,  0,  0,  0,  8,  0,  0,  0,  0,  0,  0,  0,  8,  0,
+     8,  8,  8,  8,  8,  8,  0,  8,  0,  0,  0,  0,  0,  0,  8,  0,
+     0,  0,  0,  0,  0,  0,  8,  0,  0,  0,  0,  0,  0,  0,  8,  0,
+     0,  0,  0,  0,  0,  0,  8,  0,  0,  0,  0,  0,  0,  0,  8,  0,
+     0,  0,  0,  0,  0,  0,  8,  0,  0,  0,  0,  0,  0,  0,  8,  0,
+     0,  0,  0,  0,  0,  0,  8,  0,  0,  0,  0,  0,  0,  0,  8,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
 ];
 
+// T-states added to the CB instruction after a DD/FD prefix
 pub const CYCLES_DD_FD_CB: [u8; 256] = [
-    15, 15, 15, 15, 15, 15,  8, 15, 15, 15, 15, 15, 15, 15,  8, 15,
-    15, 15, 15, 15, 15, 15,  8, 15, 15, 15, 15, 15, 15, 15,  8, 15,
-    15, 15, 15, 15, 15, 15,  8, 15, 15, 15, 15, 15, 15, 15,  8, 15,
-    15, 15, 15, 15, 15, 15,  8, 15, 15, 15, 15, 15, 15, 15,  8, 15,
-    12, 12, 12, 12, 12, 12,  8, 12, 12, 12, 12, 12, 12, 12,  8, 12,
-    12, 12, 12, 12, 12, 12,  8, 12, 12, 12, 12, 12, 12, 12,  8, 12,
-    12, 12, 12, 12, 12, 12,  8, 12, 12, 12, 12, 12, 12, 12,  8, 12,
-    12, 12, 12, 12, 12, 12,  8, 12, 12, 12, 12, 12, 12, 12,  8, 12,
-    15, 15, 15, 15, 15, 15,  8, 15, 15, 15, 15, 15, 15, 15,  8, 15,
-    15, 15, 15, 15, 15, 15,  8, 15, 15, 15, 15, 15, 15, 15,  8, 15,
-    15, 15, 15, 15, 15, 15,  8, 15, 15, 15, 15, 15, 15, 15,  8, 15,
-    15, 15, 15, 15, 15, 15,  8, 15, 15, 15, 15, 15, 15, 15,  8, 15,
-    15, 15, 15, 15, 15, 15,  8, 15, 15, 15, 15, 15, 15, 15,  8, 15,
-    15, 15, 15, 15, 15, 15,  8, 15, 15, 15, 15, 15, 15, 15,  8, 15,
-    15, 15, 15, 15, 15, 15,  8, 15, 15, 15, 15, 15, 15, 15,  8, 15,
-    15, 15, 15, 15, 15, 15,  8, 15, 15, 15, 15, 15, 15, 15,  8, 15,
+    11, 11, 11, 11, 11, 11,  4, 11, 11, 11, 11, 11, 11, 11,  4, 11,
+    11, 11, 11, 11, 11, 11,  4, 11, 11, 11, 11, 11, 11, 11,  4, 11,
+    11, 11, 11, 11, 11, 11,  4, 11, 11, 11, 11, 11, 11, 11,  4, 11,
+    11, 11, 11, 11, 11, 11,  4, 11, 11, 11, 11, 11, 11, 11,  4, 11,
+     8,  8,  8,  8,  8,  8,  4,  8,  8,  8,  8,  8,  8,  8,  4,  8,
+     8,  8,  8,  8,  8,  8,  4,  8,  8,  8,  8,  8,  8,  8,  4,  8,
+     8,  8,  8,  8,  8,  8,  4,  8,  8,  8,  8,  8,  8,  8,  4,  8,
+     8,  8,  8,  8,  8,  8,  4,  8,  8,  8,  8,  8,  8,  8,  4,  8,
+    11, 11, 11, 11, 11, 11,  4, 11, 11, 11, 11, 11, 11, 11,  4, 11,
+    11, 11, 11, 11, 11, 11,  4, 11, 11, 11, 11, 11, 11, 11,  4, 11,
+    11, 11, 11, 11, 11, 11,  4, 11, 11, 11, 11, 11, 11, 11,  4, 11,
+    11, 11, 11, 11, 11, 11,  4, 11, 11, 11, 11, 11, 11, 11,  4, 11,
+    11, 11, 11, 11, 11, 11,  4, 11, 11, 11, 11, 11, 11, 11,  4, 11,
+    11, 11, 11, 11, 11, 11,  4, 11, 11, 11, 11, 11, 11, 11,  4, 11,
+    11, 11, 11, 11, 11, 11,  4, 11, 11, 11, 11, 11, 11, 11,  4, 11,
+    11, 11, 11, 11, 11, 11,  4, 11, 11, 11, 11, 11, 11, 11,  4, 11,
 ];
\ No newline at end of file
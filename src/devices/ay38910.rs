@@ -0,0 +1,234 @@
+// General Instrument AY-3-8910/8912 programmable sound generator
+
+use super::resampler::Resampler;
+
+// Writable bits of each register
+const REG_MASK: [u8; 16] = [
+    0xFF, 0x0F, 0xFF, 0x0F, 0xFF, 0x0F, 0x1F, 0xFF, 0x1F, 0x1F, 0x1F, 0xFF, 0xFF, 0x0F, 0xFF,
+    0xFF,
+];
+
+// Output level of the 16 volume steps
+const VOLUME: [f32; 16] = [
+    0.0, 0.0106, 0.0150, 0.0222, 0.0320, 0.0466, 0.0665, 0.1039, 0.1237, 0.1986, 0.2803,
+    0.3548, 0.4702, 0.6030, 0.7530, 1.0,
+];
+
+pub struct Ay38910 {
+    pub regs: [u8; 16],
+    pub selected: usize,
+    // Level of the lines of IO port A when used as an input
+    pub port_a_input: u8,
+    // Input clock in Hz, the generators run at a eighth of it
+    pub clock: u32,
+    divider: u32,
+    tone_count: [u16; 3],
+    tone: [bool; 3],
+    noise_count: u16,
+    // 17-bit LFSR
+    noise_shift: u32,
+    env_count: u32,
+    env_step: u8,
+    env_attack: bool,
+    env_holding: bool,
+    resampler: Option<Resampler>,
+}
+
+impl Default for Ay38910 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ay38910 {
+    // 1 MHz as on the CPC
+    pub fn new() -> Self {
+        Self::with_clock(1_000_000)
+    }
+
+    pub fn with_clock(clock: u32) -> Self {
+        Self {
+            regs: [0_u8; 16],
+            selected: 0,
+            port_a_input: 0xFF,
+            clock,
+            divider: 0,
+            tone_count: [0; 3],
+            tone: [false; 3],
+            noise_count: 0,
+            noise_shift: 1,
+            env_count: 0,
+            env_step: 0,
+            env_attack: false,
+            env_holding: true,
+            resampler: None,
+        }
+    }
+
+    // Registers are cleared, the host sample rate is kept
+    pub fn reset(&mut self) {
+        let resampler = self.resampler.take();
+        *self = Self::with_clock(self.clock);
+        self.resampler = resampler;
+    }
+
+    // Bus control through BDIR and BC1 (BC2 tied high): returns the data driven by the
+    // PSG on a read, None otherwise
+    pub fn bus_control(&mut self, bdir: bool, bc1: bool, data: u8) -> Option<u8> {
+        match (bdir, bc1) {
+            (false, true) => Some(self.read()),
+            (true, false) => {
+                self.write(data);
+                None
+            }
+            (true, true) => {
+                self.select(data);
+                None
+            }
+            (false, false) => None,
+        }
+    }
+
+    pub fn select(&mut self, reg: u8) {
+        if reg < 16 {
+            self.selected = reg as usize;
+        }
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.regs[self.selected] = data & REG_MASK[self.selected];
+        // Writing the shape restarts the envelope
+        if self.selected == 13 {
+            self.env_count = 0;
+            self.env_step = 0;
+            self.env_attack = data & 0x04 == 0x04;
+            self.env_holding = false;
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        match self.selected {
+            // Port A as an input when bit 6 of the mixer is clear
+            14 if self.regs[7] & 0x40 == 0 => self.port_a_input,
+            _ => self.regs[self.selected],
+        }
+    }
+
+    // Samples are generated for the host from now on
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.resampler = Some(Resampler::new(self.clock as f64 / 8.0, rate as f64));
+    }
+
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.resampler.as_ref().map(|r| r.output_rate as u32)
+    }
+
+    // Moves the samples generated so far to the host buffer, returns how many
+    pub fn read_samples(&mut self, buffer: &mut [f32]) -> usize {
+        self.resampler.as_mut().map_or(0, |r| r.read(buffer))
+    }
+
+    pub fn samples_ready(&self) -> usize {
+        self.resampler.as_ref().map_or(0, |r| r.output.len())
+    }
+
+    // Runs the PSG alone until the host buffer is full
+    pub fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        if self.resampler.is_none() {
+            return;
+        }
+        while self.samples_ready() < buffer.len() {
+            self.run(8);
+        }
+        self.read_samples(buffer);
+    }
+
+    fn period(&self, low: usize) -> u16 {
+        let period = ((self.regs[low + 1] as u16) << 8) | self.regs[low] as u16;
+        period.max(1)
+    }
+
+    pub fn env_level(&self) -> u8 {
+        match self.env_attack {
+            true => self.env_step,
+            false => 15 - self.env_step,
+        }
+    }
+
+    fn step_envelope(&mut self) {
+        if self.env_holding {
+            return;
+        }
+        self.env_step += 1;
+        if self.env_step > 15 {
+            let shape = self.regs[13];
+            self.env_step = 15;
+            if shape & 0x08 == 0 {
+                // Single cycle then off
+                self.env_holding = true;
+                self.env_attack = false;
+            } else if shape & 0x01 == 0x01 {
+                self.env_holding = true;
+                if shape & 0x02 == 0x02 {
+                    self.env_attack = !self.env_attack;
+                }
+            } else {
+                if shape & 0x02 == 0x02 {
+                    self.env_attack = !self.env_attack;
+                }
+                self.env_step = 0;
+            }
+        }
+    }
+
+    // One period of the generators (eight input clocks), returns the mixed output
+    fn tick(&mut self) -> f32 {
+        for ch in 0..3 {
+            self.tone_count[ch] += 1;
+            if self.tone_count[ch] >= self.period(ch * 2) {
+                self.tone_count[ch] = 0;
+                self.tone[ch] = !self.tone[ch];
+            }
+        }
+        // Noise and envelope change every 16 input clocks per unit of period
+        self.noise_count += 1;
+        if self.noise_count >= 2 * (self.regs[6] as u16).max(1) {
+            self.noise_count = 0;
+            let bit = (self.noise_shift ^ (self.noise_shift >> 3)) & 1;
+            self.noise_shift = (self.noise_shift >> 1) | (bit << 16);
+        }
+        self.env_count += 1;
+        if self.env_count >= 2 * self.period(11) as u32 {
+            self.env_count = 0;
+            self.step_envelope();
+        }
+
+        let noise = self.noise_shift & 1 == 1;
+        let mixer = self.regs[7];
+        let mut output = 0.0;
+        for ch in 0..3 {
+            let tone_off = mixer & (1 << ch) != 0;
+            let noise_off = mixer & (8 << ch) != 0;
+            if (self.tone[ch] || tone_off) && (noise || noise_off) {
+                let amplitude = self.regs[8 + ch];
+                output += match amplitude & 0x10 {
+                    0 => VOLUME[(amplitude & 0x0F) as usize],
+                    _ => VOLUME[self.env_level() as usize],
+                };
+            }
+        }
+        output / 3.0
+    }
+
+    // Runs for `clocks` cycles of the input clock
+    pub fn run(&mut self, clocks: u32) {
+        self.divider += clocks;
+        while self.divider >= 8 {
+            self.divider -= 8;
+            let output = self.tick();
+            if let Some(resampler) = self.resampler.as_mut() {
+                resampler.push(output);
+            }
+        }
+    }
+}
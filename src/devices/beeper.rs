@@ -0,0 +1,64 @@
+// One bit sound output, e.g. the speaker of the ZX Spectrum driven by the ULA
+
+use super::resampler::Resampler;
+
+// The level is averaged over this many input clocks before resampling
+const DECIMATION: u32 = 16;
+
+pub struct Beeper {
+    // Input clock in Hz
+    pub clock: u32,
+    // Level driven on the speaker, 0.0 to 1.0
+    pub level: f32,
+    divider: u32,
+    sum: f32,
+    resampler: Option<Resampler>,
+}
+
+impl Beeper {
+    pub fn new(clock: u32) -> Self {
+        Self {
+            clock,
+            level: 0.0,
+            divider: 0,
+            sum: 0.0,
+            resampler: None,
+        }
+    }
+
+    // Samples are generated for the host from now on
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.resampler = Some(Resampler::new(
+            self.clock as f64 / DECIMATION as f64,
+            rate as f64,
+        ));
+    }
+
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.resampler.as_ref().map(|r| r.output_rate as u32)
+    }
+
+    // Moves the samples generated so far to the host buffer, returns how many
+    pub fn read_samples(&mut self, buffer: &mut [f32]) -> usize {
+        self.resampler.as_mut().map_or(0, |r| r.read(buffer))
+    }
+
+    pub fn samples_ready(&self) -> usize {
+        self.resampler.as_ref().map_or(0, |r| r.output.len())
+    }
+
+    // Runs for `clocks` cycles of the input clock at the current level
+    pub fn run(&mut self, clocks: u32) {
+        if let Some(resampler) = self.resampler.as_mut() {
+            for _ in 0..clocks {
+                self.sum += self.level;
+                self.divider += 1;
+                if self.divider == DECIMATION {
+                    resampler.push(self.sum / DECIMATION as f32);
+                    self.divider = 0;
+                    self.sum = 0.0;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+// Motorola 6845 CRT controller and the variants fitted to the CPCs
+
+// Writable bits of each register
+const REG_MASK: [u8; 18] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0x7F, 0x1F, 0x7F, 0x7F, 0xFF, 0x1F, 0x7F, 0x1F, 0x3F, 0xFF, 0x3F,
+    0xFF, 0x3F, 0xFF,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CrtcType {
+    // Hitachi HD6845S / UMC UM6845
+    Type0,
+    // UMC UM6845R
+    Type1,
+    // Motorola MC6845
+    Type2,
+    // Amstrad AMS40489 (CPC+ ASIC)
+    Type3,
+    // Amstrad AMS40226 (pre-ASIC)
+    Type4,
+}
+
+pub struct Crtc6845 {
+    pub crtc_type: CrtcType,
+    pub regs: [u8; 18],
+    pub selected: usize,
+    // Horizontal character counter
+    pub hcc: u8,
+    // Vertical raster counter, line inside a character row
+    pub vlc: u8,
+    // Vertical character row counter
+    pub vcc: u8,
+    // Outputs
+    pub hsync: bool,
+    pub vsync: bool,
+    pub disp_en: bool,
+    pub cursor: bool,
+    // Memory address and row address of the current character
+    pub ma: u16,
+    pub ra: u8,
+    h_display: bool,
+    v_display: bool,
+    // Address of the first character of the current and of the next row
+    ma_row: u16,
+    ma_next: u16,
+    hsync_count: u8,
+    vsync_count: u8,
+    in_adjust: bool,
+}
+
+impl Default for Crtc6845 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crtc6845 {
+    pub fn new() -> Self {
+        Self::with_type(CrtcType::Type0)
+    }
+
+    pub fn with_type(crtc_type: CrtcType) -> Self {
+        // Registers as programmed by the CPC firmware for a 50 Hz screen
+        let mut regs = [0_u8; 18];
+        regs[..10].copy_from_slice(&[63, 40, 46, 0x8E, 38, 0, 25, 30, 0, 7]);
+        regs[12] = 0x30;
+        let mut crtc = Self {
+            crtc_type,
+            regs,
+            selected: 0,
+            hcc: 0,
+            vlc: 0,
+            vcc: 0,
+            hsync: false,
+            vsync: false,
+            disp_en: false,
+            cursor: false,
+            ma: 0,
+            ra: 0,
+            h_display: true,
+            v_display: true,
+            ma_row: 0,
+            ma_next: 0,
+            hsync_count: 0,
+            vsync_count: 0,
+            in_adjust: false,
+        };
+        crtc.new_frame();
+        crtc.update_outputs();
+        crtc
+    }
+
+    pub fn select(&mut self, reg: u8) {
+        self.selected = (reg & 0x1F) as usize;
+    }
+
+    pub fn write(&mut self, data: u8) {
+        if self.selected < 16 {
+            self.regs[self.selected] = data & REG_MASK[self.selected];
+        }
+    }
+
+    // Port &BFxx
+    pub fn read(&self) -> u8 {
+        match (self.crtc_type, self.selected) {
+            (CrtcType::Type0 | CrtcType::Type3 | CrtcType::Type4, 12..=17) => {
+                self.regs[self.selected]
+            }
+            (CrtcType::Type1 | CrtcType::Type2, 14..=17) => self.regs[self.selected],
+            (CrtcType::Type1, 31) => 0xFF,
+            _ => 0x00,
+        }
+    }
+
+    // Port &BExx: only the type 1 has a status register, the Amstrad ones read the
+    // registers as on &BFxx
+    pub fn read_status(&self) -> u8 {
+        match self.crtc_type {
+            // Bit 5: vertical blanking
+            CrtcType::Type1 => match self.v_display {
+                true => 0x00,
+                false => 0x20,
+            },
+            CrtcType::Type3 | CrtcType::Type4 => self.read(),
+            _ => 0xFF,
+        }
+    }
+
+    fn start_address(&self) -> u16 {
+        ((self.regs[12] as u16) << 8) | self.regs[13] as u16
+    }
+
+    fn hsync_width(&self) -> u8 {
+        match (self.regs[3] & 0x0F, self.crtc_type) {
+            (0, CrtcType::Type0 | CrtcType::Type1) => 0,
+            (0, _) => 16,
+            (w, _) => w,
+        }
+    }
+
+    fn vsync_width(&self) -> u8 {
+        match (self.regs[3] >> 4, self.crtc_type) {
+            (_, CrtcType::Type1 | CrtcType::Type2) => 16,
+            (0, _) => 16,
+            (w, _) => w,
+        }
+    }
+
+    // Display skew of R8, types 1 and 2 ignore it. A skew of 3 disables the display.
+    fn display_skew(&self) -> u8 {
+        match self.crtc_type {
+            CrtcType::Type1 | CrtcType::Type2 => 0,
+            _ => (self.regs[8] >> 4) & 0x03,
+        }
+    }
+
+    // One character clock
+    pub fn step(&mut self) {
+        // The address of the next row is latched at the end of the display of the last
+        // raster line of a row
+        if self.hcc == self.regs[1] && self.vlc == self.regs[9] {
+            self.ma_next = self.ma_row.wrapping_add(self.regs[1] as u16);
+        }
+
+        if self.hcc == self.regs[0] {
+            self.hcc = 0;
+            self.h_display = true;
+            self.next_line();
+        } else {
+            self.hcc = self.hcc.wrapping_add(1);
+        }
+        if self.hcc == self.regs[1] {
+            self.h_display = false;
+        }
+
+        if self.hsync {
+            self.hsync_count += 1;
+            if self.hsync_count >= self.hsync_width() {
+                self.hsync = false;
+            }
+        }
+        if self.hcc == self.regs[2] && !self.hsync && self.hsync_width() > 0 {
+            self.hsync = true;
+            self.hsync_count = 0;
+        }
+
+        self.update_outputs();
+    }
+
+    fn update_outputs(&mut self) {
+        self.ma = self.ma_row.wrapping_add(self.hcc as u16) & 0x3FFF;
+        self.ra = self.vlc;
+        self.disp_en = self.h_display && self.v_display && self.display_skew() != 3;
+        let cursor = ((self.regs[14] as u16) << 8) | self.regs[15] as u16;
+        self.cursor = self.disp_en
+            && self.ma == cursor
+            && self.regs[10] & 0x60 != 0x20
+            && (self.regs[10] & 0x1F..=self.regs[11]).contains(&self.vlc);
+    }
+
+    fn next_line(&mut self) {
+        if self.vsync {
+            self.vsync_count += 1;
+            if self.vsync_count >= self.vsync_width() {
+                self.vsync = false;
+            }
+        }
+
+        if self.in_adjust {
+            // Extra raster lines after the last character row
+            self.vlc = self.vlc.wrapping_add(1);
+            if self.vlc >= self.regs[5] {
+                self.new_frame();
+            }
+        } else if self.vlc == self.regs[9] {
+            self.vlc = 0;
+            if self.vcc == self.regs[4] {
+                if self.regs[5] > 0 {
+                    self.in_adjust = true;
+                } else {
+                    self.new_frame();
+                }
+            } else {
+                self.vcc = self.vcc.wrapping_add(1) & 0x7F;
+                self.ma_row = self.ma_next;
+            }
+        } else {
+            self.vlc = self.vlc.wrapping_add(1) & 0x1F;
+        }
+
+        // The type 1 reloads the start address on every line of the first row
+        if self.crtc_type == CrtcType::Type1 && self.vcc == 0 && !self.in_adjust {
+            self.ma_row = self.start_address();
+            self.ma_next = self.ma_row;
+        }
+
+        if self.vcc == self.regs[6] {
+            self.v_display = false;
+        }
+        if self.vlc == 0 && !self.in_adjust && self.vcc == self.regs[7] && !self.vsync {
+            self.vsync = true;
+            self.vsync_count = 0;
+        }
+    }
+
+    // Restores the counters, as from a snapshot. The row addresses follow from them.
+    pub fn set_counters(&mut self, hcc: u8, vlc: u8, vcc: u8, hsync: bool, vsync: bool) {
+        self.hcc = hcc;
+        self.vlc = vlc;
+        self.vcc = vcc;
+        self.hsync = hsync;
+        self.vsync = vsync;
+        self.hsync_count = 0;
+        self.vsync_count = 0;
+        self.in_adjust = vcc > self.regs[4];
+        self.h_display = hcc < self.regs[1];
+        self.v_display = vcc < self.regs[6];
+        self.ma_row = self
+            .start_address()
+            .wrapping_add(vcc as u16 * self.regs[1] as u16);
+        self.ma_next = self.ma_row;
+        self.update_outputs();
+    }
+
+    fn new_frame(&mut self) {
+        self.in_adjust = false;
+        self.vlc = 0;
+        self.vcc = 0;
+        self.v_display = true;
+        self.ma_row = self.start_address();
+        self.ma_next = self.ma_row;
+    }
+}
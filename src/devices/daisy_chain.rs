@@ -0,0 +1,103 @@
+// IM 2 daisy chain of Zilog peripherals: the IEO output of each device drives the IEI
+// input of the next one, so that a device only interrupts while no device before it is
+// requesting or under service. On acknowledge the device requesting puts its vector on
+// the bus. RETI ends the service of the first device under service.
+//
+// The bus keeps the devices and passes them in priority order to the functions below from
+// its n_int(), int_ack() and reti().
+
+pub trait DaisyDevice {
+    fn set_iei(&mut self, iei: bool);
+    fn ieo(&self) -> bool;
+    fn n_int(&self) -> bool;
+    fn int_ack(&mut self) -> Option<u8>;
+    // An interrupt of the device is under service
+    fn under_service(&self) -> bool;
+    fn reti(&mut self);
+}
+
+// What each device of the chain keeps: its IEI input, and the priority logic of its
+// interrupt sources. The sources are given by decreasing priority as (requesting, under
+// service) pairs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DaisyLink {
+    pub iei: bool,
+}
+
+impl Default for DaisyLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DaisyLink {
+    pub fn new() -> Self {
+        Self { iei: true }
+    }
+
+    // Source with the highest priority requesting or under service, and whether it is
+    // under service
+    pub fn first_active(sources: impl IntoIterator<Item = (bool, bool)>) -> Option<(usize, bool)> {
+        sources
+            .into_iter()
+            .enumerate()
+            .find_map(|(index, (request, service))| {
+                (request || service).then_some((index, service))
+            })
+    }
+
+    // Source requesting with no source of higher priority under service
+    pub fn pending(sources: impl IntoIterator<Item = (bool, bool)>) -> Option<usize> {
+        match Self::first_active(sources) {
+            Some((index, false)) => Some(index),
+            _ => None,
+        }
+    }
+
+    // /INT is pulled low by a pending source while IEI is high
+    pub fn n_int(&self, sources: impl IntoIterator<Item = (bool, bool)>) -> bool {
+        !self.iei || Self::pending(sources).is_none()
+    }
+
+    // Source whose vector is given on interrupt acknowledge
+    pub fn int_ack(&self, sources: impl IntoIterator<Item = (bool, bool)>) -> Option<usize> {
+        match self.iei {
+            true => Self::pending(sources),
+            false => None,
+        }
+    }
+
+    // IEO to the next device: IEI while no source is requesting or under service
+    pub fn ieo(&self, sources: impl IntoIterator<Item = (bool, bool)>) -> bool {
+        self.iei && Self::first_active(sources).is_none()
+    }
+}
+
+// Passes IEO of each device to IEI of the next, the first one is enabled
+pub fn propagate(devices: &mut [&mut dyn DaisyDevice]) {
+    let mut iei = true;
+    for device in devices.iter_mut() {
+        device.set_iei(iei);
+        iei = device.ieo();
+    }
+}
+
+// /INT is the wired-OR of the /INT outputs
+pub fn n_int(devices: &mut [&mut dyn DaisyDevice]) -> bool {
+    propagate(devices);
+    devices.iter().all(|device| device.n_int())
+}
+
+// Vector of the only device whose IEI is high that requests an interrupt
+pub fn int_ack(devices: &mut [&mut dyn DaisyDevice]) -> Option<u8> {
+    propagate(devices);
+    devices.iter_mut().find_map(|device| device.int_ack())
+}
+
+// A device with a request not yet acknowledged lets RETI through to the devices after it
+pub fn reti(devices: &mut [&mut dyn DaisyDevice]) {
+    if let Some(device) = devices.iter_mut().find(|device| device.under_service()) {
+        device.reti();
+    }
+    propagate(devices);
+}
@@ -0,0 +1,249 @@
+// CPC disk images, standard (MV - CPC) and extended (EXTENDED CPC DSK) formats.
+// Images are always saved in the extended format.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const STANDARD_HEADER: &[u8] = b"MV - CPC";
+const EXTENDED_HEADER: &[u8] = b"EXTENDED CPC DSK File\r\nDisk-Info\r\n";
+const TRACK_HEADER: &[u8] = b"Track-Info\r\n";
+const CREATOR: &[u8] = b"rust_z80_emu  ";
+// The disk header has room for the sizes of 204 tracks, a track header for 29 sectors
+// and the size of a track is counted in blocks of 256 bytes
+pub const MAX_TRACKS: usize = 0xCC;
+pub const MAX_SECTORS: usize = 29;
+pub const MAX_TRACK_SIZE: usize = 0xFF00;
+
+pub struct Sector {
+    // ID field
+    pub c: u8,
+    pub h: u8,
+    pub r: u8,
+    pub n: u8,
+    // FDC status recorded when the image was made
+    pub st1: u8,
+    pub st2: u8,
+    // A weak sector has several copies of its data, which differ in the bytes that
+    // do not read the same each time
+    pub copies: Vec<Vec<u8>>,
+    next_copy: usize,
+}
+
+impl Sector {
+    pub fn new(c: u8, h: u8, r: u8, n: u8, data: Vec<u8>) -> Self {
+        Self {
+            c,
+            h,
+            r,
+            n,
+            st1: 0,
+            st2: 0,
+            copies: vec![data],
+            next_copy: 0,
+        }
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.copies.len() > 1
+    }
+
+    // Data of the next read, successive reads of a weak sector go through its copies
+    pub fn read(&mut self) -> &[u8] {
+        let copy = self.next_copy % self.copies.len();
+        self.next_copy = copy + 1;
+        &self.copies[copy]
+    }
+
+    // Writing leaves a single copy
+    pub fn write(&mut self, data: &[u8]) {
+        self.copies = vec![data.to_vec()];
+        self.next_copy = 0;
+    }
+}
+
+pub struct Track {
+    pub track: u8,
+    pub side: u8,
+    pub gap3: u8,
+    pub filler: u8,
+    pub sectors: Vec<Sector>,
+}
+
+pub struct Disk {
+    pub tracks: usize,
+    pub sides: usize,
+    // Indexed by track * sides + side, None for an unformatted track
+    pub track_data: Vec<Option<Track>>,
+    pub write_protected: bool,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Disk {
+    // Unformatted disk
+    pub fn new(tracks: usize, sides: usize) -> Self {
+        Self {
+            tracks,
+            sides,
+            track_data: (0..tracks * sides).map(|_| None).collect(),
+            write_protected: false,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes()?)
+    }
+
+    pub fn from_bytes(image: &[u8]) -> io::Result<Self> {
+        if image.len() < 0x100 {
+            return Err(invalid("DSK image too short"));
+        }
+        let extended = image.starts_with(EXTENDED_HEADER);
+        if !extended && !image.starts_with(STANDARD_HEADER) {
+            return Err(invalid("not a DSK image"));
+        }
+        let tracks = image[0x30] as usize;
+        let sides = image[0x31] as usize;
+        if sides == 0 || sides > 2 {
+            return Err(invalid("DSK image with an invalid number of sides"));
+        }
+        if extended && tracks * sides > MAX_TRACKS {
+            return Err(invalid("DSK image with too many tracks"));
+        }
+        let mut disk = Self::new(tracks, sides);
+        let mut offset = 0x100;
+        for index in 0..tracks * sides {
+            let size = match extended {
+                true => image[0x34 + index] as usize * 0x100,
+                false => u16::from_le_bytes([image[0x32], image[0x33]]) as usize,
+            };
+            if size == 0 {
+                continue;
+            }
+            let block = image
+                .get(offset..offset + size)
+                .ok_or_else(|| invalid("DSK image truncated"))?;
+            disk.track_data[index] = Some(Self::parse_track(block, extended)?);
+            offset += size;
+        }
+        Ok(disk)
+    }
+
+    fn parse_track(block: &[u8], extended: bool) -> io::Result<Track> {
+        if block.len() < 0x100 || !block.starts_with(TRACK_HEADER) {
+            return Err(invalid("DSK track without Track-Info"));
+        }
+        let mut sectors = Vec::new();
+        let mut offset = 0x100;
+        for info in block[0x18..0x100].chunks(8).take(block[0x15] as usize) {
+            let size = 128_usize << (info[3].min(8));
+            let length = match extended {
+                true => u16::from_le_bytes([info[6], info[7]]) as usize,
+                false => 128_usize << block[0x14].min(6),
+            };
+            let data = block
+                .get(offset..offset + length)
+                .ok_or_else(|| invalid("DSK sector data truncated"))?;
+            // Several copies of a weak sector are stored one after the other
+            let copies = match length > size && length % size == 0 {
+                true => data.chunks(size).map(|c| c.to_vec()).collect(),
+                false => vec![data.to_vec()],
+            };
+            sectors.push(Sector {
+                c: info[0],
+                h: info[1],
+                r: info[2],
+                n: info[3],
+                st1: info[4],
+                st2: info[5],
+                copies,
+                next_copy: 0,
+            });
+            offset += length;
+        }
+        Ok(Track {
+            track: block[0x10],
+            side: block[0x11],
+            gap3: block[0x16],
+            filler: block[0x17],
+            sectors,
+        })
+    }
+
+    // Fails when the disk does not fit in the extended format
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        if self.tracks * self.sides > MAX_TRACKS {
+            return Err(invalid("too many tracks for a DSK image"));
+        }
+        let mut image = vec![0_u8; 0x100];
+        image[..EXTENDED_HEADER.len()].copy_from_slice(EXTENDED_HEADER);
+        image[0x22..0x22 + CREATOR.len()].copy_from_slice(CREATOR);
+        image[0x30] = self.tracks as u8;
+        image[0x31] = self.sides as u8;
+        for (index, track) in self.track_data.iter().enumerate() {
+            let Some(track) = track else {
+                continue;
+            };
+            if track.sectors.len() > MAX_SECTORS {
+                return Err(invalid("too many sectors in a track for a DSK image"));
+            }
+            let mut block = vec![0_u8; 0x100];
+            block[..TRACK_HEADER.len()].copy_from_slice(TRACK_HEADER);
+            block[0x10] = track.track;
+            block[0x11] = track.side;
+            block[0x14] = track.sectors.first().map_or(2, |s| s.n);
+            block[0x15] = track.sectors.len() as u8;
+            block[0x16] = track.gap3;
+            block[0x17] = track.filler;
+            for (i, sector) in track.sectors.iter().enumerate() {
+                let length: usize = sector.copies.iter().map(|c| c.len()).sum();
+                let length = u16::try_from(length)
+                    .map_err(|_| invalid("sector too long for a DSK image"))?;
+                let info = &mut block[0x18 + i * 8..0x20 + i * 8];
+                info[..6].copy_from_slice(&[
+                    sector.c, sector.h, sector.r, sector.n, sector.st1, sector.st2,
+                ]);
+                info[6..].copy_from_slice(&length.to_le_bytes());
+            }
+            for sector in &track.sectors {
+                for copy in &sector.copies {
+                    block.extend_from_slice(copy);
+                }
+            }
+            block.resize(block.len().div_ceil(0x100) * 0x100, 0);
+            if block.len() > MAX_TRACK_SIZE {
+                return Err(invalid("track too long for a DSK image"));
+            }
+            image[0x34 + index] = (block.len() / 0x100) as u8;
+            image.extend(block);
+        }
+        Ok(image)
+    }
+
+    pub fn track(&self, track: usize, side: usize) -> Option<&Track> {
+        self.track_data.get(track * self.sides + side)?.as_ref()
+    }
+
+    pub fn track_mut(&mut self, track: usize, side: usize) -> Option<&mut Track> {
+        self.track_data.get_mut(track * self.sides + side)?.as_mut()
+    }
+
+    // Replaces a track, as done by a format. The disk grows if needed.
+    pub fn format_track(&mut self, track: usize, side: usize, data: Track) {
+        if side >= self.sides {
+            return;
+        }
+        if track >= self.tracks {
+            self.tracks = track + 1;
+            self.track_data.resize_with(self.tracks * self.sides, || None);
+        }
+        self.track_data[track * self.sides + side] = Some(data);
+    }
+}
@@ -0,0 +1,14 @@
+pub mod ay38910;
+pub mod beeper;
+pub mod crtc6845;
+pub mod daisy_chain;
+pub mod dsk;
+pub mod ppi8255;
+pub mod resampler;
+pub mod serial;
+pub mod tape;
+pub mod upd765;
+pub mod wav;
+pub mod z80ctc;
+pub mod z80pio;
+pub mod z80sio;
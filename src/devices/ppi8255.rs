@@ -0,0 +1,115 @@
+// Intel 8255 programmable peripheral interface. Modes 1 and 2 are not emulated, all the
+// ports work as in mode 0.
+
+pub const PORT_A: u8 = 0;
+pub const PORT_B: u8 = 1;
+pub const PORT_C: u8 = 2;
+pub const CONTROL: u8 = 3;
+
+pub struct Ppi8255 {
+    // Output latches
+    pub port_a: u8,
+    pub port_b: u8,
+    pub port_c: u8,
+    pub control: u8,
+}
+
+impl Default for Ppi8255 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppi8255 {
+    pub fn new() -> Self {
+        Self {
+            port_a: 0x00,
+            port_b: 0x00,
+            port_c: 0x00,
+            // Mode 0, all ports as inputs
+            control: 0x9B,
+        }
+    }
+
+    pub fn port_a_is_input(&self) -> bool {
+        self.control & 0x10 == 0x10
+    }
+
+    pub fn port_b_is_input(&self) -> bool {
+        self.control & 0x02 == 0x02
+    }
+
+    // Lines of port C used as inputs
+    fn port_c_input_mask(&self) -> u8 {
+        let mut mask = 0x00;
+        if self.control & 0x08 == 0x08 {
+            mask |= 0xF0;
+        }
+        if self.control & 0x01 == 0x01 {
+            mask |= 0x0F;
+        }
+        mask
+    }
+
+    // `input` is the level of the lines of the port, it is seen on the lines programmed
+    // as inputs
+    pub fn read(&self, port: u8, input: u8) -> u8 {
+        match port & 0x03 {
+            PORT_A if self.port_a_is_input() => input,
+            PORT_A => self.port_a,
+            PORT_B if self.port_b_is_input() => input,
+            PORT_B => self.port_b,
+            PORT_C => {
+                let mask = self.port_c_input_mask();
+                (input & mask) | (self.port_c & !mask)
+            }
+            // The control register cannot be read back
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, port: u8, data: u8) {
+        match port & 0x03 {
+            PORT_A => self.port_a = data,
+            PORT_B => self.port_b = data,
+            PORT_C => self.port_c = data,
+            _ => self.write_control(data),
+        }
+    }
+
+    pub fn write_control(&mut self, data: u8) {
+        if data & 0x80 == 0x80 {
+            // Mode set: the output latches are cleared
+            self.control = data;
+            self.port_a = 0x00;
+            self.port_b = 0x00;
+            self.port_c = 0x00;
+        } else {
+            // Bit set/reset of port C
+            let bit = 1 << ((data >> 1) & 0x07);
+            match data & 0x01 {
+                0 => self.port_c &= !bit,
+                _ => self.port_c |= bit,
+            }
+        }
+    }
+
+    // Level driven on the lines of the ports, the lines used as inputs are pulled high
+    pub fn output_a(&self) -> u8 {
+        match self.port_a_is_input() {
+            true => 0xFF,
+            false => self.port_a,
+        }
+    }
+
+    pub fn output_b(&self) -> u8 {
+        match self.port_b_is_input() {
+            true => 0xFF,
+            false => self.port_b,
+        }
+    }
+
+    pub fn output_c(&self) -> u8 {
+        self.port_c | self.port_c_input_mask()
+    }
+}
@@ -0,0 +1,104 @@
+// Band-limited resampling of a sound generated at the rate of a chip to the rate asked
+// by the host, with a windowed sinc low-pass filter
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+// Zero crossings of the sinc on each side
+const ZERO_CROSSINGS: usize = 8;
+// Entries of the kernel table per input sample
+const PHASES: usize = 64;
+
+pub struct Resampler {
+    pub input_rate: f64,
+    pub output_rate: f64,
+    // Samples ready for the host
+    pub output: VecDeque<f32>,
+    // Input samples around the next output one, `base` is the index of the first
+    history: VecDeque<f32>,
+    base: u64,
+    // Position of the next output sample, in input samples
+    next: f64,
+    step: f64,
+    half_width: f64,
+    kernel: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(input_rate: f64, output_rate: f64) -> Self {
+        // Cut off a bit below the lowest Nyquist frequency, relative to the input rate
+        let cutoff = 0.45 * (output_rate / input_rate).min(1.0);
+        let half_width = ZERO_CROSSINGS as f64 / (2.0 * cutoff);
+        let size = (half_width * PHASES as f64).ceil() as usize + 2;
+        let kernel = (0..size)
+            .map(|i| {
+                let x = i as f64 / PHASES as f64;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (2.0 * PI * cutoff * x).sin() / (2.0 * PI * cutoff * x)
+                };
+                // Blackman window
+                let w = match x / half_width {
+                    r if r >= 1.0 => 0.0,
+                    r => 0.42 + 0.5 * (PI * r).cos() + 0.08 * (2.0 * PI * r).cos(),
+                };
+                (sinc * w) as f32
+            })
+            .collect();
+        Self {
+            input_rate,
+            output_rate,
+            output: VecDeque::new(),
+            history: VecDeque::new(),
+            base: 0,
+            next: 0.0,
+            step: input_rate / output_rate,
+            half_width,
+            kernel,
+        }
+    }
+
+    fn kernel(&self, x: f64) -> f32 {
+        let pos = x.abs() * PHASES as f64;
+        let i = pos as usize;
+        if i + 1 >= self.kernel.len() {
+            return 0.0;
+        }
+        let frac = (pos - i as f64) as f32;
+        self.kernel[i] + (self.kernel[i + 1] - self.kernel[i]) * frac
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        self.history.push_back(sample);
+        let last = self.base + self.history.len() as u64 - 1;
+        while self.next + self.half_width <= last as f64 {
+            let first = (self.next - self.half_width).ceil().max(0.0) as u64;
+            let (mut sum, mut weights) = (0.0, 0.0);
+            for n in first.max(self.base)..=last {
+                let w = self.kernel(self.next - n as f64);
+                sum += w * self.history[(n - self.base) as usize];
+                weights += w;
+            }
+            // Normalised for a unity gain at DC
+            self.output
+                .push_back(if weights != 0.0 { sum / weights } else { 0.0 });
+            self.next += self.step;
+        }
+        // Drop the input samples no longer needed
+        let keep = (self.next - self.half_width).floor().max(0.0) as u64;
+        while self.base < keep && !self.history.is_empty() {
+            self.history.pop_front();
+            self.base += 1;
+        }
+    }
+
+    // Moves up to `buffer.len()` samples to the host buffer, returns how many
+    pub fn read(&mut self, buffer: &mut [f32]) -> usize {
+        let n = buffer.len().min(self.output.len());
+        for (dst, src) in buffer.iter_mut().zip(self.output.drain(..n)) {
+            *dst = src;
+        }
+        n
+    }
+}
@@ -0,0 +1,77 @@
+// Host side of a serial line: bytes received from a stream are queued by a thread so that
+// the emulation never blocks, bytes sent are written at once
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+pub struct SerialLink {
+    rx: Receiver<u8>,
+    tx: Box<dyn Write + Send>,
+    // The stream was closed by the other end
+    pub closed: bool,
+}
+
+impl SerialLink {
+    // Any pair of streams, e.g. the stdout and stdin of a child process or the two ends
+    // of a named pipe
+    pub fn from_streams<R, W>(mut reader: R, writer: W) -> Self
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let (sender, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0_u8; 256];
+            while let Ok(n @ 1..) = reader.read(&mut buf) {
+                if buf[..n].iter().any(|&b| sender.send(b).is_err()) {
+                    break;
+                }
+            }
+        });
+        Self {
+            rx,
+            tx: Box::new(writer),
+            closed: false,
+        }
+    }
+
+    // The terminal of the host
+    pub fn stdio() -> Self {
+        Self::from_streams(io::stdin(), io::stdout())
+    }
+
+    pub fn tcp(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Self::from_streams(stream.try_clone()?, stream))
+    }
+
+    pub fn tcp_connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Self::tcp(TcpStream::connect(addr)?)
+    }
+
+    // Waits for a terminal program to connect to the port on the local host
+    pub fn tcp_listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let (stream, _) = listener.accept()?;
+        Self::tcp(stream)
+    }
+
+    // Next byte received, if any
+    pub fn receive(&mut self) -> Option<u8> {
+        match self.rx.try_recv() {
+            Ok(byte) => Some(byte),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.closed = true;
+                None
+            }
+        }
+    }
+
+    pub fn send(&mut self, byte: u8) -> io::Result<()> {
+        self.tx.write_all(&[byte])?;
+        self.tx.flush()
+    }
+}
@@ -0,0 +1,456 @@
+// Cassette tapes as a sequence of pulses, loaded from TZX (CDT on the CPC) or TAP files, and a
+// tape deck playing them into a machine and recording its output. Pulse lengths are in
+// T-states of the 3.5 MHz clock of the ZX Spectrum, as in the TZX format.
+
+use super::wav;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub const TAPE_CLOCK: u64 = 3_500_000;
+const TZX_SIGNATURE: &[u8] = b"ZXTape!\x1A";
+
+// Timings of the ROM loaders
+const PILOT: u32 = 2168;
+const SYNC1: u32 = 667;
+const SYNC2: u32 = 735;
+const ZERO: u32 = 855;
+const ONE: u32 = 1710;
+const HEADER_PILOTS: u32 = 8063;
+const DATA_PILOTS: u32 = 3223;
+const MS: u32 = 3500;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Pulse {
+    pub length: u32,
+    pub level: bool,
+}
+
+// Bytes of a block with the timings of the ROM loaders, from its first pulse up to its
+// pause, so that a ROM trap can load it at once
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StandardBlock {
+    pub data: Vec<u8>,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Default)]
+pub struct Tape {
+    pub pulses: Vec<Pulse>,
+    // Pulses where the tape stops by itself
+    pub stops: Vec<usize>,
+    pub blocks: Vec<StandardBlock>,
+    level: bool,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Little endian value of `n` bytes at `pos`
+fn le(data: &[u8], pos: usize, n: usize) -> io::Result<usize> {
+    let bytes = data
+        .get(pos..pos + n)
+        .ok_or_else(|| invalid("TZX block truncated"))?;
+    Ok(bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | b as usize))
+}
+
+fn slice(data: &[u8], pos: usize, n: usize) -> io::Result<&[u8]> {
+    data.get(pos..pos + n)
+        .ok_or_else(|| invalid("TZX block truncated"))
+}
+
+impl Tape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // TZX files are recognised by their signature, other files are read as TAP
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        match data.starts_with(TZX_SIGNATURE) {
+            true => Self::from_tzx(&data),
+            false => Self::from_tap(&data),
+        }
+    }
+
+    // Each pulse changes the level
+    pub fn pulse(&mut self, length: u32) {
+        self.level = !self.level;
+        self.pulses.push(Pulse {
+            length,
+            level: self.level,
+        });
+    }
+
+    pub fn level(&mut self, length: u32, level: bool) {
+        self.level = level;
+        if let Some(last) = self.pulses.last_mut() {
+            if last.level == level && last.length.checked_add(length).is_some() {
+                last.length += length;
+                return;
+            }
+        }
+        self.pulses.push(Pulse { length, level });
+    }
+
+    pub fn pause(&mut self, ms: u32) {
+        if ms > 0 {
+            // The last edge is ended by 1 ms at the opposite level
+            self.pulse(MS);
+            self.level(ms.saturating_sub(1) * MS, false);
+        }
+    }
+
+    pub fn tone(&mut self, length: u32, count: u32) {
+        for _ in 0..count {
+            self.pulse(length);
+        }
+    }
+
+    // Two pulses per bit, most significant bit first
+    pub fn data(&mut self, zero: u32, one: u32, data: &[u8], last_bits: u8) {
+        for (i, &byte) in data.iter().enumerate() {
+            let bits = match i + 1 == data.len() {
+                true => last_bits.clamp(1, 8),
+                false => 8,
+            };
+            for bit in 0..bits {
+                let length = match byte & (0x80 >> bit) {
+                    0 => zero,
+                    _ => one,
+                };
+                self.pulse(length);
+                self.pulse(length);
+            }
+        }
+    }
+
+    // Block with the timings of the ROM loaders
+    pub fn standard_block(&mut self, data: &[u8], pause: u32) {
+        let pilots = match data.first() {
+            Some(&flag) if flag < 0x80 => HEADER_PILOTS,
+            _ => DATA_PILOTS,
+        };
+        let start = self.pulses.len();
+        self.tone(PILOT, pilots);
+        self.pulse(SYNC1);
+        self.pulse(SYNC2);
+        self.data(ZERO, ONE, data, 8);
+        self.blocks.push(StandardBlock {
+            data: data.to_vec(),
+            start,
+            end: self.pulses.len(),
+        });
+        self.pause(pause);
+    }
+
+    // TAP: each block is its length on 2 bytes followed by the flag, the data and the
+    // checksum. Blocks are played with the timings of the ROM and a pause of 1 s.
+    pub fn from_tap(tap: &[u8]) -> io::Result<Self> {
+        let mut tape = Self::new();
+        let mut pos = 0;
+        while pos < tap.len() {
+            let length = le(tap, pos, 2).map_err(|_| invalid("TAP block truncated"))?;
+            let data = tap
+                .get(pos + 2..pos + 2 + length)
+                .ok_or_else(|| invalid("TAP block truncated"))?;
+            tape.standard_block(data, 1000);
+            pos += 2 + length;
+        }
+        Ok(tape)
+    }
+
+    pub fn from_tzx(tzx: &[u8]) -> io::Result<Self> {
+        if tzx.len() < 10 || !tzx.starts_with(TZX_SIGNATURE) {
+            return Err(invalid("not a TZX file"));
+        }
+        let mut tape = Self::new();
+        let mut pos = 10;
+        // Start of the loop and repetitions left
+        let mut repeat: Option<(usize, usize)> = None;
+        while pos < tzx.len() {
+            let id = tzx[pos];
+            pos += 1;
+            let b = &tzx[pos..];
+            pos += match id {
+                // Standard speed data
+                0x10 => {
+                    let length = le(b, 2, 2)?;
+                    tape.standard_block(slice(b, 4, length)?, le(b, 0, 2)? as u32);
+                    4 + length
+                }
+                // Turbo speed data
+                0x11 => {
+                    let length = le(b, 15, 3)?;
+                    tape.tone(le(b, 0, 2)? as u32, le(b, 10, 2)? as u32);
+                    tape.pulse(le(b, 2, 2)? as u32);
+                    tape.pulse(le(b, 4, 2)? as u32);
+                    let (zero, one) = (le(b, 6, 2)? as u32, le(b, 8, 2)? as u32);
+                    tape.data(zero, one, slice(b, 18, length)?, b[12]);
+                    tape.pause(le(b, 13, 2)? as u32);
+                    18 + length
+                }
+                // Pure tone
+                0x12 => {
+                    tape.tone(le(b, 0, 2)? as u32, le(b, 2, 2)? as u32);
+                    4
+                }
+                // Pulse sequence
+                0x13 => {
+                    let count = le(b, 0, 1)?;
+                    for i in 0..count {
+                        tape.pulse(le(b, 1 + i * 2, 2)? as u32);
+                    }
+                    1 + count * 2
+                }
+                // Pure data
+                0x14 => {
+                    let length = le(b, 7, 3)?;
+                    let (zero, one) = (le(b, 0, 2)? as u32, le(b, 2, 2)? as u32);
+                    tape.data(zero, one, slice(b, 10, length)?, b[4]);
+                    tape.pause(le(b, 5, 2)? as u32);
+                    10 + length
+                }
+                // Direct recording, one bit per sample
+                0x15 => {
+                    let length = le(b, 5, 3)?;
+                    let sample = le(b, 0, 2)? as u32;
+                    let data = slice(b, 8, length)?;
+                    for (i, &byte) in data.iter().enumerate() {
+                        let bits = match i + 1 == data.len() {
+                            true => b[4].clamp(1, 8),
+                            false => 8,
+                        };
+                        for bit in 0..bits {
+                            tape.level(sample, byte & (0x80 >> bit) != 0);
+                        }
+                    }
+                    tape.pause(le(b, 2, 2)? as u32);
+                    8 + length
+                }
+                // Pause, or stop the tape when 0
+                0x20 => {
+                    match le(b, 0, 2)? {
+                        0 => tape.stops.push(tape.pulses.len()),
+                        ms => tape.pause(ms as u32),
+                    }
+                    2
+                }
+                // Group start and end
+                0x21 => 1 + le(b, 0, 1)?,
+                0x22 => 0,
+                // Jump, call sequence, return and select are not followed
+                0x23 => 2,
+                0x24 => {
+                    repeat = Some((pos + 2, le(b, 0, 2)?));
+                    2
+                }
+                0x25 => {
+                    if let Some((start, count)) = repeat {
+                        if count > 1 {
+                            repeat = Some((start, count - 1));
+                            pos = start;
+                            continue;
+                        }
+                        repeat = None;
+                    }
+                    0
+                }
+                0x26 => 2 + le(b, 0, 2)? * 2,
+                0x27 => 0,
+                0x28 => 2 + le(b, 0, 2)?,
+                // Stop the tape in 48K mode
+                0x2A => 4,
+                // Signal level
+                0x2B => {
+                    tape.level = le(b, 4, 1)? != 0;
+                    5
+                }
+                // Text, message, archive info, hardware type, custom info, glue
+                0x30 => 1 + le(b, 0, 1)?,
+                0x31 => 2 + le(b, 1, 1)?,
+                0x32 => 2 + le(b, 0, 2)?,
+                0x33 => 1 + le(b, 0, 1)? * 3,
+                0x35 => 14 + le(b, 10, 4)?,
+                0x5A => 9,
+                // Emulation info and snapshot, both obsolete
+                0x34 => 8,
+                0x40 => 4 + le(b, 1, 3)?,
+                // CSW recording, generalized data and any other block are skipped with
+                // the length that follows their ID
+                _ => 4 + le(b, 0, 4)?,
+            };
+        }
+        Ok(tape)
+    }
+
+    // Length in T-states of the 3.5 MHz clock
+    pub fn length(&self) -> u64 {
+        self.pulses.iter().map(|p| p.length as u64).sum()
+    }
+}
+
+pub struct TapeDeck {
+    pub tape: Option<Tape>,
+    // Clock of the machine in Hz
+    pub clock: u64,
+    // PLAY and RECORD keys
+    pub playing: bool,
+    pub recording: bool,
+    // Motor relay driven by the machine
+    pub motor: bool,
+    // Level played
+    pub level: bool,
+    pos: usize,
+    // Time spent in the current pulse, in T-states of the machine times TAPE_CLOCK
+    elapsed: u64,
+    // Recorded pulses in T-states of the machine, and the level being recorded
+    pub recorded: Vec<u64>,
+    pub recorded_start: bool,
+    record_level: Option<bool>,
+    since_edge: u64,
+}
+
+impl TapeDeck {
+    pub fn new(clock: u64) -> Self {
+        Self {
+            tape: None,
+            clock,
+            playing: false,
+            recording: false,
+            motor: false,
+            level: false,
+            pos: 0,
+            elapsed: 0,
+            recorded: Vec::new(),
+            recorded_start: false,
+            record_level: None,
+            since_edge: 0,
+        }
+    }
+
+    pub fn insert(&mut self, tape: Tape) {
+        self.tape = Some(tape);
+        self.rewind();
+    }
+
+    pub fn eject(&mut self) -> Option<Tape> {
+        self.playing = false;
+        self.tape.take()
+    }
+
+    pub fn rewind(&mut self) {
+        self.seek(0);
+    }
+
+    // Index of the pulse being played
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn seek(&mut self, pulse: usize) {
+        self.pos = pulse;
+        self.elapsed = 0;
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.tape.as_ref().is_none_or(|t| self.pos >= t.pulses.len())
+    }
+
+    pub fn running(&self) -> bool {
+        self.motor && self.playing
+    }
+
+    // Runs the deck for `t_states` of the machine clock
+    pub fn run(&mut self, t_states: u64) {
+        if !self.running() {
+            return;
+        }
+        let Some(tape) = &self.tape else {
+            return;
+        };
+        self.elapsed += t_states * TAPE_CLOCK;
+        while let Some(pulse) = tape.pulses.get(self.pos) {
+            self.level = pulse.level;
+            let length = pulse.length as u64 * self.clock;
+            if self.elapsed < length {
+                return;
+            }
+            self.elapsed -= length;
+            self.pos += 1;
+            if tape.stops.contains(&self.pos) {
+                self.playing = false;
+                self.elapsed = 0;
+                return;
+            }
+        }
+        self.playing = false;
+    }
+
+    // Records the level output by the machine for `t_states`
+    pub fn record(&mut self, level: bool, t_states: u64) {
+        if !(self.motor && self.recording) {
+            return;
+        }
+        match self.record_level {
+            None => self.recorded_start = level,
+            Some(previous) if previous != level => {
+                self.recorded.push(self.since_edge);
+                self.since_edge = 0;
+            }
+            _ => {}
+        }
+        self.record_level = Some(level);
+        self.since_edge += t_states;
+    }
+
+    // Recorded pulses, ended by the current one
+    fn recorded_pulses(&self) -> Vec<u64> {
+        let mut pulses = self.recorded.clone();
+        if self.since_edge > 0 {
+            pulses.push(self.since_edge);
+        }
+        pulses
+    }
+
+    // CSW version 1.01, RLE compressed
+    pub fn save_csw<P: AsRef<Path>>(&self, path: P, sample_rate: u32) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"Compressed Square Wave\x1A")?;
+        writer.write_all(&[1, 1])?;
+        writer.write_all(&(sample_rate as u16).to_le_bytes())?;
+        writer.write_all(&[1, self.recorded_start as u8, 0, 0, 0])?;
+        let mut time = 0;
+        let mut samples = 0;
+        for pulse in self.recorded_pulses() {
+            time += pulse;
+            // Rounded on the absolute time to avoid drifting
+            let end = (time * sample_rate as u64 + self.clock / 2) / self.clock;
+            let count = (end - samples).max(1);
+            samples += count;
+            match count {
+                1..=255 => writer.write_all(&[count as u8])?,
+                _ => {
+                    writer.write_all(&[0])?;
+                    writer.write_all(&(count as u32).to_le_bytes())?;
+                }
+            }
+        }
+        writer.flush()
+    }
+
+    pub fn save_wav<P: AsRef<Path>>(&self, path: P, sample_rate: u32) -> io::Result<()> {
+        let mut samples = Vec::new();
+        let mut level = self.recorded_start;
+        let mut time = 0;
+        for pulse in self.recorded_pulses() {
+            time += pulse;
+            let end = (time * sample_rate as u64 / self.clock) as usize;
+            samples.resize(end, if level { 0.5 } else { -0.5 });
+            level = !level;
+        }
+        wav::save_wav(path, sample_rate, &samples)
+    }
+}
@@ -0,0 +1,581 @@
+// NEC uPD765 floppy disk controller in non-DMA mode
+
+use super::dsk::{Disk, Sector, Track, MAX_SECTORS, MAX_TRACK_SIZE};
+use std::collections::VecDeque;
+
+// Main status register
+const MSR_RQM: u8 = 0x80;
+const MSR_DIO: u8 = 0x40;
+const MSR_EXM: u8 = 0x20;
+const MSR_CB: u8 = 0x10;
+
+// Status register 0
+const ST0_IC: u8 = 0x80;
+const ST0_AT: u8 = 0x40;
+const ST0_SE: u8 = 0x20;
+const ST0_NR: u8 = 0x08;
+// Status register 1
+const ST1_EN: u8 = 0x80;
+const ST1_DE: u8 = 0x20;
+const ST1_OR: u8 = 0x10;
+const ST1_ND: u8 = 0x04;
+const ST1_NW: u8 = 0x02;
+const ST1_MA: u8 = 0x01;
+// Status register 2
+const ST2_CM: u8 = 0x40;
+const ST2_DD: u8 = 0x20;
+const ST2_WC: u8 = 0x10;
+const ST2_BC: u8 = 0x02;
+const ST2_MD: u8 = 0x01;
+// Status register 3
+const ST3_WP: u8 = 0x40;
+const ST3_RY: u8 = 0x20;
+const ST3_T0: u8 = 0x10;
+const ST3_TS: u8 = 0x08;
+
+// Double density at 250 kbit/s: one byte every 32 us
+const BYTE_US: u32 = 32;
+
+// Commands, with the number of bytes of their command phase
+const READ_TRACK: u8 = 0x02;
+const SPECIFY: u8 = 0x03;
+const SENSE_DRIVE_STATUS: u8 = 0x04;
+const WRITE_DATA: u8 = 0x05;
+const READ_DATA: u8 = 0x06;
+const RECALIBRATE: u8 = 0x07;
+const SENSE_INTERRUPT_STATUS: u8 = 0x08;
+const WRITE_DELETED_DATA: u8 = 0x09;
+const READ_ID: u8 = 0x0A;
+const READ_DELETED_DATA: u8 = 0x0C;
+const FORMAT_TRACK: u8 = 0x0D;
+const SEEK: u8 = 0x0F;
+
+fn command_length(command: u8) -> usize {
+    match command & 0x1F {
+        READ_TRACK | WRITE_DATA | READ_DATA | WRITE_DELETED_DATA | READ_DELETED_DATA => 9,
+        SPECIFY | SEEK => 3,
+        SENSE_DRIVE_STATUS | RECALIBRATE | READ_ID => 2,
+        FORMAT_TRACK => 6,
+        _ => 1,
+    }
+}
+
+pub struct Drive {
+    pub disk: Option<Disk>,
+    // Cylinder under the head
+    pub cylinder: u8,
+    // Index of the next sector passing under the head
+    next_sector: usize,
+    seek_target: u8,
+    // Time left before the end of the seek
+    seek_us: Option<u32>,
+    // ST0 of a seek or recalibrate that has ended, for Sense Interrupt Status
+    seek_end: Option<u8>,
+}
+
+impl Drive {
+    fn new() -> Self {
+        Self {
+            disk: None,
+            cylinder: 0,
+            next_sector: 0,
+            seek_target: 0,
+            seek_us: None,
+            seek_end: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Command,
+    Execution,
+    Result,
+}
+
+pub struct Upd765 {
+    pub drives: [Drive; 4],
+    // Motor of all the drives
+    pub motor: bool,
+    pub phase: Phase,
+    command: Vec<u8>,
+    result: VecDeque<u8>,
+    // Data of the sector being transferred
+    buffer: Vec<u8>,
+    pos: usize,
+    // Time until the next byte can be transferred, and how late the CPU is once it can
+    byte_us: u32,
+    late_us: u32,
+    st0: u8,
+    st1: u8,
+    st2: u8,
+    // ID of the sector being transferred
+    chrn: [u8; 4],
+    // Sectors transferred by Read Track
+    count: u8,
+    // Time taken by a step of the head
+    step_us: u32,
+}
+
+impl Default for Upd765 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Upd765 {
+    pub fn new() -> Self {
+        Self {
+            drives: [Drive::new(), Drive::new(), Drive::new(), Drive::new()],
+            motor: false,
+            phase: Phase::Command,
+            command: Vec::new(),
+            result: VecDeque::new(),
+            buffer: Vec::new(),
+            pos: 0,
+            byte_us: 0,
+            late_us: 0,
+            st0: 0,
+            st1: 0,
+            st2: 0,
+            chrn: [0; 4],
+            count: 0,
+            step_us: 6000,
+        }
+    }
+
+    // Drives and disks are kept
+    pub fn reset(&mut self) {
+        self.phase = Phase::Command;
+        self.command.clear();
+        self.result.clear();
+        for drive in self.drives.iter_mut() {
+            drive.seek_us = None;
+            drive.seek_end = None;
+        }
+    }
+
+    pub fn insert(&mut self, drive: usize, disk: Disk) {
+        self.drives[drive & 0x03].disk = Some(disk);
+    }
+
+    pub fn eject(&mut self, drive: usize) -> Option<Disk> {
+        self.drives[drive & 0x03].disk.take()
+    }
+
+    // Drive and head selected by the second byte of the command
+    fn unit(&self) -> usize {
+        self.command.get(1).map_or(0, |&b| (b & 0x03) as usize)
+    }
+
+    fn head(&self) -> u8 {
+        self.command.get(1).map_or(0, |&b| (b >> 2) & 0x01)
+    }
+
+    fn ready(&self, unit: usize) -> bool {
+        self.motor && self.drives[unit].disk.is_some()
+    }
+
+    pub fn read_status(&self) -> u8 {
+        let mut msr = 0;
+        for (i, drive) in self.drives.iter().enumerate() {
+            if drive.seek_us.is_some() {
+                msr |= 1 << i;
+            }
+        }
+        match self.phase {
+            Phase::Command if self.command.is_empty() => msr | MSR_RQM,
+            Phase::Command => msr | MSR_RQM | MSR_CB,
+            Phase::Execution => {
+                msr |= MSR_CB | MSR_EXM;
+                if self.byte_us == 0 {
+                    msr |= MSR_RQM;
+                }
+                if self.is_read() {
+                    msr |= MSR_DIO;
+                }
+                msr
+            }
+            Phase::Result => msr | MSR_RQM | MSR_DIO | MSR_CB,
+        }
+    }
+
+    fn is_read(&self) -> bool {
+        matches!(
+            self.command[0] & 0x1F,
+            READ_DATA | READ_DELETED_DATA | READ_TRACK
+        )
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        match self.phase {
+            Phase::Execution if self.is_read() && self.byte_us == 0 => {
+                let Some(&data) = self.buffer.get(self.pos) else {
+                    return 0xFF;
+                };
+                self.next_byte();
+                data
+            }
+            Phase::Result => {
+                let data = self.result.pop_front().unwrap_or(0xFF);
+                if self.result.is_empty() {
+                    self.phase = Phase::Command;
+                }
+                data
+            }
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        match self.phase {
+            Phase::Command => {
+                self.command.push(data);
+                if self.command.len() == command_length(self.command[0]) {
+                    self.execute();
+                }
+            }
+            Phase::Execution if !self.is_read() && self.byte_us == 0 => {
+                self.buffer.push(data);
+                self.next_byte();
+            }
+            _ => {}
+        }
+    }
+
+    fn next_byte(&mut self) {
+        self.pos += 1;
+        self.byte_us = BYTE_US;
+        self.late_us = 0;
+        let length = match self.command[0] & 0x1F {
+            FORMAT_TRACK => 4 * self.command[3] as usize,
+            _ => self.sector_length(),
+        };
+        if self.pos >= length {
+            self.sector_done();
+        }
+    }
+
+    // Runs the drives and the data transfer for `us` microseconds
+    pub fn run(&mut self, us: u32) {
+        if self.phase == Phase::Execution {
+            if self.byte_us > us {
+                self.byte_us -= us;
+            } else {
+                self.late_us += us - self.byte_us;
+                self.byte_us = 0;
+                // The CPU missed a byte
+                if self.late_us >= BYTE_US {
+                    self.st1 |= ST1_OR;
+                    self.finish(ST0_AT);
+                }
+            }
+        }
+        for unit in 0..4 {
+            let Some(left) = self.drives[unit].seek_us else {
+                continue;
+            };
+            if left > us {
+                self.drives[unit].seek_us = Some(left - us);
+                continue;
+            }
+            let ready = self.ready(unit);
+            let drive = &mut self.drives[unit];
+            drive.seek_us = None;
+            drive.cylinder = drive.seek_target;
+            drive.seek_end = Some(match ready {
+                true => ST0_SE | unit as u8,
+                false => ST0_SE | ST0_AT | ST0_NR | unit as u8,
+            });
+        }
+    }
+
+    fn execute(&mut self) {
+        self.st0 = 0;
+        self.st1 = 0;
+        self.st2 = 0;
+        let unit = self.unit();
+        match self.command[0] & 0x1F {
+            SPECIFY => {
+                self.step_us = (16 - (self.command[1] >> 4) as u32) * 2000;
+                self.command.clear();
+            }
+            SENSE_DRIVE_STATUS => {
+                let drive = &self.drives[unit];
+                let mut st3 = self.command[1] & 0x07;
+                if let Some(disk) = &drive.disk {
+                    if disk.write_protected {
+                        st3 |= ST3_WP;
+                    }
+                    if disk.sides > 1 {
+                        st3 |= ST3_TS;
+                    }
+                }
+                if self.ready(unit) {
+                    st3 |= ST3_RY;
+                }
+                if drive.cylinder == 0 {
+                    st3 |= ST3_T0;
+                }
+                self.set_result(&[st3]);
+            }
+            SENSE_INTERRUPT_STATUS => {
+                let end = self
+                    .drives
+                    .iter_mut()
+                    .find_map(|d| d.seek_end.take().map(|st0| (st0, d.cylinder)));
+                match end {
+                    Some((st0, pcn)) => self.set_result(&[st0, pcn]),
+                    None => self.set_result(&[ST0_IC]),
+                }
+            }
+            RECALIBRATE | SEEK => {
+                let target = match self.command[0] & 0x1F {
+                    SEEK => self.command[2],
+                    _ => 0,
+                };
+                let drive = &mut self.drives[unit];
+                let steps = drive.cylinder.abs_diff(target) as u32;
+                drive.seek_target = target;
+                drive.seek_us = Some((steps * self.step_us).max(1));
+                self.command.clear();
+            }
+            READ_ID => {
+                if !self.ready(unit) {
+                    return self.finish(ST0_AT | ST0_NR);
+                }
+                let head = self.head() as usize;
+                let drive = &mut self.drives[unit];
+                let id = drive
+                    .disk
+                    .as_ref()
+                    .and_then(|d| d.track(drive.cylinder as usize, head))
+                    .filter(|t| !t.sectors.is_empty())
+                    .map(|t| {
+                        let sector = &t.sectors[drive.next_sector % t.sectors.len()];
+                        [sector.c, sector.h, sector.r, sector.n]
+                    });
+                drive.next_sector += 1;
+                match id {
+                    Some(id) => {
+                        self.chrn = id;
+                        self.finish(0);
+                    }
+                    None => {
+                        self.st1 = ST1_MA;
+                        self.finish(ST0_AT);
+                    }
+                }
+            }
+            READ_DATA | READ_DELETED_DATA | READ_TRACK | WRITE_DATA | WRITE_DELETED_DATA => {
+                self.chrn.copy_from_slice(&self.command[2..6]);
+                self.count = 0;
+                if !self.ready(unit) {
+                    return self.finish(ST0_AT | ST0_NR);
+                }
+                self.start_sector();
+            }
+            FORMAT_TRACK => {
+                if !self.ready(unit) {
+                    return self.finish(ST0_AT | ST0_NR);
+                }
+                if self.drives[unit].disk.as_ref().is_some_and(|d| d.write_protected) {
+                    self.st1 = ST1_NW;
+                    return self.finish(ST0_AT);
+                }
+                self.start_execution(Vec::new());
+            }
+            _ => self.set_result(&[ST0_IC]),
+        }
+    }
+
+    fn start_execution(&mut self, buffer: Vec<u8>) {
+        self.phase = Phase::Execution;
+        self.buffer = buffer;
+        self.pos = 0;
+        self.byte_us = BYTE_US;
+        self.late_us = 0;
+    }
+
+    // Bytes transferred for each sector, DTL is kept within 1 to 128 for N=0
+    fn sector_length(&self) -> usize {
+        match self.command[5] {
+            0 => (self.command[8] as usize).clamp(1, 128),
+            n => 128 << n.min(8),
+        }
+    }
+
+    // Looks for the sector with the ID in `chrn` on the track under the head
+    fn find_sector(&mut self) -> Option<&mut Sector> {
+        let unit = self.unit();
+        let head = self.head() as usize;
+        let read_track = self.command[0] & 0x1F == READ_TRACK;
+        let [c, h, r, n] = self.chrn;
+        let drive = &mut self.drives[unit];
+        let track = drive
+            .disk
+            .as_mut()
+            .and_then(|d| d.track_mut(drive.cylinder as usize, head))
+            .filter(|t| !t.sectors.is_empty());
+        let Some(track) = track else {
+            self.st1 |= ST1_MA;
+            return None;
+        };
+        let index = match read_track {
+            true => Some(self.count as usize).filter(|&i| i < track.sectors.len()),
+            false => track.sectors.iter().position(|s| s.r == r && s.c == c && s.h == h && s.n == n),
+        };
+        match index {
+            Some(index) => {
+                drive.next_sector = index + 1;
+                Some(&mut track.sectors[index])
+            }
+            None => {
+                self.st1 |= ST1_ND;
+                if track.sectors.iter().any(|s| s.r == r && s.c != c) {
+                    self.st2 |= match track.sectors.iter().any(|s| s.c == 0xFF) {
+                        true => ST2_WC | ST2_BC,
+                        false => ST2_WC,
+                    };
+                }
+                None
+            }
+        }
+    }
+
+    fn start_sector(&mut self) {
+        let command = self.command[0];
+        let length = self.sector_length();
+        let write = matches!(command & 0x1F, WRITE_DATA | WRITE_DELETED_DATA);
+        if write
+            && self.drives[self.unit()]
+                .disk
+                .as_ref()
+                .is_some_and(|d| d.write_protected)
+        {
+            self.st1 = ST1_NW;
+            return self.finish(ST0_AT);
+        }
+        let Some(sector) = self.find_sector() else {
+            return self.finish(ST0_AT);
+        };
+        if write {
+            return self.start_execution(Vec::with_capacity(length));
+        }
+        // Errors recorded in the image
+        let (st1, st2) = (sector.st1 & (ST1_DE | ST1_ND), sector.st2 & (ST2_DD | ST2_MD));
+        let deleted = sector.st2 & ST2_CM == ST2_CM;
+        let id = [sector.c, sector.h, sector.r, sector.n];
+        let mut data = sector.read().to_vec();
+        data.resize(length, 0xE5);
+        self.st1 |= st1;
+        self.st2 |= st2;
+        if command & 0x1F == READ_TRACK {
+            self.chrn = id;
+        }
+        // A sector with the other data mark is skipped or ends the command
+        if deleted != (command & 0x1F == READ_DELETED_DATA) && command & 0x1F != READ_TRACK {
+            self.st2 |= ST2_CM;
+            if command & 0x20 == 0x20 {
+                return self.sector_done();
+            }
+        }
+        self.start_execution(data);
+    }
+
+    fn sector_done(&mut self) {
+        let command = self.command[0] & 0x1F;
+        match command {
+            WRITE_DATA | WRITE_DELETED_DATA => {
+                let data = std::mem::take(&mut self.buffer);
+                if let Some(sector) = self.find_sector() {
+                    sector.write(&data);
+                    match command {
+                        WRITE_DELETED_DATA => sector.st2 |= ST2_CM,
+                        _ => sector.st2 &= !ST2_CM,
+                    }
+                }
+            }
+            FORMAT_TRACK => return self.format(),
+            _ => {}
+        }
+        if self.st1 != 0 || (self.st2 & !ST2_CM) != 0 {
+            return self.finish(ST0_AT);
+        }
+        if self.st2 & ST2_CM == ST2_CM && self.command[0] & 0x20 == 0 {
+            return self.finish(0);
+        }
+        self.count = self.count.wrapping_add(1);
+        // Without a terminal count, as on the CPC, the command ends past the last sector
+        let eot = self.command[6];
+        let last = match command {
+            READ_TRACK => self.count >= eot,
+            _ => self.chrn[2] == eot,
+        };
+        if last {
+            self.chrn[0] = self.chrn[0].wrapping_add(1);
+            self.chrn[2] = 1;
+            self.st1 |= ST1_EN;
+            return self.finish(ST0_AT);
+        }
+        self.chrn[2] = self.chrn[2].wrapping_add(1);
+        self.start_sector();
+    }
+
+    fn format(&mut self) {
+        let unit = self.unit();
+        let head = self.head();
+        let size = 128_usize << self.command[2].min(8);
+        let filler = self.command[5];
+        // The sectors a DSK track cannot hold are left out, as if they had run over the
+        // index hole
+        let mut room = MAX_TRACK_SIZE - 0x100;
+        let sectors: Vec<Sector> = self
+            .buffer
+            .chunks(4)
+            .take(MAX_SECTORS)
+            .take_while(|_| match room.checked_sub(size) {
+                Some(left) => {
+                    room = left;
+                    true
+                }
+                None => false,
+            })
+            .map(|id| Sector::new(id[0], id[1], id[2], id[3], vec![filler; size]))
+            .collect();
+        if let Some(last) = self.buffer.chunks(4).last() {
+            self.chrn.copy_from_slice(last);
+        }
+        let drive = &mut self.drives[unit];
+        let cylinder = drive.cylinder;
+        // The format ends on the index hole
+        drive.next_sector = 0;
+        if let Some(disk) = drive.disk.as_mut() {
+            disk.format_track(
+                cylinder as usize,
+                head as usize,
+                Track {
+                    track: cylinder,
+                    side: head,
+                    gap3: self.command[4],
+                    filler,
+                    sectors,
+                },
+            );
+        }
+        self.finish(0);
+    }
+
+    // Ends the command with the standard result phase
+    fn finish(&mut self, st0: u8) {
+        let st0 = st0 | (self.command[1] & 0x07);
+        let [c, h, r, n] = self.chrn;
+        self.set_result(&[st0, self.st1, self.st2, c, h, r, n]);
+    }
+
+    fn set_result(&mut self, result: &[u8]) {
+        self.command.clear();
+        self.result = result.iter().copied().collect();
+        self.phase = Phase::Result;
+    }
+}
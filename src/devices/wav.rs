@@ -0,0 +1,34 @@
+// WAV files with 16-bit PCM samples
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Samples between -1.0 and 1.0, one channel
+pub fn write_wav<W: Write>(writer: &mut W, sample_rate: u32, samples: &[f32]) -> io::Result<()> {
+    let data_size = samples.len() as u32 * 2;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16_u32.to_le_bytes())?;
+    // PCM, mono
+    writer.write_all(&1_u16.to_le_bytes())?;
+    writer.write_all(&1_u16.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * 2).to_le_bytes())?;
+    writer.write_all(&2_u16.to_le_bytes())?;
+    writer.write_all(&16_u16.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    for &sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn save_wav<P: AsRef<Path>>(path: P, sample_rate: u32, samples: &[f32]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_wav(&mut writer, sample_rate, samples)?;
+    writer.flush()
+}
@@ -0,0 +1,253 @@
+// Zilog Z80 CTC counter/timer circuit: four down-counters clocked by the system clock
+// through a prescaler (timer mode) or by their CLK/TRG input (counter mode). Channels 0-2
+// pulse their ZC/TO output at each zero count. Interrupts are vectored for IM 2, channel 0
+// has the highest priority.
+
+use crate::devices::daisy_chain::{DaisyDevice, DaisyLink};
+
+// Bits of the channel control word
+pub const INT_ENABLE: u8 = 0x80;
+pub const COUNTER_MODE: u8 = 0x40;
+pub const PRESCALER_256: u8 = 0x20;
+pub const RISING_EDGE: u8 = 0x10;
+pub const TRIGGER_START: u8 = 0x08;
+pub const CONSTANT_FOLLOWS: u8 = 0x04;
+pub const SOFTWARE_RESET: u8 = 0x02;
+pub const CONTROL_WORD: u8 = 0x01;
+
+#[derive(Clone, Copy)]
+pub struct CtcChannel {
+    pub control: u8,
+    pub time_constant: u8,
+    // Down-counter, 256 when loaded with 0
+    pub counter: u16,
+    // System clocks counted by the prescaler
+    pub prescaler: u16,
+    pub running: bool,
+    // The next byte written is the time constant
+    pub constant_next: bool,
+    // Level of CLK/TRG
+    pub trg: bool,
+    pub int_pending: bool,
+    pub in_service: bool,
+}
+
+impl Default for CtcChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CtcChannel {
+    pub fn new() -> Self {
+        Self {
+            control: 0x00,
+            time_constant: 0x00,
+            counter: 256,
+            prescaler: 0,
+            running: false,
+            constant_next: false,
+            trg: false,
+            int_pending: false,
+            in_service: false,
+        }
+    }
+
+    pub fn counter_mode(&self) -> bool {
+        self.control & COUNTER_MODE != 0
+    }
+
+    fn prescaler_period(&self) -> u16 {
+        match self.control & PRESCALER_256 {
+            0 => 16,
+            _ => 256,
+        }
+    }
+
+    fn reload(&mut self) {
+        self.counter = match self.time_constant {
+            0 => 256,
+            tc => tc as u16,
+        };
+    }
+
+    // Returns true at the zero count
+    fn count_down(&mut self) -> bool {
+        self.counter -= 1;
+        if self.counter > 0 {
+            return false;
+        }
+        self.reload();
+        if self.control & INT_ENABLE != 0 {
+            self.int_pending = true;
+        }
+        true
+    }
+}
+
+pub struct Z80Ctc {
+    pub channels: [CtcChannel; 4],
+    // Bits 7-3 of the vectors, bits 2-1 are the channel
+    pub vector: u8,
+    // Pulses given on the ZC/TO outputs of channels 0-2, channel 3 has none
+    pub zc_to: [u32; 3],
+    pub daisy: DaisyLink,
+}
+
+impl Default for Z80Ctc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Z80Ctc {
+    pub fn new() -> Self {
+        Self {
+            channels: [CtcChannel::new(); 4],
+            vector: 0x00,
+            zc_to: [0; 3],
+            daisy: DaisyLink::new(),
+        }
+    }
+
+    // /RESET: the channels stop and their interrupts are disabled
+    pub fn reset(&mut self) {
+        for channel in self.channels.iter_mut() {
+            channel.control &= !INT_ENABLE;
+            channel.running = false;
+            channel.constant_next = false;
+            channel.int_pending = false;
+            channel.in_service = false;
+        }
+    }
+
+    // Value of the down-counter
+    pub fn read(&self, channel: usize) -> u8 {
+        self.channels[channel & 0x03].counter as u8
+    }
+
+    pub fn write(&mut self, channel: usize, data: u8) {
+        let index = channel & 0x03;
+        let ch = &mut self.channels[index];
+        if ch.constant_next {
+            ch.constant_next = false;
+            ch.time_constant = data;
+            // A running channel takes the new constant at its next zero count
+            if !ch.running {
+                ch.reload();
+                ch.prescaler = 0;
+                ch.running = ch.counter_mode() || ch.control & TRIGGER_START == 0;
+            }
+        } else if data & CONTROL_WORD != 0 {
+            ch.control = data;
+            ch.constant_next = data & CONSTANT_FOLLOWS != 0;
+            if data & SOFTWARE_RESET != 0 {
+                ch.running = false;
+            }
+            if data & INT_ENABLE == 0 {
+                ch.int_pending = false;
+            }
+        } else if index == 0 {
+            self.vector = data & 0xF8;
+        }
+    }
+
+    // Runs the timers for `clocks` periods of the system clock
+    pub fn run(&mut self, clocks: u32) {
+        for index in 0..4 {
+            let ch = &mut self.channels[index];
+            if !ch.running || ch.counter_mode() {
+                continue;
+            }
+            let period = ch.prescaler_period() as u32;
+            let mut elapsed = ch.prescaler as u32 + clocks;
+            while elapsed >= period {
+                elapsed -= period;
+                if ch.count_down() && index < 3 {
+                    self.zc_to[index] += 1;
+                }
+            }
+            ch.prescaler = elapsed as u16;
+        }
+    }
+
+    // Level of the CLK/TRG input of a channel. Its active edge decrements the counter in
+    // counter mode, or starts the timer waiting for a trigger.
+    pub fn trigger(&mut self, channel: usize, level: bool) {
+        let index = channel & 0x03;
+        let ch = &mut self.channels[index];
+        let edge = ch.trg != level && level == (ch.control & RISING_EDGE != 0);
+        ch.trg = level;
+        if !edge || ch.constant_next {
+            return;
+        }
+        if ch.counter_mode() {
+            if ch.running && ch.count_down() && index < 3 {
+                self.zc_to[index] += 1;
+            }
+        } else if !ch.running && ch.control & TRIGGER_START != 0 {
+            ch.running = true;
+        }
+    }
+
+    // Interrupt sources of the daisy chain, channel 0 first
+    fn sources(&self) -> impl Iterator<Item = (bool, bool)> + '_ {
+        self.channels
+            .iter()
+            .map(|ch| (ch.int_pending, ch.in_service))
+    }
+
+    // /INT is pulled low by a pending channel, unless one of higher priority is under
+    // service
+    pub fn n_int(&self) -> bool {
+        self.daisy.n_int(self.sources())
+    }
+
+    // Interrupt acknowledge: the vector of the requesting channel, which goes under
+    // service until RETI
+    pub fn int_ack(&mut self) -> Option<u8> {
+        let index = self.daisy.int_ack(self.sources())?;
+        let ch = &mut self.channels[index];
+        ch.int_pending = false;
+        ch.in_service = true;
+        Some(self.vector | (index as u8) << 1)
+    }
+
+    // RETI, passed on by `Bus::reti()`, ends the service of the channel of highest
+    // priority
+    pub fn reti(&mut self) {
+        if let Some(ch) = self.channels.iter_mut().find(|ch| ch.in_service) {
+            ch.in_service = false;
+        }
+    }
+
+    pub fn ieo(&self) -> bool {
+        self.daisy.ieo(self.sources())
+    }
+}
+
+impl DaisyDevice for Z80Ctc {
+    fn set_iei(&mut self, iei: bool) {
+        self.daisy.iei = iei;
+    }
+
+    fn ieo(&self) -> bool {
+        Z80Ctc::ieo(self)
+    }
+
+    fn n_int(&self) -> bool {
+        Z80Ctc::n_int(self)
+    }
+
+    fn int_ack(&mut self) -> Option<u8> {
+        Z80Ctc::int_ack(self)
+    }
+
+    fn under_service(&self) -> bool {
+        self.channels.iter().any(|ch| ch.in_service)
+    }
+
+    fn reti(&mut self) {
+        Z80Ctc::reti(self)
+    }
+}
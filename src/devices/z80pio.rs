@@ -0,0 +1,331 @@
+// Zilog Z80 PIO parallel input/output: two 8 bit ports A and B, each in output, input,
+// bidirectional (port A only) or bit control mode, with RDY and /STB handshake lines and
+// IM 2 vectored interrupts. Port A has the highest priority.
+
+use crate::devices::daisy_chain::{DaisyDevice, DaisyLink};
+
+pub const PORT_A: usize = 0;
+pub const PORT_B: usize = 1;
+
+// Operating modes, bits 7-6 of the mode control word
+pub const MODE_OUTPUT: u8 = 0;
+pub const MODE_INPUT: u8 = 1;
+pub const MODE_BIDIRECTIONAL: u8 = 2;
+pub const MODE_BIT_CONTROL: u8 = 3;
+
+// Bits of the interrupt control word
+pub const INT_ENABLE: u8 = 0x80;
+pub const INT_AND: u8 = 0x40;
+pub const INT_HIGH: u8 = 0x20;
+pub const MASK_FOLLOWS: u8 = 0x10;
+
+// Meaning of the next byte written to the control port
+#[derive(Clone, Copy, PartialEq)]
+enum Expect {
+    Control,
+    IoMask,
+    IntMask,
+}
+
+#[derive(Clone, Copy)]
+pub struct PioPort {
+    pub mode: u8,
+    pub output: u8,
+    // Latched by /STB in input and bidirectional modes
+    pub input: u8,
+    // Level driven by the peripheral on the lines, read in bit control mode
+    pub lines: u8,
+    // Bit control mode: lines used as inputs
+    pub io_mask: u8,
+    pub vector: u8,
+    pub int_enable: bool,
+    // INT_AND and INT_HIGH of the interrupt control word
+    pub int_control: u8,
+    // Bit control mode: lines not monitored for the interrupt
+    pub int_mask: u8,
+    // RDY output: data available (output) or room for data (input)
+    pub rdy: bool,
+    // Bidirectional mode: RDY of the input handshake, on the lines of port B
+    pub input_rdy: bool,
+    pub int_pending: bool,
+    pub in_service: bool,
+    expect: Expect,
+    // Bit control mode: the condition is met, an interrupt is only given when it becomes so
+    matched: bool,
+}
+
+impl Default for PioPort {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PioPort {
+    pub fn new() -> Self {
+        Self {
+            mode: MODE_INPUT,
+            output: 0x00,
+            input: 0x00,
+            lines: 0xFF,
+            io_mask: 0xFF,
+            vector: 0x00,
+            int_enable: false,
+            int_control: 0x00,
+            int_mask: 0xFF,
+            rdy: false,
+            input_rdy: false,
+            int_pending: false,
+            in_service: false,
+            expect: Expect::Control,
+            matched: false,
+        }
+    }
+
+    fn request(&mut self) {
+        if self.int_enable {
+            self.int_pending = true;
+        }
+    }
+
+    // Bit control mode: true when the monitored input lines are all (AND) or any (OR) at
+    // their active level
+    fn condition(&self) -> bool {
+        let monitored = self.io_mask & !self.int_mask;
+        let active = match self.int_control & INT_HIGH {
+            0 => !self.lines & monitored,
+            _ => self.lines & monitored,
+        };
+        match self.int_control & INT_AND {
+            0 => active != 0,
+            _ => monitored != 0 && active == monitored,
+        }
+    }
+
+    fn check_condition(&mut self) {
+        let matched = self.mode == MODE_BIT_CONTROL && self.condition();
+        if matched && !self.matched {
+            self.request();
+        }
+        self.matched = matched;
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        match self.mode {
+            MODE_OUTPUT => self.output,
+            MODE_INPUT => {
+                self.rdy = true;
+                self.input
+            }
+            MODE_BIDIRECTIONAL => {
+                self.input_rdy = true;
+                self.input
+            }
+            _ => (self.lines & self.io_mask) | (self.output & !self.io_mask),
+        }
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        self.output = data;
+        if self.mode == MODE_OUTPUT || self.mode == MODE_BIDIRECTIONAL {
+            self.rdy = true;
+        }
+    }
+
+    pub fn write_control(&mut self, data: u8) {
+        match self.expect {
+            Expect::IoMask => {
+                self.io_mask = data;
+                self.expect = Expect::Control;
+            }
+            Expect::IntMask => {
+                self.int_mask = data;
+                self.expect = Expect::Control;
+            }
+            Expect::Control if data & 0x01 == 0 => self.vector = data,
+            Expect::Control => match data & 0x0F {
+                0x0F => {
+                    self.mode = data >> 6;
+                    self.rdy = self.mode == MODE_INPUT;
+                    self.input_rdy = self.mode == MODE_BIDIRECTIONAL;
+                    if self.mode == MODE_BIT_CONTROL {
+                        self.expect = Expect::IoMask;
+                    }
+                }
+                0x07 => {
+                    self.int_enable = data & INT_ENABLE != 0;
+                    self.int_control = data & (INT_AND | INT_HIGH);
+                    // A new control word clears the interrupt waiting
+                    self.int_pending = false;
+                    if data & MASK_FOLLOWS != 0 {
+                        self.expect = Expect::IntMask;
+                    }
+                }
+                0x03 => self.int_enable = data & INT_ENABLE != 0,
+                _ => {}
+            },
+        }
+        if !self.int_enable {
+            self.int_pending = false;
+        }
+        self.check_condition();
+    }
+
+    // Level of the lines driven by the port, the inputs are pulled high
+    pub fn output_lines(&self) -> u8 {
+        match self.mode {
+            MODE_OUTPUT | MODE_BIDIRECTIONAL => self.output,
+            MODE_INPUT => 0xFF,
+            _ => self.output | self.io_mask,
+        }
+    }
+}
+
+pub struct Z80Pio {
+    pub ports: [PioPort; 2],
+    pub daisy: DaisyLink,
+}
+
+impl Default for Z80Pio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Z80Pio {
+    pub fn new() -> Self {
+        Self {
+            ports: [PioPort::new(); 2],
+            daisy: DaisyLink::new(),
+        }
+    }
+
+    // /RESET (or M1 without /RD nor /IORQ): both ports in input mode, handshake and
+    // interrupts disabled
+    pub fn reset(&mut self) {
+        for port in self.ports.iter_mut() {
+            *port = PioPort {
+                vector: port.vector,
+                lines: port.lines,
+                ..PioPort::new()
+            };
+        }
+    }
+
+    // Bit 0 of `port` selects port B, bit 1 the control register, as when B/A and C/D
+    // are wired to A0 and A1
+    pub fn read(&mut self, port: u8) -> u8 {
+        match port & 0x02 {
+            0 => self.ports[port as usize & 0x01].read_data(),
+            // The control registers cannot be read back
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, port: u8, data: u8) {
+        let index = port as usize & 0x01;
+        match port & 0x02 {
+            0 => self.ports[index].write_data(data),
+            _ => self.ports[index].write_control(data),
+        }
+    }
+
+    // Lines driven by the peripheral on a port, checked in bit control mode
+    pub fn set_lines(&mut self, port: usize, lines: u8) {
+        let port = &mut self.ports[port];
+        port.lines = lines;
+        port.check_condition();
+    }
+
+    // Pulse on /STB by the peripheral: it took the data of an output, or gives the
+    // `data` of an input. /BSTB is the input strobe of port A in bidirectional mode.
+    pub fn strobe(&mut self, port: usize, data: u8) {
+        let a = &mut self.ports[PORT_A];
+        if port == PORT_B && a.mode == MODE_BIDIRECTIONAL {
+            a.input = data;
+            a.input_rdy = false;
+            a.request();
+            return;
+        }
+        let port = &mut self.ports[port];
+        match port.mode {
+            MODE_OUTPUT | MODE_BIDIRECTIONAL => {
+                port.rdy = false;
+                port.request();
+            }
+            MODE_INPUT => {
+                port.input = data;
+                port.rdy = false;
+                port.request();
+            }
+            _ => {}
+        }
+    }
+
+    // Level of the RDY output of a port. BRDY is the input handshake of port A in
+    // bidirectional mode.
+    pub fn rdy(&self, port: usize) -> bool {
+        let a = &self.ports[PORT_A];
+        match port == PORT_B && a.mode == MODE_BIDIRECTIONAL {
+            true => a.input_rdy,
+            false => self.ports[port].rdy,
+        }
+    }
+
+    // Interrupt sources of the daisy chain, port A first
+    fn sources(&self) -> impl Iterator<Item = (bool, bool)> + '_ {
+        self.ports
+            .iter()
+            .map(|port| (port.int_pending, port.in_service))
+    }
+
+    // /INT is pulled low by a pending port, unless port A is under service
+    pub fn n_int(&self) -> bool {
+        self.daisy.n_int(self.sources())
+    }
+
+    // Interrupt acknowledge: the vector of the requesting port, which goes under service
+    // until RETI
+    pub fn int_ack(&mut self) -> Option<u8> {
+        let port = &mut self.ports[self.daisy.int_ack(self.sources())?];
+        port.int_pending = false;
+        port.in_service = true;
+        Some(port.vector)
+    }
+
+    // RETI, passed on by `Bus::reti()`, ends the service of the port of highest priority
+    pub fn reti(&mut self) {
+        if let Some(port) = self.ports.iter_mut().find(|port| port.in_service) {
+            port.in_service = false;
+        }
+    }
+
+    pub fn ieo(&self) -> bool {
+        self.daisy.ieo(self.sources())
+    }
+}
+
+impl DaisyDevice for Z80Pio {
+    fn set_iei(&mut self, iei: bool) {
+        self.daisy.iei = iei;
+    }
+
+    fn ieo(&self) -> bool {
+        Z80Pio::ieo(self)
+    }
+
+    fn n_int(&self) -> bool {
+        Z80Pio::n_int(self)
+    }
+
+    fn int_ack(&mut self) -> Option<u8> {
+        Z80Pio::int_ack(self)
+    }
+
+    fn under_service(&self) -> bool {
+        self.ports.iter().any(|port| port.in_service)
+    }
+
+    fn reti(&mut self) {
+        Z80Pio::reti(self)
+    }
+}
@@ -0,0 +1,416 @@
+// Zilog Z80 SIO/2 serial input/output, and the Z80 DART which has the same asynchronous
+// channels without the synchronous modes. Each channel has write registers WR0-WR7, read
+// registers RR0-RR2, a 3 byte receive FIFO and a transmit buffer. Characters move at each
+// run(), the bit rate is not emulated. Interrupts are vectored for IM 2 in the order: A
+// receive, A transmit, A external/status, then the same for B.
+
+use crate::devices::daisy_chain::{DaisyDevice, DaisyLink};
+use crate::devices::serial::SerialLink;
+use std::collections::VecDeque;
+
+pub const CHANNEL_A: usize = 0;
+pub const CHANNEL_B: usize = 1;
+
+const FIFO_SIZE: usize = 3;
+
+// Commands of WR0, bits 5-3
+const RESET_EXT_STATUS: u8 = 2;
+const CHANNEL_RESET: u8 = 3;
+const ENABLE_INT_NEXT_RX: u8 = 4;
+const RESET_TX_INT: u8 = 5;
+const ERROR_RESET: u8 = 6;
+const RETURN_FROM_INT: u8 = 7;
+
+// WR1
+pub const EXT_INT_ENABLE: u8 = 0x01;
+pub const TX_INT_ENABLE: u8 = 0x02;
+pub const STATUS_AFFECTS_VECTOR: u8 = 0x04;
+pub const RX_INT_MASK: u8 = 0x18;
+pub const RX_INT_FIRST: u8 = 0x08;
+pub const RX_INT_ALL_PARITY: u8 = 0x10;
+pub const RX_INT_ALL: u8 = 0x18;
+// WR3
+pub const RX_ENABLE: u8 = 0x01;
+// WR5
+pub const TX_ENABLE: u8 = 0x08;
+pub const RTS: u8 = 0x02;
+pub const DTR: u8 = 0x80;
+
+// RR0
+pub const RX_AVAILABLE: u8 = 0x01;
+pub const INT_PENDING: u8 = 0x02;
+pub const TX_EMPTY: u8 = 0x04;
+pub const DCD: u8 = 0x08;
+pub const CTS: u8 = 0x20;
+// RR1
+pub const ALL_SENT: u8 = 0x01;
+pub const RX_OVERRUN: u8 = 0x20;
+
+// Interrupt sources of a channel, by priority
+const RX: usize = 0;
+const TX: usize = 1;
+const EXT: usize = 2;
+
+pub struct SioChannel {
+    pub wr: [u8; 8],
+    // Register addressed by the next control access
+    pub pointer: usize,
+    pub rx_fifo: VecDeque<u8>,
+    pub tx_buffer: Option<u8>,
+    // Errors of RR1, kept until an error reset
+    pub errors: u8,
+    // Inputs driven by the modem or the terminal
+    pub dcd: bool,
+    pub cts: bool,
+    // Bytes transmitted without a link connected
+    pub output: Vec<u8>,
+    pub link: Option<SerialLink>,
+    // Interrupt on the first character received, armed by a reset or a command
+    first_rx: bool,
+    rx_pending: bool,
+    tx_pending: bool,
+    ext_pending: bool,
+    // Sources under service until RETI
+    in_service: [bool; 3],
+}
+
+impl Default for SioChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SioChannel {
+    pub fn new() -> Self {
+        Self {
+            wr: [0x00; 8],
+            pointer: 0,
+            rx_fifo: VecDeque::with_capacity(FIFO_SIZE),
+            tx_buffer: None,
+            errors: 0x00,
+            dcd: true,
+            cts: true,
+            output: Vec::new(),
+            link: None,
+            first_rx: true,
+            rx_pending: false,
+            tx_pending: false,
+            ext_pending: false,
+            in_service: [false; 3],
+        }
+    }
+
+    // Channel reset command: the write registers are cleared, the line is idle
+    pub fn reset(&mut self) {
+        self.wr[1..6].fill(0x00);
+        self.pointer = 0;
+        self.rx_fifo.clear();
+        self.tx_buffer = None;
+        self.errors = 0x00;
+        self.first_rx = true;
+        self.rx_pending = false;
+        self.tx_pending = false;
+        self.ext_pending = false;
+        self.in_service = [false; 3];
+    }
+
+    fn rr0(&self) -> u8 {
+        let mut rr0 = 0x00;
+        if !self.rx_fifo.is_empty() {
+            rr0 |= RX_AVAILABLE;
+        }
+        if self.tx_buffer.is_none() {
+            rr0 |= TX_EMPTY;
+        }
+        if self.dcd {
+            rr0 |= DCD;
+        }
+        if self.cts {
+            rr0 |= CTS;
+        }
+        rr0
+    }
+
+    fn rr1(&self) -> u8 {
+        let sent = match self.tx_buffer {
+            None => ALL_SENT,
+            Some(_) => 0x00,
+        };
+        self.errors | sent
+    }
+
+    // A character arrives from the line. It is lost when the FIFO is full.
+    pub fn receive(&mut self, data: u8) {
+        if self.wr[3] & RX_ENABLE == 0 {
+            return;
+        }
+        if self.rx_fifo.len() == FIFO_SIZE {
+            // A special receive condition
+            self.errors |= RX_OVERRUN;
+            self.rx_pending = self.wr[1] & RX_INT_MASK != 0;
+            return;
+        }
+        self.rx_fifo.push_back(data);
+        match self.wr[1] & RX_INT_MASK {
+            RX_INT_FIRST if self.first_rx => {
+                self.first_rx = false;
+                self.rx_pending = true;
+            }
+            RX_INT_ALL_PARITY | RX_INT_ALL => self.rx_pending = true,
+            _ => {}
+        }
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        let data = self.rx_fifo.pop_front().unwrap_or(0xFF);
+        if self.rx_fifo.is_empty() || self.wr[1] & RX_INT_MASK == RX_INT_FIRST {
+            self.rx_pending = false;
+        }
+        data
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        self.tx_buffer = Some(data);
+        self.tx_pending = false;
+    }
+
+    fn write_wr0(&mut self, data: u8) {
+        self.pointer = (data & 0x07) as usize;
+        match (data >> 3) & 0x07 {
+            RESET_EXT_STATUS => self.ext_pending = false,
+            CHANNEL_RESET => self.reset(),
+            ENABLE_INT_NEXT_RX => self.first_rx = true,
+            RESET_TX_INT => self.tx_pending = false,
+            ERROR_RESET => {
+                self.errors = 0x00;
+                self.rx_pending = self.rx_pending && !self.rx_fifo.is_empty();
+            }
+            _ => {}
+        }
+    }
+
+    // Sends the transmit buffer and fills the FIFO from the link
+    fn run(&mut self) {
+        if self.wr[5] & TX_ENABLE != 0 && self.cts {
+            if let Some(data) = self.tx_buffer.take() {
+                match self.link.as_mut() {
+                    Some(link) => {
+                        if link.send(data).is_err() {
+                            link.closed = true;
+                        }
+                    }
+                    None => self.output.push(data),
+                }
+                self.tx_pending = true;
+            }
+        }
+        while self.rx_fifo.len() < FIFO_SIZE && self.wr[3] & RX_ENABLE != 0 {
+            match self.link.as_mut().and_then(|link| link.receive()) {
+                Some(data) => self.receive(data),
+                None => break,
+            }
+        }
+    }
+
+    // Interrupts requested by each source, as enabled in WR1
+    fn requests(&self) -> [bool; 3] {
+        let mut requests = [false; 3];
+        requests[RX] = self.rx_pending && self.wr[1] & RX_INT_MASK != 0;
+        requests[TX] = self.tx_pending && self.wr[1] & TX_INT_ENABLE != 0;
+        requests[EXT] = self.ext_pending && self.wr[1] & EXT_INT_ENABLE != 0;
+        requests
+    }
+}
+
+pub struct Z80Sio {
+    pub channels: [SioChannel; 2],
+    // DART: WR6 and WR7 (sync characters) are not there
+    pub dart: bool,
+    pub daisy: DaisyLink,
+}
+
+impl Default for Z80Sio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Z80Sio {
+    pub fn new() -> Self {
+        Self {
+            channels: [SioChannel::new(), SioChannel::new()],
+            dart: false,
+            daisy: DaisyLink::new(),
+        }
+    }
+
+    pub fn new_dart() -> Self {
+        Self {
+            dart: true,
+            ..Self::new()
+        }
+    }
+
+    // /RESET: both channels are reset and the vector cleared
+    pub fn reset(&mut self) {
+        for channel in self.channels.iter_mut() {
+            channel.reset();
+            channel.wr[2] = 0x00;
+        }
+    }
+
+    // Connects a channel to a host byte stream
+    pub fn connect(&mut self, channel: usize, link: SerialLink) {
+        self.channels[channel].link = Some(link);
+    }
+
+    pub fn disconnect(&mut self, channel: usize) -> Option<SerialLink> {
+        self.channels[channel].link.take()
+    }
+
+    // Bit 0 of `port` selects the data register, bit 1 channel B, as on the RC2014 SIO/2
+    // module (A control, A data, B control, B data)
+    pub fn read(&mut self, port: u8) -> u8 {
+        let index = (port as usize >> 1) & 0x01;
+        if port & 0x01 != 0 {
+            return self.channels[index].read_data();
+        }
+        let pointer = std::mem::take(&mut self.channels[index].pointer);
+        let channel = &self.channels[index];
+        match pointer {
+            0 if index == CHANNEL_A && self.pending().is_some() => channel.rr0() | INT_PENDING,
+            0 => channel.rr0(),
+            1 => channel.rr1(),
+            // The vector is read through channel B, as modified when status affects it
+            2 if index == CHANNEL_B => self.vector(),
+            _ => 0x00,
+        }
+    }
+
+    pub fn write(&mut self, port: u8, data: u8) {
+        let index = (port as usize >> 1) & 0x01;
+        let channel = &mut self.channels[index];
+        if port & 0x01 != 0 {
+            channel.write_data(data);
+            return;
+        }
+        match std::mem::take(&mut channel.pointer) {
+            0 => {
+                channel.write_wr0(data);
+                if (data >> 3) & 0x07 == RETURN_FROM_INT && index == CHANNEL_A {
+                    self.reti();
+                }
+            }
+            // The vector is shared, written through either channel
+            2 => self.channels.iter_mut().for_each(|ch| ch.wr[2] = data),
+            6 | 7 if self.dart => {}
+            register => channel.wr[register] = data,
+        }
+    }
+
+    // Level of the modem inputs of a channel. A change requests an external/status
+    // interrupt.
+    pub fn set_dcd(&mut self, channel: usize, level: bool) {
+        let channel = &mut self.channels[channel];
+        channel.ext_pending |= channel.dcd != level;
+        channel.dcd = level;
+    }
+
+    pub fn set_cts(&mut self, channel: usize, level: bool) {
+        let channel = &mut self.channels[channel];
+        channel.ext_pending |= channel.cts != level;
+        channel.cts = level;
+    }
+
+    // Moves the characters between the channels and their links
+    pub fn run(&mut self) {
+        self.channels.iter_mut().for_each(|channel| channel.run());
+    }
+
+    // Interrupt sources of the daisy chain, the three of channel A first
+    fn sources(&self) -> impl Iterator<Item = (bool, bool)> + '_ {
+        (0..6).map(|n| {
+            let channel = &self.channels[n / 3];
+            (channel.requests()[n % 3], channel.in_service[n % 3])
+        })
+    }
+
+    // Channel and source pending
+    fn pending(&self) -> Option<(usize, usize)> {
+        DaisyLink::pending(self.sources()).map(|n| (n / 3, n % 3))
+    }
+
+    // Vector of WR2, bits 3-1 replaced by the source requesting when WR1 of channel B
+    // has STATUS_AFFECTS_VECTOR: transmit 0, external/status 1, receive 2, special
+    // receive condition 3, plus 4 for channel A. They are 011 when nothing is requesting.
+    pub fn vector(&self) -> u8 {
+        let vector = self.channels[CHANNEL_B].wr[2];
+        if self.channels[CHANNEL_B].wr[1] & STATUS_AFFECTS_VECTOR == 0 {
+            return vector;
+        }
+        let status = match self.pending() {
+            Some((index, source)) => {
+                let code = match source {
+                    RX if self.channels[index].errors & RX_OVERRUN != 0 => 3,
+                    RX => 2,
+                    EXT => 1,
+                    _ => 0,
+                };
+                (1 - index as u8) << 2 | code
+            }
+            None => 3,
+        };
+        (vector & 0xF1) | status << 1
+    }
+
+    pub fn n_int(&self) -> bool {
+        self.daisy.n_int(self.sources())
+    }
+
+    // Interrupt acknowledge: the source requesting goes under service until RETI
+    pub fn int_ack(&mut self) -> Option<u8> {
+        let vector = self.vector();
+        let n = self.daisy.int_ack(self.sources())?;
+        self.channels[n / 3].in_service[n % 3] = true;
+        Some(vector)
+    }
+
+    // RETI, passed on by `Bus::reti()` or given to channel A, ends the service of the
+    // source of highest priority
+    pub fn reti(&mut self) {
+        let in_service = self.channels.iter_mut().flat_map(|ch| ch.in_service.iter_mut());
+        if let Some(source) = in_service.into_iter().find(|s| **s) {
+            *source = false;
+        }
+    }
+
+    pub fn ieo(&self) -> bool {
+        self.daisy.ieo(self.sources())
+    }
+}
+
+impl DaisyDevice for Z80Sio {
+    fn set_iei(&mut self, iei: bool) {
+        self.daisy.iei = iei;
+    }
+
+    fn ieo(&self) -> bool {
+        Z80Sio::ieo(self)
+    }
+
+    fn n_int(&self) -> bool {
+        Z80Sio::n_int(self)
+    }
+
+    fn int_ack(&mut self) -> Option<u8> {
+        Z80Sio::int_ack(self)
+    }
+
+    fn under_service(&self) -> bool {
+        self.channels.iter().any(|ch| ch.in_service.contains(&true))
+    }
+
+    fn reti(&mut self) {
+        Z80Sio::reti(self)
+    }
+}
@@ -1,10 +1,10 @@
-use crate::bus::{read_io, write_io};
-use crate::{cycles::CYCLES_ED, z80::*};
+use crate::cycles::CYCLES_ED;
+use crate::z80::*;
 
-impl Z80 {
+impl<B: Bus> Z80<B> {
     fn in_r_c(&mut self) -> u8 {
         let addr = self.reg.get_bc();
-        let data = read_io(addr);
+        let data = self.read_port(addr);
         self.reg.flags.s = data & 0x80 == 0x80;
         self.reg.flags.z = data == 0x00;
         self.reg.flags.h = false;
@@ -15,10 +15,11 @@ impl Z80 {
 
     fn out_c_r(&mut self, reg: u8) {
         let addr = self.reg.get_bc();
-        write_io(addr, reg);
+        self.write_port(addr, reg);
     }
 
     fn sbc_hl_rr(&mut self, reg: u16) -> u16 {
+        self.extend_m1(7);
         let c = self.reg.flags.c as u16;
         let hl = self.reg.get_hl();
         let r = hl.wrapping_sub(reg.wrapping_add(c));
@@ -32,6 +33,7 @@ impl Z80 {
     }
 
     fn adc_hl_rr(&mut self, reg: u16) -> u16 {
+        self.extend_m1(7);
         let c = self.reg.flags.c as u16;
         let hl = self.reg.get_hl();
         let r = hl.wrapping_add(reg).wrapping_add(c);
@@ -59,8 +61,9 @@ impl Z80 {
     fn ldi(&mut self) {
         let s = self.reg.get_hl();
         let d = self.reg.get_de();
-        let data = self.bus.read(s);
-        self.bus.write(d, data);
+        let data = self.read_mem(s);
+        self.write_mem(d, data);
+        self.record_internal(d, 2);
         self.reg.set_hl(s.wrapping_add(1));
         self.reg.set_de(d.wrapping_add(1));
         let bc = self.reg.get_bc();
@@ -75,8 +78,9 @@ impl Z80 {
     fn ldd(&mut self) {
         let s = self.reg.get_hl();
         let d = self.reg.get_de();
-        let data = self.bus.read(s);
-        self.bus.write(d, data);
+        let data = self.read_mem(s);
+        self.write_mem(d, data);
+        self.record_internal(d, 2);
         self.reg.set_hl(s.wrapping_sub(1));
         self.reg.set_de(d.wrapping_sub(1));
         let bc = self.reg.get_bc();
@@ -90,7 +94,8 @@ impl Z80 {
 
     fn cpi(&mut self) {
         let s = self.reg.get_hl();
-        let data = self.bus.read(s);
+        let data = self.read_mem(s);
+        self.record_internal(s, 5);
         let a = self.reg.a;
         let r = a.wrapping_sub(data);
         self.reg.set_hl(s.wrapping_add(1));
@@ -108,7 +113,8 @@ impl Z80 {
 
     fn cpd(&mut self) {
         let s = self.reg.get_hl();
-        let data = self.bus.read(s);
+        let data = self.read_mem(s);
+        self.record_internal(s, 5);
         let a = self.reg.a;
         let r = a.wrapping_sub(data);
         self.reg.set_hl(s.wrapping_sub(1));
@@ -125,10 +131,11 @@ impl Z80 {
     }
 
     fn ini(&mut self) {
+        self.extend_m1(1);
         let s = self.reg.get_bc();
-        let data = read_io(s);
+        let data = self.read_port(s);
         let d = self.reg.get_hl();
-        self.bus.write(d, data);
+        self.write_mem(d, data);
         self.reg.set_hl(d.wrapping_add(1));
         self.reg.b = self.dec_r(self.reg.b);
         self.reg.flags.n = data & 0x80 == 0x80;
@@ -139,10 +146,11 @@ impl Z80 {
     }
 
     fn ind(&mut self) {
+        self.extend_m1(1);
         let s = self.reg.get_bc();
-        let data = read_io(s);
+        let data = self.read_port(s);
         let d = self.reg.get_hl();
-        self.bus.write(d, data);
+        self.write_mem(d, data);
         self.reg.set_hl(d.wrapping_sub(1));
         self.reg.b = self.dec_r(self.reg.b);
         self.reg.flags.n = data & 0x80 == 0x80;
@@ -153,13 +161,14 @@ impl Z80 {
     }
 
     fn outi(&mut self) {
+        self.extend_m1(1);
         let s = self.reg.get_hl();
-        let data = self.bus.read(s);
+        let data = self.read_mem(s);
         self.reg.set_hl(s.wrapping_add(1));
         self.reg.b = self.dec_r(self.reg.b);
         self.reg.flags.n = data & 0x80 == 0x80;
         let d = self.reg.get_bc();
-        write_io(d, data);
+        self.write_port(d, data);
         let k = data as u16 + self.reg.l as u16;
         self.reg.flags.c = k > 0x00FF;
         self.reg.flags.h = self.reg.flags.c;
@@ -167,13 +176,14 @@ impl Z80 {
     }
 
     fn outd(&mut self) {
+        self.extend_m1(1);
         let s = self.reg.get_hl();
-        let data = self.bus.read(s);
+        let data = self.read_mem(s);
         self.reg.set_hl(s.wrapping_sub(1));
         self.reg.b = self.dec_r(self.reg.b);
         self.reg.flags.n = data & 0x80 == 0x80;
         let d = self.reg.get_bc();
-        write_io(d, data);
+        self.write_port(d, data);
         let k = data as u16 + self.reg.l as u16;
         self.reg.flags.c = k > 0x00FF;
         self.reg.flags.h = self.reg.flags.c;
@@ -190,7 +200,8 @@ impl Z80 {
     }
 
     fn rld(&mut self) {
-        let n = self.bus.read(self.reg.get_hl());
+        let n = self.read_mem(self.reg.get_hl());
+        self.record_internal(self.reg.get_hl(), 4);
         let a = self.reg.a;
         let tmp = a & 0x0F;
         let a = (a & 0xF0) | (n >> 4);
@@ -201,11 +212,12 @@ impl Z80 {
         self.reg.flags.p = a.count_ones() & 0x01 == 0;
         self.reg.flags.n = false;
         self.reg.a = a;
-        self.bus.write(self.reg.get_hl(), n);
+        self.write_mem(self.reg.get_hl(), n);
     }
 
     fn rrd(&mut self) {
-        let n = self.bus.read(self.reg.get_hl());
+        let n = self.read_mem(self.reg.get_hl());
+        self.record_internal(self.reg.get_hl(), 4);
         let a = self.reg.a;
         let tmp = a << 4;
         let a = (a & 0xF0) | (n & 0x0F);
@@ -216,12 +228,12 @@ impl Z80 {
         self.reg.flags.p = a.count_ones() & 0x01 == 0;
         self.reg.flags.n = false;
         self.reg.a = a;
-        self.bus.write(self.reg.get_hl(), n);
+        self.write_mem(self.reg.get_hl(), n);
     }
 
     pub fn ed_instructions(&mut self) -> u8 {
         self.reg.inc_pc();
-        let opcode = self.bus.read(self.reg.pc);
+        let opcode = self.fetch_opcode();
         let mut cycles = CYCLES_ED[opcode as usize];
 
         match opcode {
@@ -280,45 +292,45 @@ impl Z80 {
             // LD (nn), rr
             0x43 => {
                 let nn = self.get_nn();
-                self.bus.write(nn, self.reg.c);
-                self.bus.write(nn.wrapping_add(1), self.reg.b);
+                self.write_mem(nn, self.reg.c);
+                self.write_mem(nn.wrapping_add(1), self.reg.b);
             }
             0x53 => {
                 let nn = self.get_nn();
-                self.bus.write(nn, self.reg.e);
-                self.bus.write(nn.wrapping_add(1), self.reg.d);
+                self.write_mem(nn, self.reg.e);
+                self.write_mem(nn.wrapping_add(1), self.reg.d);
             }
             0x63 => {
                 let nn = self.get_nn();
-                self.bus.write(nn, self.reg.l);
-                self.bus.write(nn.wrapping_add(1), self.reg.h);
+                self.write_mem(nn, self.reg.l);
+                self.write_mem(nn.wrapping_add(1), self.reg.h);
             }
             0x73 => {
                 let nn = self.get_nn();
                 let [spl, sph] = self.reg.sp.to_le_bytes();
-                self.bus.write(nn, spl);
-                self.bus.write(nn.wrapping_add(1), sph);
+                self.write_mem(nn, spl);
+                self.write_mem(nn.wrapping_add(1), sph);
             }
             // LD rr, (nn)
             0x4B => {
                 let nn = self.get_nn();
-                self.reg.c = self.bus.read(nn);
-                self.reg.b = self.bus.read(nn.wrapping_add(1));
+                self.reg.c = self.read_mem(nn);
+                self.reg.b = self.read_mem(nn.wrapping_add(1));
             }
             0x5B => {
                 let nn = self.get_nn();
-                self.reg.e = self.bus.read(nn);
-                self.reg.d = self.bus.read(nn.wrapping_add(1));
+                self.reg.e = self.read_mem(nn);
+                self.reg.d = self.read_mem(nn.wrapping_add(1));
             }
             0x6B => {
                 let nn = self.get_nn();
-                self.reg.l = self.bus.read(nn);
-                self.reg.h = self.bus.read(nn.wrapping_add(1));
+                self.reg.l = self.read_mem(nn);
+                self.reg.h = self.read_mem(nn.wrapping_add(1));
             }
             0x7B => {
                 let nn = self.get_nn();
-                let spl = self.bus.read(nn);
-                let sph = self.bus.read(nn.wrapping_add(1));
+                let spl = self.read_mem(nn);
+                let sph = self.read_mem(nn.wrapping_add(1));
                 self.reg.sp = u16::from_le_bytes([spl, sph]);
             }
             0x44 | 0x4C | 0x54 | 0x5C | 0x64 | 0x6C | 0x74 | 0x7C => self.neg(),
@@ -327,15 +339,28 @@ impl Z80 {
             0x56 | 0x76 => self.im = InterruptMode::IM_1,
             0x5E | 0x7E => self.im = InterruptMode::IM_2,
             // LD I,A ; LD A,I ; LD R,A ; LD A,R
-            0x47 => self.reg.i = self.reg.a,
-            0x57 => self.ld_a_ri(self.reg.i),
-            0x4F => self.reg.r = self.reg.a,
-            0x5F => self.ld_a_ri(self.reg.r),
+            0x47 => {
+                self.extend_m1(1);
+                self.reg.i = self.reg.a;
+            }
+            0x57 => {
+                self.extend_m1(1);
+                self.ld_a_ri(self.reg.i);
+            }
+            0x4F => {
+                self.extend_m1(1);
+                self.reg.r = self.reg.a;
+            }
+            0x5F => {
+                self.extend_m1(1);
+                self.ld_a_ri(self.reg.r);
+            }
             // LDI ; LDIR
             0xA0 => self.ldi(),
             0xB0 => {
                 self.ldi();
                 if self.reg.flags.p {
+                    self.record_internal(self.reg.get_de().wrapping_sub(1), 5);
                     self.reg.pc = self.reg.pc.wrapping_sub(2);
                     cycles += 5;
                 }
@@ -345,6 +370,7 @@ impl Z80 {
             0xB8 => {
                 self.ldd();
                 if self.reg.flags.p {
+                    self.record_internal(self.reg.get_de().wrapping_add(1), 5);
                     self.reg.pc = self.reg.pc.wrapping_sub(2);
                     cycles += 5;
                 }
@@ -354,6 +380,7 @@ impl Z80 {
             0xB1 => {
                 self.cpi();
                 if self.reg.flags.p {
+                    self.record_internal(self.reg.get_hl().wrapping_sub(1), 5);
                     self.reg.pc = self.reg.pc.wrapping_sub(2);
                     cycles += 5;
                 }
@@ -363,6 +390,7 @@ impl Z80 {
             0xB9 => {
                 self.cpd();
                 if self.reg.flags.p {
+                    self.record_internal(self.reg.get_hl().wrapping_add(1), 5);
                     self.reg.pc = self.reg.pc.wrapping_sub(2);
                     cycles += 5;
                 }
@@ -372,6 +400,7 @@ impl Z80 {
             0xB2 => {
                 self.ini();
                 if !self.reg.flags.z {
+                    self.record_internal(self.reg.get_hl().wrapping_sub(1), 5);
                     self.reg.pc = self.reg.pc.wrapping_sub(2);
                     cycles += 5;
                 }
@@ -381,6 +410,7 @@ impl Z80 {
             0xBA => {
                 self.ind();
                 if !self.reg.flags.z {
+                    self.record_internal(self.reg.get_hl().wrapping_add(1), 5);
                     self.reg.pc = self.reg.pc.wrapping_sub(2);
                     cycles += 5;
                 }
@@ -390,6 +420,7 @@ impl Z80 {
             0xB3 => {
                 self.outi();
                 if !self.reg.flags.z {
+                    self.record_internal(self.reg.get_bc(), 5);
                     self.reg.pc = self.reg.pc.wrapping_sub(2);
                     cycles += 5;
                 }
@@ -399,6 +430,7 @@ impl Z80 {
             0xBB => {
                 self.outd();
                 if !self.reg.flags.z {
+                    self.record_internal(self.reg.get_bc(), 5);
                     self.reg.pc = self.reg.pc.wrapping_sub(2);
                     cycles += 5;
                 }
@@ -412,6 +444,10 @@ impl Z80 {
             0x4D => {
                 self.iff1 = self.iff2;
                 self.ret();
+                // tick() tells the bus when the 4D opcode is latched
+                if !self.deferred {
+                    self.bus.reti();
+                }
             }
 
             // RRD and RLD
@@ -1,22 +1,21 @@
-use crate::bus::{read_io, write_io};
 use crate::cycles::{CYCLES, CYCLES_DD_FD};
 use crate::z80::*;
 use std::io::{self, Write};
 
+#[allow(clippy::upper_case_acronyms)]
 enum BitOp {
     AND,
     XOR,
     OR,
 }
 
-impl Z80 {
+impl<B: Bus> Z80<B> {
     pub fn get_nn(&mut self) -> u16 {
         self.reg.inc_pc();
-        let nl = self.bus.read(self.reg.pc);
+        let nl = self.read_mem(self.reg.pc);
         self.reg.inc_pc();
-        let nh = self.bus.read(self.reg.pc);
-        let nn = u16::from_le_bytes([nl, nh]);
-        nn
+        let nh = self.read_mem(self.reg.pc);
+        u16::from_le_bytes([nl, nh])
     }
 
     fn jp_nn(&mut self) {
@@ -27,14 +26,45 @@ impl Z80 {
 
     fn jr_e(&mut self) {
         self.reg.inc_pc();
-        let e = self.bus.read(self.reg.pc);
+        let e = self.read_mem(self.reg.pc);
+        self.record_internal(self.reg.pc, 5);
         self.reg.pc = self.reg.pc.wrapping_add((e as i8) as u16);
     }
 
+    // The operand is read whether the jump is taken or not
+    fn jp_cc(&mut self, condition: bool) {
+        let nn = self.get_nn();
+        if condition {
+            self.reg.pc = nn.wrapping_sub(1);
+        }
+    }
+
+    // Returns the extra T-states when the condition is met
+    fn jr_cc(&mut self, condition: bool) -> u8 {
+        if condition {
+            self.jr_e();
+            5
+        } else {
+            self.reg.inc_pc();
+            _ = self.read_mem(self.reg.pc);
+            0
+        }
+    }
+
+    // Returns the extra T-states when the condition is met
+    fn call_cc(&mut self, condition: bool) -> u8 {
+        if condition {
+            self.call_nn();
+            7
+        } else {
+            _ = self.get_nn();
+            0
+        }
+    }
+
     fn call_nn(&mut self) {
-        let addrl = self.bus.read(self.reg.pc + 1);
-        let addrh = self.bus.read(self.reg.pc + 2);
-        let addr = u16::from_le_bytes([addrl, addrh]);
+        let addr = self.get_nn();
+        self.record_internal(self.reg.pc, 1);
         if addr == 0x0005 {
             let f = self.reg.c;
             if f == 0x02 {
@@ -51,40 +81,46 @@ impl Z80 {
                 }
             }
             io::stdout().flush().unwrap();
-            self.reg.inc_pc();
-            self.reg.inc_pc();
+            // In the time of the pushes
+            self.record_internal(self.reg.pc, 6);
             return;
         }
-        // PC is first incremented by 3 to resume the flow after this 3-byte instruction
-        let pc = self.reg.pc.wrapping_add(3);
-        let [mut pcl, mut pch] = pc.to_le_bytes();
+        // PC points to the last byte of this 3-byte instruction, resume the flow after it
+        let [pcl, pch] = self.reg.pc.wrapping_add(1).to_le_bytes();
         self.reg.dec_sp();
-        self.bus.write(self.reg.sp, pch);
+        self.write_mem(self.reg.sp, pch);
         self.reg.dec_sp();
-        self.bus.write(self.reg.sp, pcl);
-        self.reg.inc_pc();
-        pcl = self.bus.read(self.reg.pc);
-        self.reg.inc_pc();
-        pch = self.bus.read(self.reg.pc);
-        self.reg.pc = u16::from_le_bytes([pcl, pch]);
-        self.reg.dec_pc();
+        self.write_mem(self.reg.sp, pcl);
+        self.reg.pc = addr.wrapping_sub(1);
     }
 
     pub fn ret(&mut self) {
-        let pcl = self.bus.read(self.reg.sp);
+        let pcl = self.read_mem(self.reg.sp);
         self.reg.inc_sp();
-        let pch = self.bus.read(self.reg.sp);
+        let pch = self.read_mem(self.reg.sp);
         self.reg.inc_sp();
         self.reg.pc = u16::from_le_bytes([pcl, pch]);
         self.reg.dec_pc();
     }
 
+    // Returns the extra T-states when the condition is met
+    fn ret_cc(&mut self, condition: bool) -> u8 {
+        self.extend_m1(1);
+        if condition {
+            self.ret();
+            6
+        } else {
+            0
+        }
+    }
+
     fn rst(&mut self, addr: u8) {
+        self.extend_m1(1);
         let [pcl, pch] = self.reg.pc.to_be_bytes();
         self.reg.dec_sp();
-        self.bus.write(self.reg.sp, pch);
+        self.write_mem(self.reg.sp, pch);
         self.reg.dec_sp();
-        self.bus.write(self.reg.sp, pcl);
+        self.write_mem(self.reg.sp, pcl);
         self.reg.pc = u16::from_le_bytes([addr, 0x00]);
         self.reg.dec_pc();
     }
@@ -201,6 +237,7 @@ impl Z80 {
     }
 
     fn add_hl_ix_iy_rr(&mut self, reg: u16) {
+        self.extend_m1(7);
         let hl = match self.p_inst {
             0xDD => self.reg.get_ix(),
             0xFD => self.reg.get_iy(),
@@ -312,53 +349,94 @@ impl Z80 {
         };
     }
 
-    pub fn read_hl_ix_iy(&mut self) -> u8 {
+    // Address of the (HL) operand. The displacement of (IX+d) (IY+d) is read then added
+    // during 5 internal T-states.
+    fn addr_hl_ix_iy(&mut self) -> u16 {
         match self.p_inst {
             0xDD => {
                 self.reg.inc_pc();
-                let d = self.bus.read(self.reg.pc);
+                let d = self.read_mem(self.reg.pc);
+                self.record_internal(self.reg.pc, 5);
                 let ix = self.reg.get_ix();
-                let addr = ix.wrapping_add((d as i8) as u16);
-                self.bus.read(addr)
+                ix.wrapping_add((d as i8) as u16)
             }
             0xFD => {
                 self.reg.inc_pc();
-                let d = self.bus.read(self.reg.pc);
+                let d = self.read_mem(self.reg.pc);
+                self.record_internal(self.reg.pc, 5);
                 let iy = self.reg.get_iy();
-                let addr = iy.wrapping_add((d as i8) as u16);
-                self.bus.read(addr)
+                iy.wrapping_add((d as i8) as u16)
             }
-            _ => self.bus.read(self.reg.get_hl()),
+            _ => self.reg.get_hl(),
         }
     }
 
+    pub fn read_hl_ix_iy(&mut self) -> u8 {
+        let addr = self.addr_hl_ix_iy();
+        self.read_mem(addr)
+    }
+
     fn write_hl_ix_iy(&mut self, reg: u8) {
-        match self.p_inst {
-            0xDD => {
-                self.reg.inc_pc();
-                let d = self.bus.read(self.reg.pc);
-                let ix = self.reg.get_ix();
-                let addr = ix.wrapping_add((d as i8) as u16);
-                self.bus.write(addr, reg);
-            }
-            0xFD => {
-                self.reg.inc_pc();
-                let d = self.bus.read(self.reg.pc);
-                let iy = self.reg.get_iy();
-                let addr = iy.wrapping_add((d as i8) as u16);
-                self.bus.write(addr, reg);
+        let addr = self.addr_hl_ix_iy();
+        self.write_mem(addr, reg);
+    }
+
+    // INC (HL) and DEC (HL): the result is computed during an internal T-state
+    fn modify_hl_ix_iy(&mut self, op: fn(&mut Self, u8) -> u8) {
+        let addr = self.addr_hl_ix_iy();
+        let data = self.read_mem(addr);
+        self.record_internal(addr, 1);
+        let r = op(self, data);
+        self.write_mem(addr, r);
+    }
+
+    // Main function to run the CPU's instructions
+    // Returns the number of T-states used, wait states included
+    pub fn execute(&mut self) -> u32 {
+        if self.hold_in_reset() {
+            return 1;
+        }
+        self.m_cycles.clear();
+        self.t_offset = 0;
+        // BUSRQ is honoured at the end of the last machine cycle of the previous instruction,
+        // tick() has already sampled it
+        if !self.deferred {
+            let released = self.bus_release_cycles();
+            if released > 0 {
+                return released as u32;
             }
-            _ => self.bus.write(self.reg.get_hl(), reg),
         }
+        let cycles = match self.accept_interrupt() {
+            Some(cycles) => cycles,
+            None if self.trap() => return 0,
+            None => self.execute_opcode(),
+        };
+        let wait: u32 = self.m_cycles.iter().map(|m| m.wait).sum();
+        let cycles = cycles as u32 + wait;
+        if !self.deferred {
+            self.clock += cycles as u64;
+        }
+        cycles
     }
 
-    // Main function to run the CPU's instructions
-    pub fn execute(&mut self) -> u8 {
-        let instr = self.bus.read(self.reg.pc);
-        let mut cycles = CYCLES[instr as usize];
+    // True when PC reaches a trap, unless the machine let the instruction run. Traps
+    // are not checked between a DD/FD prefix and its opcode, nor by tick().
+    fn trap(&mut self) -> bool {
+        let pc = self.reg.pc;
+        let resumed = self.trapped.take() == Some(pc);
+        if resumed || self.deferred || self.p_inst == 0xDD || self.p_inst == 0xFD {
+            return false;
+        }
+        if self.traps.contains(&pc) {
+            self.trapped = Some(pc);
+            return true;
+        }
+        false
+    }
 
-        // Increment R register at each instruction
-        self.reg.inc_r();
+    fn execute_opcode(&mut self) -> u8 {
+        let instr = self.fetch_opcode();
+        let mut cycles = CYCLES[instr as usize];
 
         match instr {
             // NOP
@@ -440,67 +518,80 @@ impl Z80 {
             // LD r, n
             0x06 => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.reg.b = n;
             }
             0x16 => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.reg.d = n;
             }
             0x26 => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.set_h_ixh_iyh(n);
             }
             0x36 => {
-                let n = if self.p_inst == 0xDD || self.p_inst == 0xFD {
-                    // LD (IX+d IY+d), n -> d is first byte, n is second byte (xxyyddnn)
-                    self.bus.read(self.reg.pc.wrapping_add(2))
-                } else {
-                    // LD (HL), n -> n is first byte (xxyynn)
-                    self.bus.read(self.reg.pc.wrapping_add(1))
+                // LD (IX+d IY+d), n -> d is first byte, n is second byte (xxyyddnn)
+                // LD (HL), n -> n is first byte (xxyynn)
+                let addr = match self.p_inst {
+                    0xDD => {
+                        self.reg.inc_pc();
+                        let d = self.read_mem(self.reg.pc);
+                        self.reg.get_ix().wrapping_add((d as i8) as u16)
+                    }
+                    0xFD => {
+                        self.reg.inc_pc();
+                        let d = self.read_mem(self.reg.pc);
+                        self.reg.get_iy().wrapping_add((d as i8) as u16)
+                    }
+                    _ => self.reg.get_hl(),
                 };
-                self.write_hl_ix_iy(n);
                 self.reg.inc_pc();
+                let n = self.read_mem(self.reg.pc);
+                if self.p_inst == 0xDD || self.p_inst == 0xFD {
+                    // The displacement is added while n is read
+                    self.record_internal(self.reg.pc, 2);
+                }
+                self.write_mem(addr, n);
             }
             0x0E => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.reg.c = n;
             }
             0x1E => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.reg.e = n;
             }
             0x2E => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.set_l_ixl_iyl(n);
             }
             0x3E => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.reg.a = n;
             }
             // LD (BC), A
-            0x02 => self.bus.write(self.reg.get_bc(), self.reg.a),
+            0x02 => self.write_mem(self.reg.get_bc(), self.reg.a),
             // LD (DE), A
-            0x12 => self.bus.write(self.reg.get_de(), self.reg.a),
+            0x12 => self.write_mem(self.reg.get_de(), self.reg.a),
             // LD (nn), A
             0x32 => {
                 let nn = self.get_nn();
-                self.bus.write(nn, self.reg.a);
+                self.write_mem(nn, self.reg.a);
             }
             // LD A, (BC)
-            0x0A => self.reg.a = self.bus.read(self.reg.get_bc()),
+            0x0A => self.reg.a = self.read_mem(self.reg.get_bc()),
             // LD A, (DE)
-            0x1A => self.reg.a = self.bus.read(self.reg.get_de()),
+            0x1A => self.reg.a = self.read_mem(self.reg.get_de()),
             // LD A, (nn)
             0x3A => {
                 let nn = self.get_nn();
-                self.reg.a = self.bus.read(nn);
+                self.reg.a = self.read_mem(nn);
             }
 
             // 16-bit Load Group
@@ -527,73 +618,82 @@ impl Z80 {
             // LD HL, (nn)
             0x2A => {
                 let nn = self.get_nn();
-                let l = self.bus.read(nn);
-                let h = self.bus.read(nn.wrapping_add(1));
+                let l = self.read_mem(nn);
+                let h = self.read_mem(nn.wrapping_add(1));
                 self.set_hl_ix_iy(u16::from_le_bytes([l, h]));
             }
             // LD (nn), HL
             0x22 => {
                 let nn = self.get_nn();
-                self.bus.write(nn, self.get_l_ixl_iyl());
-                self.bus.write(nn.wrapping_add(1), self.get_h_ixh_iyh());
+                self.write_mem(nn, self.get_l_ixl_iyl());
+                self.write_mem(nn.wrapping_add(1), self.get_h_ixh_iyh());
             }
             // LD SP, HL
-            0xF9 => self.reg.sp = u16::from_le_bytes([self.get_l_ixl_iyl(), self.get_h_ixh_iyh()]),
+            0xF9 => {
+                self.extend_m1(2);
+                self.reg.sp = u16::from_le_bytes([self.get_l_ixl_iyl(), self.get_h_ixh_iyh()]);
+            }
             // PUSH BC
             0xC5 => {
+                self.extend_m1(1);
                 self.reg.dec_sp();
-                self.bus.write(self.reg.sp, self.reg.b);
+                self.write_mem(self.reg.sp, self.reg.b);
                 self.reg.dec_sp();
-                self.bus.write(self.reg.sp, self.reg.c);
+                self.write_mem(self.reg.sp, self.reg.c);
             }
             // PUSH DE
             0xD5 => {
+                self.extend_m1(1);
                 self.reg.dec_sp();
-                self.bus.write(self.reg.sp, self.reg.d);
+                self.write_mem(self.reg.sp, self.reg.d);
                 self.reg.dec_sp();
-                self.bus.write(self.reg.sp, self.reg.e);
+                self.write_mem(self.reg.sp, self.reg.e);
             }
             // PUSH HL IX IY
             0xE5 => {
+                self.extend_m1(1);
                 self.reg.dec_sp();
-                self.bus.write(self.reg.sp, self.get_h_ixh_iyh());
+                self.write_mem(self.reg.sp, self.get_h_ixh_iyh());
                 self.reg.dec_sp();
-                self.bus.write(self.reg.sp, self.get_l_ixl_iyl());
+                self.write_mem(self.reg.sp, self.get_l_ixl_iyl());
             }
             // PUSH AF
             0xF5 => {
+                self.extend_m1(1);
                 self.reg.dec_sp();
-                self.bus.write(self.reg.sp, self.reg.a);
+                self.write_mem(self.reg.sp, self.reg.a);
                 self.reg.dec_sp();
-                self.bus.write(self.reg.sp, self.reg.flags.to_byte());
+                self.write_mem(self.reg.sp, self.reg.flags.to_byte());
             }
             // POP BC
             0xC1 => {
-                self.reg.c = self.bus.read(self.reg.sp);
+                self.reg.c = self.read_mem(self.reg.sp);
                 self.reg.inc_sp();
-                self.reg.b = self.bus.read(self.reg.sp);
+                self.reg.b = self.read_mem(self.reg.sp);
                 self.reg.inc_sp();
             }
             // POP DE
             0xD1 => {
-                self.reg.e = self.bus.read(self.reg.sp);
+                self.reg.e = self.read_mem(self.reg.sp);
                 self.reg.inc_sp();
-                self.reg.d = self.bus.read(self.reg.sp);
+                self.reg.d = self.read_mem(self.reg.sp);
                 self.reg.inc_sp();
             }
             // POP HL IX IY
             0xE1 => {
-                self.set_l_ixl_iyl(self.bus.read(self.reg.sp));
+                let l = self.read_mem(self.reg.sp);
+                self.set_l_ixl_iyl(l);
                 self.reg.inc_sp();
-                self.set_h_ixh_iyh(self.bus.read(self.reg.sp));
+                let h = self.read_mem(self.reg.sp);
+                self.set_h_ixh_iyh(h);
                 self.reg.inc_sp();
             }
             // POP AF
             0xF1 => {
-                let f = self.bus.read(self.reg.sp);
+                let f = self.read_mem(self.reg.sp);
                 self.reg.flags.from_byte(f);
                 self.reg.inc_sp();
-                self.reg.a = self.bus.read(self.reg.sp);
+                self.reg.a = self.read_mem(self.reg.sp);
                 self.reg.inc_sp();
             }
             // Exchange
@@ -625,126 +725,52 @@ impl Z80 {
             }
             // EX (SP), HL IX IY
             0xE3 => {
-                let n = self.bus.read(self.reg.sp);
-                self.bus.write(self.reg.sp, self.get_l_ixl_iyl());
-                self.set_l_ixl_iyl(n);
-                self.reg.inc_sp();
-                let n = self.bus.read(self.reg.sp);
-                self.bus.write(self.reg.sp, self.get_h_ixh_iyh());
-                self.set_h_ixh_iyh(n);
+                let sp = self.reg.sp;
+                let l = self.read_mem(sp);
+                let h = self.read_mem(sp.wrapping_add(1));
+                self.record_internal(sp.wrapping_add(1), 1);
+                self.write_mem(sp.wrapping_add(1), self.get_h_ixh_iyh());
+                self.write_mem(sp, self.get_l_ixl_iyl());
+                self.record_internal(sp, 2);
+                self.set_l_ixl_iyl(l);
+                self.set_h_ixh_iyh(h);
             }
 
             // Jump group
             // JP nn
             0xC3 => self.jp_nn(),
             // JP nz, nn
-            0xC2 => {
-                if !self.reg.flags.z {
-                    self.jp_nn();
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xC2 => self.jp_cc(!self.reg.flags.z),
             // JP z, nn
-            0xCA => {
-                if self.reg.flags.z {
-                    self.jp_nn();
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xCA => self.jp_cc(self.reg.flags.z),
             // JP nc, nn
-            0xD2 => {
-                if !self.reg.flags.c {
-                    self.jp_nn();
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xD2 => self.jp_cc(!self.reg.flags.c),
             // JP c, nn
-            0xDA => {
-                if self.reg.flags.c {
-                    self.jp_nn();
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xDA => self.jp_cc(self.reg.flags.c),
             // JP po, nn
-            0xE2 => {
-                if !self.reg.flags.p {
-                    self.jp_nn();
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xE2 => self.jp_cc(!self.reg.flags.p),
             // JP pe, nn
-            0xEA => {
-                if self.reg.flags.p {
-                    self.jp_nn();
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xEA => self.jp_cc(self.reg.flags.p),
             // JP p, nn
-            0xF2 => {
-                if !self.reg.flags.s {
-                    self.jp_nn();
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xF2 => self.jp_cc(!self.reg.flags.s),
             // JP m, nn
-            0xFA => {
-                if self.reg.flags.s {
-                    self.jp_nn();
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xFA => self.jp_cc(self.reg.flags.s),
             // JR e
             0x18 => self.jr_e(),
             // JR z, e
-            0x28 => {
-                if self.reg.flags.z {
-                    self.jr_e();
-                } else {
-                    self.reg.inc_pc();
-                }
-            }
+            0x28 => cycles += self.jr_cc(self.reg.flags.z),
             // JR c, e
-            0x38 => {
-                if self.reg.flags.c {
-                    self.jr_e();
-                } else {
-                    self.reg.inc_pc();
-                }
-            }
+            0x38 => cycles += self.jr_cc(self.reg.flags.c),
             // DJNZ e
             0x10 => {
+                self.extend_m1(1);
                 self.reg.b = self.reg.b.wrapping_sub(1);
-                if self.reg.b != 0 {
-                    self.jr_e();
-                    cycles += 5;
-                } else {
-                    self.reg.inc_pc();
-                }
+                cycles += self.jr_cc(self.reg.b != 0);
             }
             // JR nz, e
-            0x20 => {
-                if !self.reg.flags.z {
-                    self.jr_e();
-                } else {
-                    self.reg.inc_pc();
-                }
-            }
+            0x20 => cycles += self.jr_cc(!self.reg.flags.z),
             // JR nc, nn
-            0x30 => {
-                if !self.reg.flags.c {
-                    self.jr_e();
-                } else {
-                    self.reg.inc_pc();
-                }
-            }
+            0x30 => cycles += self.jr_cc(!self.reg.flags.c),
             // JP (HL)
             0xE9 => {
                 self.reg.pc =
@@ -755,135 +781,39 @@ impl Z80 {
             // CALL nn
             0xCD => self.call_nn(),
             // CALL nz, nn
-            0xC4 => {
-                if !self.reg.flags.z {
-                    self.call_nn();
-                    cycles += 7;
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xC4 => cycles += self.call_cc(!self.reg.flags.z),
             // CALL nc, nn
-            0xD4 => {
-                if !self.reg.flags.c {
-                    self.call_nn();
-                    cycles += 7;
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xD4 => cycles += self.call_cc(!self.reg.flags.c),
             // CALL po, nn
-            0xE4 => {
-                if !self.reg.flags.p {
-                    self.call_nn();
-                    cycles += 7;
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xE4 => cycles += self.call_cc(!self.reg.flags.p),
             // CALL p, nn
-            0xF4 => {
-                if !self.reg.flags.s {
-                    self.call_nn();
-                    cycles += 7;
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xF4 => cycles += self.call_cc(!self.reg.flags.s),
             // CALL z, nn
-            0xCC => {
-                if self.reg.flags.z {
-                    self.call_nn();
-                    cycles += 7;
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xCC => cycles += self.call_cc(self.reg.flags.z),
             // CALL c, nn
-            0xDC => {
-                if self.reg.flags.c {
-                    self.call_nn();
-                    cycles += 7;
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xDC => cycles += self.call_cc(self.reg.flags.c),
             // CALL pe, nn
-            0xEC => {
-                if self.reg.flags.p {
-                    self.call_nn();
-                    cycles += 7;
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xEC => cycles += self.call_cc(self.reg.flags.p),
             // CALL m, nn
-            0xFC => {
-                if self.reg.flags.s {
-                    self.call_nn();
-                    cycles += 7;
-                } else {
-                    self.reg.pc = self.reg.pc.wrapping_add(2);
-                }
-            }
+            0xFC => cycles += self.call_cc(self.reg.flags.s),
             // RET
             0xC9 => self.ret(),
             // RET nz
-            0xC0 => {
-                if !self.reg.flags.z {
-                    self.ret();
-                    cycles = cycles.wrapping_add(6);
-                }
-            }
+            0xC0 => cycles = cycles.wrapping_add(self.ret_cc(!self.reg.flags.z)),
             // RET nc
-            0xD0 => {
-                if !self.reg.flags.c {
-                    self.ret();
-                    cycles = cycles.wrapping_add(6);
-                }
-            }
+            0xD0 => cycles = cycles.wrapping_add(self.ret_cc(!self.reg.flags.c)),
             // RET po
-            0xE0 => {
-                if !self.reg.flags.p {
-                    self.ret();
-                    cycles = cycles.wrapping_add(6);
-                }
-            }
+            0xE0 => cycles = cycles.wrapping_add(self.ret_cc(!self.reg.flags.p)),
             // RET p
-            0xF0 => {
-                if !self.reg.flags.s {
-                    self.ret();
-                    cycles = cycles.wrapping_add(6);
-                }
-            }
+            0xF0 => cycles = cycles.wrapping_add(self.ret_cc(!self.reg.flags.s)),
             // RET z
-            0xC8 => {
-                if self.reg.flags.z {
-                    self.ret();
-                    cycles = cycles.wrapping_add(6);
-                }
-            }
+            0xC8 => cycles = cycles.wrapping_add(self.ret_cc(self.reg.flags.z)),
             // RET c
-            0xD8 => {
-                if self.reg.flags.c {
-                    self.ret();
-                    cycles = cycles.wrapping_add(6);
-                }
-            }
+            0xD8 => cycles = cycles.wrapping_add(self.ret_cc(self.reg.flags.c)),
             // RET pe
-            0xE8 => {
-                if self.reg.flags.p {
-                    self.ret();
-                    cycles = cycles.wrapping_add(6);
-                }
-            }
+            0xE8 => cycles = cycles.wrapping_add(self.ret_cc(self.reg.flags.p)),
             // RET m
-            0xF8 => {
-                if self.reg.flags.s {
-                    self.ret();
-                    cycles = cycles.wrapping_add(6);
-                }
-            }
+            0xF8 => cycles = cycles.wrapping_add(self.ret_cc(self.reg.flags.s)),
             // RST 0x00..0x38
             0xC7 => self.rst(0x00),
             0xCF => self.rst(0x08),
@@ -898,16 +828,16 @@ impl Z80 {
             // IN A, (n)
             0xDB => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 let addr = u16::from_le_bytes([n, self.reg.a]);
-                self.reg.a = read_io(addr);
+                self.reg.a = self.read_port(addr);
             }
             // OUT (n), A
             0xD3 => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 let addr = u16::from_le_bytes([n, self.reg.a]);
-                write_io(addr, self.reg.a);
+                self.write_port(addr, self.reg.a);
             }
 
             // 8-bit arithmetic group
@@ -1010,49 +940,49 @@ impl Z80 {
             // ADD a, n
             0xC6 => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.add_a_r(n);
             }
             // SUB A, n
             0xD6 => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.sub_a_r(n);
             }
             // AND A, n
             0xE6 => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.bit_op_a_r(BitOp::AND, n);
             }
             // OR A, n
             0xF6 => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.bit_op_a_r(BitOp::OR, n);
             }
             // ADC A, n
             0xCE => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.adc_a_r(n);
             }
             // SBC A, n
             0xDE => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.sbc_a_r(n);
             }
             // XOR A, n
             0xEE => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.bit_op_a_r(BitOp::XOR, n);
             }
             // CP A, n
             0xFE => {
                 self.reg.inc_pc();
-                let n = self.bus.read(self.reg.pc);
+                let n = self.read_mem(self.reg.pc);
                 self.cp_r(n);
             }
             // INC r
@@ -1062,11 +992,7 @@ impl Z80 {
                 let reg = self.inc_r(self.get_h_ixh_iyh());
                 self.set_h_ixh_iyh(reg);
             }
-            0x34 => {
-                let mut n = self.read_hl_ix_iy();
-                n = self.inc_r(n);
-                self.write_hl_ix_iy(n);
-            }
+            0x34 => self.modify_hl_ix_iy(Self::inc_r),
             0x0C => self.reg.c = self.inc_r(self.reg.c),
             0x1C => self.reg.e = self.inc_r(self.reg.e),
             0x2C => {
@@ -1081,11 +1007,7 @@ impl Z80 {
                 let reg = self.dec_r(self.get_h_ixh_iyh());
                 self.set_h_ixh_iyh(reg);
             }
-            0x35 => {
-                let mut n = self.read_hl_ix_iy();
-                n = self.dec_r(n);
-                self.write_hl_ix_iy(n);
-            }
+            0x35 => self.modify_hl_ix_iy(Self::dec_r),
             0x0D => self.reg.c = self.dec_r(self.reg.c),
             0x1D => self.reg.e = self.dec_r(self.reg.e),
             0x2D => {
@@ -1101,15 +1023,39 @@ impl Z80 {
             0x29 => self.add_hl_ix_iy_rr(self.get_hl_ix_iy()),
             0x39 => self.add_hl_ix_iy_rr(self.reg.sp),
             // INC rr
-            0x03 => self.reg.set_bc(self.reg.get_bc().wrapping_add(1)),
-            0x13 => self.reg.set_de(self.reg.get_de().wrapping_add(1)),
-            0x23 => self.set_hl_ix_iy(self.get_hl_ix_iy().wrapping_add(1)),
-            0x33 => self.reg.sp = self.reg.sp.wrapping_add(1),
+            0x03 => {
+                self.extend_m1(2);
+                self.reg.set_bc(self.reg.get_bc().wrapping_add(1));
+            }
+            0x13 => {
+                self.extend_m1(2);
+                self.reg.set_de(self.reg.get_de().wrapping_add(1));
+            }
+            0x23 => {
+                self.extend_m1(2);
+                self.set_hl_ix_iy(self.get_hl_ix_iy().wrapping_add(1));
+            }
+            0x33 => {
+                self.extend_m1(2);
+                self.reg.sp = self.reg.sp.wrapping_add(1);
+            }
             // DEC rr
-            0x0B => self.reg.set_bc(self.reg.get_bc().wrapping_sub(1)),
-            0x1B => self.reg.set_de(self.reg.get_de().wrapping_sub(1)),
-            0x2B => self.set_hl_ix_iy(self.get_hl_ix_iy().wrapping_sub(1)),
-            0x3B => self.reg.sp = self.reg.sp.wrapping_sub(1),
+            0x0B => {
+                self.extend_m1(2);
+                self.reg.set_bc(self.reg.get_bc().wrapping_sub(1));
+            }
+            0x1B => {
+                self.extend_m1(2);
+                self.reg.set_de(self.reg.get_de().wrapping_sub(1));
+            }
+            0x2B => {
+                self.extend_m1(2);
+                self.set_hl_ix_iy(self.get_hl_ix_iy().wrapping_sub(1));
+            }
+            0x3B => {
+                self.extend_m1(2);
+                self.reg.sp = self.reg.sp.wrapping_sub(1);
+            }
 
             // Rotate group
             // RLCA
@@ -0,0 +1,86 @@
+use crate::m_cycles::{MCycle, MCycleKind};
+use crate::z80::*;
+
+impl<B: Bus> Z80<B> {
+    // Sampled at the start of each instruction. When an interrupt is accepted the
+    // acknowledge sequence is run and its number of T-states is returned.
+    pub fn accept_interrupt(&mut self) -> Option<u8> {
+        // No interrupt between a DD/FD prefix and its opcode
+        if self.p_inst == 0xDD || self.p_inst == 0xFD {
+            return None;
+        }
+        let nmi_edge = self.nmi_latch && !self.n_nmi;
+        self.nmi_latch = self.n_nmi;
+        if nmi_edge {
+            return Some(self.nmi());
+        }
+        // Maskable interrupts are not accepted right after EI
+        if (!self.n_int || !self.bus.n_int()) && self.iff1 && self.p_inst != 0xFB {
+            return Some(self.int());
+        }
+        None
+    }
+
+    fn leave_halt(&mut self) {
+        // PC still points to the HALT instruction
+        if !self.n_halt {
+            self.n_halt = true;
+            self.reg.inc_pc();
+        }
+    }
+
+    fn push_pc(&mut self) {
+        let [pcl, pch] = self.reg.pc.to_le_bytes();
+        self.reg.dec_sp();
+        self.write_mem(self.reg.sp, pch);
+        self.reg.dec_sp();
+        self.write_mem(self.reg.sp, pcl);
+    }
+
+    fn nmi(&mut self) -> u8 {
+        self.leave_halt();
+        // The opcode fetched during the acknowledge is ignored
+        _ = self.fetch_opcode();
+        self.extend_m1(1);
+        self.iff1 = false;
+        self.push_pc();
+        self.reg.pc = 0x0066;
+        self.p_inst = 0x00;
+        11
+    }
+
+    fn int(&mut self) -> u8 {
+        self.leave_halt();
+        self.iff1 = false;
+        self.iff2 = false;
+        let data = self.bus.int_ack().unwrap_or(self.int_vector);
+        let mut m = MCycle::new(MCycleKind::InterruptAck, self.reg.pc, data);
+        m.refresh = self.reg.get_ir();
+        self.record(m);
+        // The acknowledge is an M1 cycle, followed by an internal T-state
+        self.reg.inc_r();
+        self.extend_m1(1);
+        self.p_inst = 0x00;
+        match self.im {
+            // Only RST instructions are expected on the data bus
+            InterruptMode::IM_0 => {
+                self.push_pc();
+                self.reg.pc = (data & 0x38) as u16;
+                13
+            }
+            InterruptMode::IM_1 => {
+                self.push_pc();
+                self.reg.pc = 0x0038;
+                13
+            }
+            InterruptMode::IM_2 => {
+                self.push_pc();
+                let addr = u16::from_le_bytes([data, self.reg.i]);
+                let pcl = self.read_mem(addr);
+                let pch = self.read_mem(addr.wrapping_add(1));
+                self.reg.pc = u16::from_le_bytes([pcl, pch]);
+                19
+            }
+        }
+    }
+}
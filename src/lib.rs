@@ -1,8 +1,14 @@
 pub mod bus;
+pub mod bus_request;
 pub mod cb_instructions;
 pub mod cycles;
+pub mod devices;
 pub mod ed_instructions;
 pub mod flags;
 pub mod instructions;
+pub mod interrupts;
+pub mod m_cycles;
+pub mod machines;
+pub mod power_on;
 pub mod registers;
 pub mod z80;
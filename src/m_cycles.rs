@@ -0,0 +1,304 @@
+use crate::z80::*;
+
+// Kind of machine cycle performed by the CPU on its buses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MCycleKind {
+    OpcodeFetch,
+    MemoryRead,
+    MemoryWrite,
+    IoRead,
+    IoWrite,
+    InterruptAck,
+    Internal,
+}
+
+// One machine cycle: what was on the address and data buses and how many T-states it lasts.
+// `refresh` is the I/R pair put on the address bus during the refresh of M1 cycles and
+// `wait` the number of wait states inserted on top of `t_states`. Internal cycles leave
+// `addr` on the address bus while the CPU works without accessing the buses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MCycle {
+    pub kind: MCycleKind,
+    pub addr: u16,
+    pub data: u8,
+    pub refresh: u16,
+    pub t_states: u8,
+    pub wait: u32,
+}
+
+impl MCycle {
+    pub fn new(kind: MCycleKind, addr: u16, data: u8) -> Self {
+        let t_states = match kind {
+            MCycleKind::OpcodeFetch => 4,
+            MCycleKind::MemoryRead | MCycleKind::MemoryWrite => 3,
+            // T1, T2, automatic wait state, T3
+            MCycleKind::IoRead | MCycleKind::IoWrite => 4,
+            // M1 stretched by two automatic wait states
+            MCycleKind::InterruptAck => 6,
+            MCycleKind::Internal => 0,
+        };
+        Self {
+            kind,
+            addr,
+            data,
+            refresh: 0,
+            t_states,
+            wait: 0,
+        }
+    }
+
+    // T-state after which the wait states are inserted
+    pub fn wait_sample(&self) -> Option<u8> {
+        match self.kind {
+            MCycleKind::OpcodeFetch | MCycleKind::MemoryRead | MCycleKind::MemoryWrite => Some(1),
+            MCycleKind::IoRead | MCycleKind::IoWrite => Some(2),
+            MCycleKind::InterruptAck => Some(3),
+            MCycleKind::Internal => None,
+        }
+    }
+
+    // Maps a T-state of the cycle, wait states included, to the T-state of the
+    // cycle without wait states. Wait states repeat the state they follow.
+    pub fn phase(&self, t: u32) -> u8 {
+        match self.wait_sample().map(u32::from) {
+            Some(sample) if t > sample + self.wait => (t - self.wait) as u8,
+            Some(sample) if t > sample => sample as u8,
+            _ => t as u8,
+        }
+    }
+}
+
+impl<B: Bus> Z80<B> {
+    // Bus accesses made by the instructions go through these functions so that each
+    // machine cycle of the last instruction is recorded in `m_cycles`, along with the
+    // wait states requested by the bus.
+    // When the CPU is driven T-state by T-state, memory and IO writes are held back
+    // until the T-state where /WR is asserted; reads are performed right away.
+    pub fn fetch_opcode(&mut self) -> u8 {
+        let data = self.read_deferred(self.reg.pc);
+        let mut m = MCycle::new(MCycleKind::OpcodeFetch, self.reg.pc, data);
+        m.refresh = self.reg.get_ir();
+        self.record(m);
+        // R is incremented by each M1 cycle: once per opcode and once more per CB, ED,
+        // DD or FD prefix. With DD CB / FD CB the last two bytes are plain memory reads.
+        self.reg.inc_r();
+        data
+    }
+
+    pub fn read_mem(&mut self, addr: u16) -> u8 {
+        let data = self.read_deferred(addr);
+        self.record(MCycle::new(MCycleKind::MemoryRead, addr, data));
+        data
+    }
+
+    pub fn write_mem(&mut self, addr: u16, data: u8) {
+        self.record(MCycle::new(MCycleKind::MemoryWrite, addr, data));
+        if !self.deferred {
+            self.bus.write(addr, data);
+        }
+    }
+
+    pub fn read_port(&mut self, addr: u16) -> u8 {
+        let data = self.bus.read_io(addr);
+        self.record(MCycle::new(MCycleKind::IoRead, addr, data));
+        data
+    }
+
+    pub fn write_port(&mut self, addr: u16, data: u8) {
+        self.record(MCycle::new(MCycleKind::IoWrite, addr, data));
+        if !self.deferred {
+            self.bus.write_io(addr, data);
+        }
+    }
+
+    pub(crate) fn record(&mut self, mut m: MCycle) {
+        m.wait = self.bus.wait_states(&m, self.clock + self.t_offset as u64) as u32;
+        self.t_offset += m.t_states as u32 + m.wait;
+        self.m_cycles.push(m);
+    }
+
+    // T-states spent by the instruction without accessing the buses, recorded where
+    // they occur with `addr` left on the address bus
+    pub(crate) fn record_internal(&mut self, addr: u16, t_states: u8) {
+        let mut m = MCycle::new(MCycleKind::Internal, addr, 0);
+        m.t_states = t_states;
+        self.record(m);
+    }
+
+    // M1 cycles of 5 or 6 T-states: the refresh address stays on the address bus
+    pub(crate) fn extend_m1(&mut self, t_states: u8) {
+        let refresh = self.m_cycles.last().map_or(self.reg.get_ir(), |m| m.refresh);
+        self.record_internal(refresh, t_states);
+    }
+
+    // A read following a held back write to the same address must see the new value
+    fn read_deferred(&self, addr: u16) -> u8 {
+        if self.deferred {
+            let pending = self
+                .m_cycles
+                .iter()
+                .rev()
+                .find(|m| m.kind == MCycleKind::MemoryWrite && m.addr == addr);
+            if let Some(m) = pending {
+                return m.data;
+            }
+        }
+        self.bus.read(addr)
+    }
+
+    // Cycle-level execution: advance the CPU by a single T-state and drive the
+    // control pins, address bus and data bus accordingly.
+    // The next instruction is decoded on its first T-state and its machine cycles
+    // are then played back one T-state per call, internal cycles included where they
+    // occur in the instruction.
+    // /WAIT is sampled during T2 (during the last automatic wait state for IO and
+    // interrupt acknowledge); while it is low, wait states are inserted.
+    // Pulling /RESET low resets the CPU on the next T-state.
+    // When /BUSRQ is low at the end of a machine cycle the buses are released and
+    // /BUSACK stays low until the request is removed.
+    pub fn tick(&mut self) {
+        if self.hold_in_reset() {
+            return;
+        }
+        // BUSRQ is sampled at the end of each machine cycle
+        if self.t_index == 0 && self.bus_requested() {
+            self.release_bus();
+            return;
+        }
+        self.n_busack = true;
+
+        if self.m_index >= self.m_cycles.len() {
+            self.m_index = 0;
+            self.t_index = 0;
+            self.deferred = true;
+            self.execute();
+            self.deferred = false;
+        }
+
+        let m = self.m_cycles[self.m_index];
+        if let Some(sample) = m.wait_sample() {
+            if self.t_index == sample as u32 + m.wait + 1 && !self.n_wait {
+                self.m_cycles[self.m_index].wait += 1;
+            }
+        }
+        let m = self.m_cycles[self.m_index];
+        self.drive_pins(&m, m.phase(self.t_index));
+        self.clock += 1;
+
+        self.t_index += 1;
+        if self.t_index >= m.t_states as u32 + m.wait {
+            self.t_index = 0;
+            self.m_index += 1;
+        }
+    }
+
+    // Returns true when the next call to tick() starts a new instruction
+    pub fn instruction_complete(&self) -> bool {
+        self.m_index >= self.m_cycles.len()
+    }
+
+    // Second opcode fetch of RETI, where the Zilog peripherals decode it
+    fn reti_fetch(&self, m: &MCycle) -> bool {
+        let previous = self.m_index.checked_sub(1).map(|i| self.m_cycles[i]);
+        m.data == 0x4D
+            && previous.is_some_and(|p| p.kind == MCycleKind::OpcodeFetch && p.data == 0xED)
+    }
+
+    fn drive_pins(&mut self, m: &MCycle, t: u8) {
+        self.n_m1 = true;
+        self.n_mreq = true;
+        self.n_iorq = true;
+        self.n_rd = true;
+        self.n_wr = true;
+        self.n_rfsh = true;
+        let refresh = m.refresh;
+
+        match m.kind {
+            MCycleKind::OpcodeFetch => match t {
+                0 | 1 => {
+                    self.addr_bus = m.addr;
+                    self.n_m1 = false;
+                    self.n_mreq = false;
+                    self.n_rd = false;
+                }
+                2 => {
+                    // Opcode latched, the refresh address follows
+                    self.data_bus = m.data;
+                    self.addr_bus = refresh;
+                    self.n_mreq = false;
+                    self.n_rfsh = false;
+                    if self.reti_fetch(m) {
+                        self.bus.reti();
+                    }
+                }
+                _ => {
+                    self.addr_bus = refresh;
+                    self.n_rfsh = false;
+                }
+            },
+            MCycleKind::MemoryRead => {
+                self.addr_bus = m.addr;
+                self.n_mreq = false;
+                self.n_rd = false;
+                if t == 2 {
+                    self.data_bus = m.data;
+                }
+            }
+            MCycleKind::MemoryWrite => {
+                self.addr_bus = m.addr;
+                self.data_bus = m.data;
+                self.n_mreq = false;
+                if t >= 1 {
+                    self.n_wr = false;
+                }
+                if t == 2 {
+                    self.bus.write(m.addr, m.data);
+                }
+            }
+            MCycleKind::IoRead => {
+                self.addr_bus = m.addr;
+                if t >= 1 {
+                    self.n_iorq = false;
+                    self.n_rd = false;
+                }
+                if t == 3 {
+                    self.data_bus = m.data;
+                }
+            }
+            MCycleKind::IoWrite => {
+                self.addr_bus = m.addr;
+                self.data_bus = m.data;
+                if t >= 1 {
+                    self.n_iorq = false;
+                    self.n_wr = false;
+                }
+                if t == 3 {
+                    self.bus.write_io(m.addr, m.data);
+                }
+            }
+            MCycleKind::InterruptAck => match t {
+                0 | 1 => {
+                    self.addr_bus = m.addr;
+                    self.n_m1 = false;
+                }
+                2 | 3 => {
+                    // The device puts its data on the bus while /M1 and /IORQ are low
+                    self.addr_bus = m.addr;
+                    self.n_m1 = false;
+                    self.n_iorq = false;
+                    self.data_bus = m.data;
+                }
+                4 => {
+                    self.addr_bus = refresh;
+                    self.n_mreq = false;
+                    self.n_rfsh = false;
+                }
+                _ => {
+                    self.addr_bus = refresh;
+                    self.n_rfsh = false;
+                }
+            },
+            MCycleKind::Internal => self.addr_bus = m.addr,
+        }
+    }
+}
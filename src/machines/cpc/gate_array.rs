@@ -0,0 +1,127 @@
+// Amstrad Gate Array
+
+// RGB of the 32 hardware colours, 27 of them are distinct. Each gun has three levels.
+pub const HW_PALETTE: [u32; 32] = [
+    0x808080, 0x808080, 0x00FF80, 0xFFFF80, 0x000080, 0xFF0080, 0x008080, 0xFF8080,
+    0xFF0080, 0xFFFF80, 0xFFFF00, 0xFFFFFF, 0xFF0000, 0xFF00FF, 0xFF8000, 0xFF80FF,
+    0x000080, 0x00FF80, 0x00FF00, 0x00FFFF, 0x000000, 0x0000FF, 0x008000, 0x0080FF,
+    0x800080, 0x80FF80, 0x80FF00, 0x80FFFF, 0x800000, 0x8000FF, 0x808000, 0x8080FF,
+];
+
+// Pen 16 is the border
+pub const BORDER: usize = 16;
+
+pub struct GateArray {
+    // Hardware colour of each pen and of the border
+    pub pens: [u8; 17],
+    pub selected_pen: usize,
+    // Screen mode used for the current line, and the one written which is latched at
+    // the next HSYNC
+    pub mode: u8,
+    pub next_mode: u8,
+    pub lower_rom_enabled: bool,
+    pub upper_rom_enabled: bool,
+    // Counts the HSYNCs, an interrupt is raised every 52 lines
+    pub int_counter: u8,
+    pub int_pending: bool,
+    // HSYNCs left before the counter is resynchronised on the VSYNC
+    pub vsync_delay: u8,
+}
+
+impl Default for GateArray {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GateArray {
+    pub fn new() -> Self {
+        Self {
+            pens: [0x14; 17],
+            selected_pen: 0,
+            mode: 1,
+            next_mode: 1,
+            lower_rom_enabled: true,
+            upper_rom_enabled: true,
+            int_counter: 0,
+            int_pending: false,
+            vsync_delay: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    // Bits 7-6 of the data select the function, 11 is the RAM configuration which is
+    // handled by the PAL
+    pub fn write(&mut self, data: u8) {
+        match data >> 6 {
+            0b00 => {
+                self.selected_pen = match data & 0x10 {
+                    0x10 => BORDER,
+                    _ => (data & 0x0F) as usize,
+                };
+            }
+            0b01 => self.pens[self.selected_pen] = data & 0x1F,
+            0b10 => {
+                self.next_mode = data & 0x03;
+                self.lower_rom_enabled = data & 0x04 == 0;
+                self.upper_rom_enabled = data & 0x08 == 0;
+                if data & 0x10 == 0x10 {
+                    self.int_counter = 0;
+                    self.int_pending = false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Value of the last write of the function 10
+    pub fn config(&self) -> u8 {
+        0x80 | self.next_mode
+            | ((!self.lower_rom_enabled as u8) << 2)
+            | ((!self.upper_rom_enabled as u8) << 3)
+    }
+
+    pub fn pen_rgb(&self, pen: usize) -> u32 {
+        HW_PALETTE[self.pens[pen] as usize]
+    }
+
+    // Rising edge of the CRTC HSYNC
+    pub fn hsync_start(&mut self) {
+        self.mode = self.next_mode;
+    }
+
+    // Falling edge of the CRTC HSYNC
+    pub fn hsync_end(&mut self) {
+        self.int_counter += 1;
+        if self.int_counter == 52 {
+            self.int_counter = 0;
+            self.int_pending = true;
+        }
+        // Two lines after the start of the VSYNC the counter is cleared, an interrupt
+        // is raised unless the last one was less than 32 lines ago
+        if self.vsync_delay > 0 {
+            self.vsync_delay -= 1;
+            if self.vsync_delay == 0 {
+                if self.int_counter >= 32 {
+                    self.int_pending = true;
+                }
+                self.int_counter = 0;
+            }
+        }
+    }
+
+    // Rising edge of the CRTC VSYNC
+    pub fn vsync_start(&mut self) {
+        self.vsync_delay = 2;
+    }
+
+    // The Z80 acknowledges the interrupt: bit 5 of the counter is cleared so that the
+    // next interrupt comes at least 32 lines later
+    pub fn int_ack(&mut self) {
+        self.int_counter &= 0x1F;
+        self.int_pending = false;
+    }
+}
@@ -0,0 +1,213 @@
+// CPC keyboard matrix: 10 rows of 8 keys scanned through the PPI and the PSG port A.
+// Joystick 0 is on row 9, joystick 1 on row 6.
+
+use std::collections::{HashMap, VecDeque};
+
+// Keys in matrix order, the value is row * 8 + bit
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[repr(u8)]
+pub enum Key {
+    CursorUp, CursorRight, CursorDown, F9, F6, F3, Enter, FDot,
+    CursorLeft, Copy, F7, F8, F5, F1, F2, F0,
+    Clr, LeftBracket, Return, RightBracket, F4, Shift, Backslash, Control,
+    Caret, Minus, At, P, Semicolon, Colon, Slash, Dot,
+    Key0, Key9, O, I, L, K, M, Comma,
+    Key8, Key7, U, Y, H, J, N, Space,
+    Key6, Key5, R, T, G, F, B, V,
+    Key4, Key3, E, W, S, D, C, X,
+    Key1, Key2, Esc, Q, Tab, A, CapsLock, Z,
+    JoyUp, JoyDown, JoyLeft, JoyRight, JoyFire2, JoyFire1, JoyFire3, Del,
+}
+
+use Key::*;
+
+// Keys by matrix position, with their names
+pub const KEYS: [(Key, &str); 80] = [
+    (CursorUp, "CURSOR UP"), (CursorRight, "CURSOR RIGHT"), (CursorDown, "CURSOR DOWN"),
+    (F9, "F9"), (F6, "F6"), (F3, "F3"), (Enter, "ENTER"), (FDot, "F."),
+    (CursorLeft, "CURSOR LEFT"), (Copy, "COPY"), (F7, "F7"), (F8, "F8"), (F5, "F5"),
+    (F1, "F1"), (F2, "F2"), (F0, "F0"),
+    (Clr, "CLR"), (LeftBracket, "["), (Return, "RETURN"), (RightBracket, "]"), (F4, "F4"),
+    (Shift, "SHIFT"), (Backslash, "\\"), (Control, "CONTROL"),
+    (Caret, "^"), (Minus, "-"), (At, "@"), (P, "P"), (Semicolon, ";"), (Colon, ":"),
+    (Slash, "/"), (Dot, "."),
+    (Key0, "0"), (Key9, "9"), (O, "O"), (I, "I"), (L, "L"), (K, "K"), (M, "M"),
+    (Comma, ","),
+    (Key8, "8"), (Key7, "7"), (U, "U"), (Y, "Y"), (H, "H"), (J, "J"), (N, "N"),
+    (Space, "SPACE"),
+    (Key6, "6"), (Key5, "5"), (R, "R"), (T, "T"), (G, "G"), (F, "F"), (B, "B"), (V, "V"),
+    (Key4, "4"), (Key3, "3"), (E, "E"), (W, "W"), (S, "S"), (D, "D"), (C, "C"), (X, "X"),
+    (Key1, "1"), (Key2, "2"), (Esc, "ESC"), (Q, "Q"), (Tab, "TAB"), (A, "A"),
+    (CapsLock, "CAPS LOCK"), (Z, "Z"),
+    (JoyUp, "JOY UP"), (JoyDown, "JOY DOWN"), (JoyLeft, "JOY LEFT"), (JoyRight, "JOY RIGHT"),
+    (JoyFire2, "JOY FIRE 2"), (JoyFire1, "JOY FIRE 1"), (JoyFire3, "JOY FIRE 3"),
+    (Del, "DEL"),
+];
+
+impl Key {
+    pub fn row(self) -> usize {
+        self as usize >> 3
+    }
+
+    pub fn mask(self) -> u8 {
+        1 << (self as u8 & 0x07)
+    }
+
+    pub fn name(self) -> &'static str {
+        KEYS[self as usize].1
+    }
+
+    // Name as printed on the key, case insensitive
+    pub fn from_name(name: &str) -> Option<Key> {
+        KEYS.iter()
+            .find(|(_, n)| n.eq_ignore_ascii_case(name))
+            .map(|&(key, _)| key)
+    }
+
+    // Keys to press to type a character: the key and whether SHIFT is needed
+    pub fn from_char(c: char) -> Option<(Key, bool)> {
+        // Characters of the keys other than the letters, unshifted and shifted
+        const CHARS: [(Key, char, char); 23] = [
+            (Key0, '0', '_'), (Key1, '1', '!'), (Key2, '2', '"'), (Key3, '3', '#'),
+            (Key4, '4', '$'), (Key5, '5', '%'), (Key6, '6', '&'), (Key7, '7', '\''),
+            (Key8, '8', '('), (Key9, '9', ')'), (Minus, '-', '='), (Caret, '^', '£'),
+            (At, '@', '|'), (LeftBracket, '[', '{'), (Semicolon, ';', '+'),
+            (Colon, ':', '*'), (RightBracket, ']', '}'), (Comma, ',', '<'),
+            (Dot, '.', '>'), (Slash, '/', '?'), (Backslash, '\\', '`'),
+            (Space, ' ', ' '), (Return, '\n', '\n'),
+        ];
+        if c.is_ascii_alphabetic() {
+            let key = Key::from_name(&c.to_ascii_uppercase().to_string())?;
+            return Some((key, c.is_ascii_uppercase()));
+        }
+        CHARS.iter().find_map(|&(key, unshifted, shifted)| match c {
+            _ if c == unshifted => Some((key, false)),
+            _ if c == shifted => Some((key, true)),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Joystick {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    pub fire1: bool,
+    pub fire2: bool,
+}
+
+impl Joystick {
+    // Row of the matrix, a direction or button pressed reads as 0
+    pub fn row(&self) -> u8 {
+        let lines = [
+            self.up, self.down, self.left, self.right, self.fire2, self.fire1,
+        ];
+        !lines
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (bit, &on)| acc | ((on as u8) << bit))
+    }
+}
+
+// Host key codes, named as the KeyboardEvent.code values, mapped to CPC keys by
+// position on the keyboard
+pub struct KeyMap {
+    pub map: HashMap<String, Key>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let mut map = HashMap::new();
+        for c in 'A'..='Z' {
+            map.insert(format!("Key{}", c), Key::from_name(&c.to_string()).unwrap());
+        }
+        for c in '0'..='9' {
+            map.insert(format!("Digit{}", c), Key::from_char(c).unwrap().0);
+        }
+        let others = [
+            ("ArrowUp", CursorUp), ("ArrowDown", CursorDown), ("ArrowLeft", CursorLeft),
+            ("ArrowRight", CursorRight), ("Enter", Return), ("NumpadEnter", Enter),
+            ("Space", Space), ("ShiftLeft", Shift), ("ShiftRight", Shift),
+            ("ControlLeft", Control), ("ControlRight", Control), ("AltLeft", Copy),
+            ("Escape", Esc), ("Tab", Tab), ("CapsLock", CapsLock), ("Backspace", Del),
+            ("Delete", Clr), ("Minus", Minus), ("Equal", Caret), ("BracketLeft", At),
+            ("BracketRight", LeftBracket), ("Backslash", RightBracket),
+            ("Semicolon", Colon), ("Quote", Semicolon), ("Comma", Comma), ("Period", Dot),
+            ("Slash", Slash), ("IntlBackslash", Backslash), ("Numpad0", F0),
+            ("Numpad1", F1), ("Numpad2", F2), ("Numpad3", F3), ("Numpad4", F4),
+            ("Numpad5", F5), ("Numpad6", F6), ("Numpad7", F7), ("Numpad8", F8),
+            ("Numpad9", F9), ("NumpadDecimal", FDot),
+        ];
+        for (code, key) in others {
+            map.insert(code.to_string(), key);
+        }
+        Self { map }
+    }
+}
+
+impl KeyMap {
+    pub fn get(&self, code: &str) -> Option<Key> {
+        self.map.get(code).copied()
+    }
+
+    pub fn insert(&mut self, code: &str, key: Key) {
+        self.map.insert(code.to_string(), key);
+    }
+}
+
+// Key presses and releases to apply at given frames
+#[derive(Default)]
+pub struct InputScript {
+    pub events: VecDeque<(u64, Key, bool)>,
+}
+
+// Frames a key of a typed string is held, then released
+const TYPE_FRAMES: u64 = 2;
+
+impl InputScript {
+    pub fn press(&mut self, frame: u64, key: Key) {
+        self.insert(frame, key, true);
+    }
+
+    pub fn release(&mut self, frame: u64, key: Key) {
+        self.insert(frame, key, false);
+    }
+
+    fn insert(&mut self, frame: u64, key: Key, pressed: bool) {
+        let at = self.events.partition_point(|&(f, _, _)| f <= frame);
+        self.events.insert(at, (frame, key, pressed));
+    }
+
+    // Types the text from `frame`, characters without a key are skipped. Returns the
+    // frame after the last key is released.
+    pub fn type_text(&mut self, frame: u64, text: &str) -> u64 {
+        let mut frame = frame;
+        for (key, shift) in text.chars().filter_map(Key::from_char) {
+            if shift {
+                self.press(frame, Shift);
+            }
+            self.press(frame, key);
+            self.release(frame + TYPE_FRAMES, key);
+            if shift {
+                self.release(frame + TYPE_FRAMES, Shift);
+            }
+            frame += 2 * TYPE_FRAMES;
+        }
+        frame
+    }
+
+    // Events due at `frame`
+    pub fn due(&mut self, frame: u64) -> Vec<(Key, bool)> {
+        let mut due = Vec::new();
+        while let Some(&(f, key, pressed)) = self.events.front() {
+            if f > frame {
+                break;
+            }
+            due.push((key, pressed));
+            self.events.pop_front();
+        }
+        due
+    }
+}
@@ -0,0 +1,156 @@
+pub const BANK_SIZE: usize = 0x10000;
+pub const PAGE_SIZE: usize = 0x4000;
+pub const ROM_SIZE: usize = 0x4000;
+
+// Blocks of 16 KiB mapped in the four pages of the Z80 address space by the RAM
+// configurations C0-C7. Blocks 4-7 are in the selected expansion bank.
+const RAM_CONFIGS: [[usize; 4]; 8] = [
+    [0, 1, 2, 3],
+    [0, 1, 2, 7],
+    [4, 5, 6, 7],
+    [0, 3, 2, 7],
+    [0, 4, 2, 3],
+    [0, 5, 2, 3],
+    [0, 6, 2, 3],
+    [0, 7, 2, 3],
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Page {
+    // Offset of the page in the RAM
+    Ram(usize),
+    LowerRom,
+    UpperRom,
+}
+
+pub const ROM_SLOTS: usize = 256;
+
+// 64 KiB of base RAM followed by the expansion banks of 64 KiB, with the lower ROM
+// (firmware) over 0x0000-0x3FFF and the selected upper ROM over 0xC000-0xFFFF.
+// Reads see the ROMs when enabled, writes always go to RAM.
+pub struct CpcMemory {
+    pub ram: Vec<u8>,
+    pub lower_rom: Vec<u8>,
+    // Slot 0 holds BASIC
+    pub upper_roms: Vec<Option<Vec<u8>>>,
+    // Last value written to &DFxx, and the slot it selects
+    pub upper_rom_select: u8,
+    upper_rom_slot: usize,
+    pub lower_rom_enabled: bool,
+    pub upper_rom_enabled: bool,
+    // Last value written with bits 7-6 set to 11
+    pub ram_config: u8,
+    pub read_pages: [Page; 4],
+    pub write_pages: [usize; 4],
+}
+
+impl CpcMemory {
+    // 128 KiB of RAM as on the 6128
+    pub fn new(lower_rom: &[u8], upper_rom: &[u8]) -> Self {
+        Self::with_banks(lower_rom, upper_rom, 1)
+    }
+
+    // `banks` expansion banks of 64 KiB, up to 8 for a 512 KiB expansion
+    pub fn with_banks(lower_rom: &[u8], upper_rom: &[u8], banks: usize) -> Self {
+        let banks = banks.clamp(1, 8);
+        let mut memory = Self {
+            ram: vec![0_u8; BANK_SIZE * (banks + 1)],
+            lower_rom: lower_rom.to_vec(),
+            upper_roms: (0..ROM_SLOTS)
+                .map(|slot| (slot == 0).then(|| upper_rom.to_vec()))
+                .collect(),
+            upper_rom_select: 0,
+            upper_rom_slot: 0,
+            lower_rom_enabled: true,
+            upper_rom_enabled: true,
+            ram_config: 0xC0,
+            read_pages: [Page::Ram(0); 4],
+            write_pages: [0; 4],
+        };
+        memory.update_pages();
+        memory
+    }
+
+    pub fn banks(&self) -> usize {
+        self.ram.len() / BANK_SIZE - 1
+    }
+
+    // Bits 2-0 select the configuration, bits 5-3 the expansion bank
+    pub fn set_ram_config(&mut self, data: u8) {
+        self.ram_config = data;
+        self.update_pages();
+    }
+
+    // A slot without ROM selects BASIC
+    pub fn select_upper_rom(&mut self, data: u8) {
+        self.upper_rom_select = data;
+        self.upper_rom_slot = match self.upper_roms[data as usize] {
+            Some(_) => data as usize,
+            None => 0,
+        };
+    }
+
+    pub fn upper_rom_slot(&self) -> usize {
+        self.upper_rom_slot
+    }
+
+    // ROMs shorter than 16 KiB are padded with &FF
+    pub fn set_upper_rom(&mut self, slot: u8, rom: &[u8]) {
+        let mut rom = rom[..rom.len().min(ROM_SIZE)].to_vec();
+        rom.resize(ROM_SIZE, 0xFF);
+        self.upper_roms[slot as usize] = Some(rom);
+        self.select_upper_rom(self.upper_rom_select);
+    }
+
+    pub fn remove_upper_rom(&mut self, slot: u8) -> Option<Vec<u8>> {
+        let rom = self.upper_roms[slot as usize].take();
+        self.select_upper_rom(self.upper_rom_select);
+        rom
+    }
+
+    pub fn set_rom_enables(&mut self, lower: bool, upper: bool) {
+        self.lower_rom_enabled = lower;
+        self.upper_rom_enabled = upper;
+        self.update_pages();
+    }
+
+    fn update_pages(&mut self) {
+        let bank = ((self.ram_config >> 3) & 0x07) as usize % self.banks();
+        let config = RAM_CONFIGS[(self.ram_config & 0x07) as usize];
+        for (page, &block) in config.iter().enumerate() {
+            self.write_pages[page] = match block {
+                0..=3 => block * PAGE_SIZE,
+                _ => BANK_SIZE * (bank + 1) + (block - 4) * PAGE_SIZE,
+            };
+            self.read_pages[page] = Page::Ram(self.write_pages[page]);
+        }
+        if self.lower_rom_enabled {
+            self.read_pages[0] = Page::LowerRom;
+        }
+        if self.upper_rom_enabled {
+            self.read_pages[3] = Page::UpperRom;
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        let offset = addr as usize & (PAGE_SIZE - 1);
+        match self.read_pages[addr as usize >> 14] {
+            Page::Ram(base) => self.ram[base + offset],
+            Page::LowerRom => self.lower_rom[offset],
+            Page::UpperRom => match &self.upper_roms[self.upper_rom_slot] {
+                Some(rom) => rom[offset],
+                None => 0xFF,
+            },
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        let offset = addr as usize & (PAGE_SIZE - 1);
+        self.ram[self.write_pages[addr as usize >> 14] + offset] = data;
+    }
+
+    // The Gate Array always reads the screen from the base 64 KiB
+    pub fn video_read(&self, addr: u16) -> u8 {
+        self.ram[addr as usize]
+    }
+}
@@ -0,0 +1,414 @@
+// Amstrad CPC 6128
+pub mod gate_array;
+pub mod keyboard;
+pub mod memory;
+pub mod snapshot;
+pub mod video;
+
+use crate::devices::ay38910::Ay38910;
+use crate::devices::crtc6845::Crtc6845;
+use crate::devices::dsk::Disk;
+use crate::devices::tape::{Tape, TapeDeck};
+use crate::devices::upd765::Upd765;
+use crate::devices::ppi8255::{Ppi8255, PORT_A, PORT_B};
+use crate::m_cycles::MCycle;
+use crate::z80::*;
+use gate_array::GateArray;
+use keyboard::{InputScript, Joystick, Key, KeyMap};
+use memory::{CpcMemory, ROM_SIZE};
+use video::Video;
+use std::io;
+use std::path::Path;
+
+// The Z80 runs at 4 MHz, the CRTC, the Gate Array and the PSG at 1 MHz
+pub const CPU_CLOCK: u64 = 4_000_000;
+// 312 lines of 64 us
+pub const FRAME_US: u64 = 19_968;
+
+pub struct CpcBus {
+    pub memory: CpcMemory,
+    pub gate_array: GateArray,
+    pub crtc: Crtc6845,
+    pub ppi: Ppi8255,
+    pub psg: Ay38910,
+    pub fdc: Upd765,
+    pub video: Video,
+    pub tape: TapeDeck,
+    // Keyboard matrix rows 0-9, a key pressed reads as 0
+    pub keyboard: [u8; 10],
+    pub joysticks: [Joystick; 2],
+    // Level of the cassette read data line
+    pub cassette_in: bool,
+    // Microseconds elapsed since power on
+    pub us: u64,
+    // Set at the start of each VSYNC
+    pub frame_done: bool,
+}
+
+impl CpcBus {
+    pub fn new(memory: CpcMemory) -> Self {
+        Self {
+            memory,
+            gate_array: GateArray::new(),
+            crtc: Crtc6845::new(),
+            ppi: Ppi8255::new(),
+            psg: Ay38910::new(),
+            fdc: Upd765::new(),
+            video: Video::new(),
+            tape: TapeDeck::new(CPU_CLOCK),
+            keyboard: [0xFF; 10],
+            joysticks: [Joystick::default(); 2],
+            cassette_in: false,
+            us: 0,
+            frame_done: false,
+        }
+    }
+
+    // Runs the devices for one microsecond
+    pub fn step_us(&mut self) {
+        let hsync = self.crtc.hsync;
+        let vsync = self.crtc.vsync;
+        self.crtc.step();
+        self.psg.run(1);
+        self.fdc.run(1);
+        self.tape.motor = self.cassette_motor();
+        self.tape.run(4);
+        self.tape.record(self.cassette_out(), 4);
+        self.cassette_in = self.tape.level;
+        if !hsync && self.crtc.hsync {
+            self.gate_array.hsync_start();
+        }
+        if hsync && !self.crtc.hsync {
+            self.gate_array.hsync_end();
+            self.video.hsync_end();
+        }
+        if !vsync && self.crtc.vsync {
+            self.gate_array.vsync_start();
+            self.video.vsync_start();
+            self.frame_done = true;
+        }
+        self.video
+            .render(&self.crtc, &self.gate_array, &self.memory);
+        self.us += 1;
+    }
+
+    pub(crate) fn update_memory(&mut self) {
+        self.memory.set_rom_enables(
+            self.gate_array.lower_rom_enabled,
+            self.gate_array.upper_rom_enabled,
+        );
+    }
+
+    // PPI port B inputs. Bit 7: cassette data, 6: printer busy, 5: /EXP, 4: 50 Hz,
+    // 3-1: manufacturer (Amstrad), 0: VSYNC
+    fn ppi_port_b(&self) -> u8 {
+        let mut data = 0x7E | self.crtc.vsync as u8;
+        if self.cassette_in {
+            data |= 0x80;
+        }
+        data
+    }
+
+    // PPI port C outputs. Bits 3-0: keyboard row, 4: cassette motor, 5: cassette write
+    // data, 7-6: PSG BDIR and BC1
+    pub fn keyboard_row(&self) -> usize {
+        (self.ppi.output_c() & 0x0F) as usize
+    }
+
+    pub fn cassette_motor(&self) -> bool {
+        self.ppi.output_c() & 0x10 == 0x10
+    }
+
+    pub fn cassette_out(&self) -> bool {
+        self.ppi.output_c() & 0x20 == 0x20
+    }
+
+    // The PSG data bus is connected to PPI port A, and its IO port A reads the keyboard
+    // row selected by the PPI
+    fn psg_control(&mut self) -> Option<u8> {
+        let c = self.ppi.output_c();
+        self.psg.port_a_input = match self.keyboard_row() {
+            6 => self.keyboard[6] & self.joysticks[1].row(),
+            9 => self.keyboard[9] & self.joysticks[0].row(),
+            row if row < 10 => self.keyboard[row],
+            _ => 0xFF,
+        };
+        self.psg
+            .bus_control(c & 0x80 == 0x80, c & 0x40 == 0x40, self.ppi.output_a())
+    }
+
+    fn ppi_read(&mut self, port: u16) -> u8 {
+        let port = ((port >> 8) & 0x03) as u8;
+        let input = match port {
+            // Only a PSG read drives the lines
+            PORT_A if self.ppi.output_c() & 0xC0 == 0x40 => self.psg_control().unwrap_or(0xFF),
+            PORT_B => self.ppi_port_b(),
+            _ => 0xFF,
+        };
+        self.ppi.read(port, input)
+    }
+
+    fn ppi_write(&mut self, port: u16, data: u8) {
+        self.ppi.write(((port >> 8) & 0x03) as u8, data);
+        self.psg_control();
+    }
+}
+
+impl Bus for CpcBus {
+    fn read(&self, addr: u16) -> u8 {
+        self.memory.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.memory.write(addr, data);
+    }
+
+    // Devices are selected by a single address line being low, several of them can
+    // answer to the same port
+    fn read_io(&mut self, addr: u16) -> u8 {
+        let mut data = 0xFF;
+        // CRTC &BCxx-&BFxx
+        if addr & 0x4000 == 0 {
+            data &= match (addr >> 8) & 0x03 {
+                2 => self.crtc.read_status(),
+                3 => self.crtc.read(),
+                _ => 0xFF,
+            };
+        }
+        // PPI &F4xx-&F7xx
+        if addr & 0x0800 == 0 {
+            data &= self.ppi_read(addr);
+        }
+        // FDC &FB7E-&FB7F
+        if addr & 0x0580 == 0x0100 {
+            data &= match addr & 0x0001 {
+                0 => self.fdc.read_status(),
+                _ => self.fdc.read_data(),
+            };
+        }
+        data
+    }
+
+    fn write_io(&mut self, addr: u16, data: u8) {
+        // Gate Array &7Fxx, the RAM configuration is decoded by the PAL
+        if addr & 0xC000 == 0x4000 {
+            if data >> 6 == 0b11 {
+                self.memory.set_ram_config(data);
+            } else {
+                self.gate_array.write(data);
+                self.update_memory();
+            }
+        }
+        // CRTC &BCxx-&BFxx
+        if addr & 0x4000 == 0 {
+            match (addr >> 8) & 0x03 {
+                0 => self.crtc.select(data),
+                1 => self.crtc.write(data),
+                _ => {}
+            }
+        }
+        // Upper ROM select &DFxx
+        if addr & 0x2000 == 0 {
+            self.memory.select_upper_rom(data);
+        }
+        // PPI &F4xx-&F7xx
+        if addr & 0x0800 == 0 {
+            self.ppi_write(addr, data);
+        }
+        // Disc motor &FA7E, FDC &FB7F
+        if addr & 0x0480 == 0 {
+            match addr & 0x0101 {
+                0x0000 => self.fdc.motor = data & 0x01 == 0x01,
+                0x0101 => self.fdc.write_data(data),
+                _ => {}
+            }
+        }
+    }
+
+    // The Gate Array gives the buses to the Z80 once every microsecond: /WAIT is sampled
+    // high on one T-state out of 4 only. Internal cycles are not stretched: the access
+    // that follows them waits instead, so step() and tick() agree.
+    fn wait_states(&mut self, m: &MCycle, t_state: u64) -> u8 {
+        match m.wait_sample() {
+            Some(sample) => ((5 - (t_state + sample as u64) % 4) % 4) as u8,
+            None => 0,
+        }
+    }
+
+    fn n_int(&mut self) -> bool {
+        !self.gate_array.int_pending
+    }
+
+    fn int_ack(&mut self) -> Option<u8> {
+        self.gate_array.int_ack();
+        None
+    }
+}
+
+pub struct Cpc {
+    pub cpu: Z80<CpcBus>,
+    pub key_map: KeyMap,
+    // Scripted input, applied at the start of the frames
+    pub input: InputScript,
+    // Frames run since power on
+    pub frames: u64,
+}
+
+impl Cpc {
+    // `firmware` holds the 16 KiB OS ROM followed by the 16 KiB BASIC ROM
+    pub fn new(firmware: &[u8]) -> io::Result<Self> {
+        Self::with_ram_banks(firmware, 1)
+    }
+
+    // 64 KiB of base RAM plus `banks` expansion banks of 64 KiB (8 for a 512 KiB
+    // expansion)
+    pub fn with_ram_banks(firmware: &[u8], banks: usize) -> io::Result<Self> {
+        if firmware.len() != 2 * ROM_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the firmware image must hold the OS and BASIC ROMs (32 KiB)",
+            ));
+        }
+        let memory = CpcMemory::with_banks(&firmware[..ROM_SIZE], &firmware[ROM_SIZE..], banks);
+        let bus = CpcBus::new(memory);
+        let mut cpc = Self {
+            cpu: Z80::with_bus(bus),
+            key_map: KeyMap::default(),
+            input: InputScript::default(),
+            frames: 0,
+        };
+        cpc.reset();
+        Ok(cpc)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let firmware = std::fs::read(path)?;
+        Self::new(&firmware)
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        let bus = &mut self.cpu.bus;
+        bus.gate_array.reset();
+        bus.memory.set_ram_config(0xC0);
+        bus.memory.select_upper_rom(0);
+        bus.update_memory();
+        bus.ppi = Ppi8255::new();
+        bus.psg.reset();
+        bus.fdc.reset();
+        bus.fdc.motor = false;
+    }
+
+    // Drive 0 is A, drive 1 is B
+    pub fn insert_disk(&mut self, drive: usize, disk: Disk) {
+        self.cpu.bus.fdc.insert(drive, disk);
+    }
+
+    pub fn eject_disk(&mut self, drive: usize) -> Option<Disk> {
+        self.cpu.bus.fdc.eject(drive)
+    }
+
+    // Puts a 16 KiB ROM in one of the 256 upper ROM slots, slot 0 is BASIC and slot 7
+    // AMSDOS. A file with an AMSDOS header is accepted.
+    pub fn set_rom(&mut self, slot: u8, rom: &[u8]) -> io::Result<()> {
+        let rom = match rom.len() {
+            len if len == ROM_SIZE + 128 => &rom[128..],
+            len if len <= ROM_SIZE => rom,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "an upper ROM is 16 KiB at most",
+                ))
+            }
+        };
+        self.cpu.bus.memory.set_upper_rom(slot, rom);
+        Ok(())
+    }
+
+    pub fn load_rom<P: AsRef<Path>>(&mut self, slot: u8, path: P) -> io::Result<()> {
+        let rom = std::fs::read(path)?;
+        self.set_rom(slot, &rom)
+    }
+
+    pub fn remove_rom(&mut self, slot: u8) -> Option<Vec<u8>> {
+        self.cpu.bus.memory.remove_upper_rom(slot)
+    }
+
+    // Slots holding a ROM
+    pub fn rom_slots(&self) -> Vec<u8> {
+        let roms = &self.cpu.bus.memory.upper_roms;
+        (0..=255).filter(|&s| roms[s as usize].is_some()).collect()
+    }
+
+    // Inserts the tape and presses PLAY, it runs when the firmware starts the motor
+    pub fn insert_tape(&mut self, tape: Tape) {
+        self.cpu.bus.tape.insert(tape);
+        self.cpu.bus.tape.playing = true;
+    }
+
+    // Runs one instruction and the devices for the time it took.
+    // Returns the number of T-states used.
+    pub fn step(&mut self) -> u32 {
+        let start = self.cpu.clock;
+        // Internal T-states are not stretched, the next access waits for the Gate Array
+        self.cpu.execute();
+        while self.cpu.bus.us < self.cpu.clock / 4 {
+            self.cpu.bus.step_us();
+        }
+        (self.cpu.clock - start) as u32
+    }
+
+    pub fn press_key(&mut self, key: Key) {
+        self.cpu.bus.keyboard[key.row()] &= !key.mask();
+    }
+
+    pub fn release_key(&mut self, key: Key) {
+        self.cpu.bus.keyboard[key.row()] |= key.mask();
+    }
+
+    pub fn release_all_keys(&mut self) {
+        self.cpu.bus.keyboard = [0xFF; 10];
+    }
+
+    // Host key by its KeyboardEvent.code name, through `key_map`. Returns false if the
+    // key is not mapped.
+    pub fn host_key(&mut self, code: &str, pressed: bool) -> bool {
+        match (self.key_map.get(code), pressed) {
+            (Some(key), true) => self.press_key(key),
+            (Some(key), false) => self.release_key(key),
+            (None, _) => return false,
+        }
+        true
+    }
+
+    pub fn set_joystick(&mut self, port: usize, state: Joystick) {
+        self.cpu.bus.joysticks[port & 0x01] = state;
+    }
+
+    // Types the text from the given frame, for example RUN"DISC followed by a new line
+    pub fn type_text_at(&mut self, frame: u64, text: &str) -> u64 {
+        self.input.type_text(frame, text)
+    }
+
+    // Last frame, video::WIDTH by video::HEIGHT pixels
+    pub fn frame(&self) -> &[u32] {
+        &self.cpu.bus.video.frame
+    }
+
+    // Runs until the start of the next VSYNC, or for two frames if the CRTC does not
+    // generate any
+    pub fn run_frame(&mut self) {
+        for (key, pressed) in self.input.due(self.frames) {
+            match pressed {
+                true => self.press_key(key),
+                false => self.release_key(key),
+            }
+        }
+        self.frames += 1;
+        let start = self.cpu.bus.us;
+        self.cpu.bus.frame_done = false;
+        while !self.cpu.bus.frame_done && self.cpu.bus.us - start < 2 * FRAME_US {
+            self.step();
+        }
+    }
+}
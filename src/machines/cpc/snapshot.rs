@@ -0,0 +1,298 @@
+// Amstrad SNA snapshots, versions 1 to 3
+
+use super::Cpc;
+use crate::devices::crtc6845::CrtcType;
+use crate::z80::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const HEADER: &[u8] = b"MV - SNA";
+const HEADER_SIZE: usize = 0x100;
+const CHUNK_SIZE: usize = 0x10000;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Memory chunks of version 3 are compressed: &E5, count, byte repeats the byte and
+// &E5, 0 is a single &E5
+fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(CHUNK_SIZE);
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            0xE5 => {
+                let count = *data.get(i + 1).ok_or_else(|| invalid("SNA chunk truncated"))?;
+                if count == 0 {
+                    out.push(0xE5);
+                    i += 2;
+                } else {
+                    let byte = *data.get(i + 2).ok_or_else(|| invalid("SNA chunk truncated"))?;
+                    out.extend(std::iter::repeat_n(byte, count as usize));
+                    i += 3;
+                }
+            }
+            byte => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i];
+        let run = data[i..].iter().take(255).take_while(|&&b| b == byte).count();
+        if run > 2 || (byte == 0xE5 && run > 1) {
+            out.extend_from_slice(&[0xE5, run as u8, byte]);
+        } else if byte == 0xE5 {
+            out.extend_from_slice(&[0xE5, 0x00]);
+        } else {
+            out.push(byte);
+        }
+        i += match run > 2 || (byte == 0xE5 && run > 1) {
+            true => run,
+            false => 1,
+        };
+    }
+    out
+}
+
+impl Cpc {
+    pub fn load_sna_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.load_sna(&fs::read(path)?)
+    }
+
+    pub fn save_sna_file<P: AsRef<Path>>(&self, path: P, version: u8) -> io::Result<()> {
+        fs::write(path, self.save_sna(version)?)
+    }
+
+    // The snapshot is checked before the machine is changed
+    pub fn load_sna(&mut self, sna: &[u8]) -> io::Result<()> {
+        if sna.len() < HEADER_SIZE || !sna.starts_with(HEADER) {
+            return Err(invalid("not a SNA snapshot"));
+        }
+        let h = &sna[..HEADER_SIZE];
+        let version = h[0x10];
+        if !(1..=3).contains(&version) {
+            return Err(invalid("unknown SNA version"));
+        }
+        let ram_size = self.cpu.bus.memory.ram.len();
+        let mut ram = vec![0_u8; ram_size];
+        let dump_size = u16::from_le_bytes([h[0x6B], h[0x6C]]) as usize * 1024;
+        if dump_size > ram_size {
+            return Err(invalid("SNA memory larger than the RAM of the machine"));
+        }
+        if !matches!(dump_size, 0 | 0x10000 | 0x20000) || (dump_size == 0 && version < 3) {
+            return Err(invalid("SNA memory size must be 64 or 128 KiB"));
+        }
+        let dump = sna
+            .get(HEADER_SIZE..HEADER_SIZE + dump_size)
+            .ok_or_else(|| invalid("SNA memory truncated"))?;
+        ram[..dump_size].copy_from_slice(dump);
+
+        // Chunks of version 3
+        let mut pos = HEADER_SIZE + dump_size;
+        while version == 3 && pos < sna.len() {
+            let header = sna
+                .get(pos..pos + 8)
+                .ok_or_else(|| invalid("SNA chunk header truncated"))?;
+            let length = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+            let data = sna
+                .get(pos + 8..pos + 8 + length)
+                .ok_or_else(|| invalid("SNA chunk truncated"))?;
+            if header.starts_with(b"MEM") && header[3].is_ascii_digit() {
+                let bank = (header[3] - b'0') as usize;
+                let memory = match length {
+                    CHUNK_SIZE => data.to_vec(),
+                    _ => decompress(data)?,
+                };
+                if memory.len() != CHUNK_SIZE {
+                    return Err(invalid("SNA memory chunk is not 64 KiB"));
+                }
+                ram.get_mut(bank * CHUNK_SIZE..(bank + 1) * CHUNK_SIZE)
+                    .ok_or_else(|| invalid("SNA memory chunk beyond the RAM of the machine"))?
+                    .copy_from_slice(&memory);
+            }
+            pos += 8 + length;
+        }
+
+        // Z80
+        let reg = &mut self.cpu.reg;
+        reg.set_af(u16::from_le_bytes([h[0x11], h[0x12]]));
+        reg.set_bc(u16::from_le_bytes([h[0x13], h[0x14]]));
+        reg.set_de(u16::from_le_bytes([h[0x15], h[0x16]]));
+        reg.set_hl(u16::from_le_bytes([h[0x17], h[0x18]]));
+        reg.r = h[0x19];
+        reg.i = h[0x1A];
+        reg.set_ix(u16::from_le_bytes([h[0x1D], h[0x1E]]));
+        reg.set_iy(u16::from_le_bytes([h[0x1F], h[0x20]]));
+        reg.sp = u16::from_le_bytes([h[0x21], h[0x22]]);
+        reg.pc = u16::from_le_bytes([h[0x23], h[0x24]]);
+        reg.eaf = u16::from_le_bytes([h[0x26], h[0x27]]);
+        reg.ebc = u16::from_le_bytes([h[0x28], h[0x29]]);
+        reg.ede = u16::from_le_bytes([h[0x2A], h[0x2B]]);
+        reg.ehl = u16::from_le_bytes([h[0x2C], h[0x2D]]);
+        self.cpu.iff1 = h[0x1B] & 0x01 == 0x01;
+        self.cpu.iff2 = h[0x1C] & 0x01 == 0x01;
+        self.cpu.im = match h[0x25] {
+            1 => InterruptMode::IM_1,
+            2 => InterruptMode::IM_2,
+            _ => InterruptMode::IM_0,
+        };
+        self.cpu.n_halt = true;
+        self.cpu.p_inst = 0;
+
+        let bus = &mut self.cpu.bus;
+        bus.memory.ram = ram;
+        // Gate Array and PAL
+        for (pen, &colour) in h[0x2F..0x40].iter().enumerate() {
+            bus.gate_array.pens[pen] = colour & 0x1F;
+        }
+        bus.gate_array.write(0x80 | (h[0x40] & 0x0F));
+        bus.gate_array.mode = bus.gate_array.next_mode;
+        bus.gate_array.write(h[0x2E] & 0x1F);
+        bus.memory.set_ram_config(0xC0 | h[0x41]);
+        bus.memory.select_upper_rom(h[0x55]);
+        bus.update_memory();
+        // CRTC
+        for (reg, &value) in h[0x43..0x55].iter().enumerate() {
+            bus.crtc.select(reg as u8);
+            bus.crtc.write(value);
+        }
+        bus.crtc.select(h[0x42]);
+        // PPI and PSG
+        bus.ppi.control = h[0x59];
+        bus.ppi.port_a = h[0x56];
+        bus.ppi.port_b = h[0x57];
+        bus.ppi.port_c = h[0x58];
+        bus.psg.regs.copy_from_slice(&h[0x5B..0x6B]);
+        bus.psg.select(h[0x5A]);
+
+        if version == 3 {
+            bus.fdc.motor = h[0x9C] & 0x01 == 0x01;
+            for (drive, &track) in bus.fdc.drives.iter_mut().zip(&h[0x9D..0xA1]) {
+                drive.cylinder = track;
+            }
+            bus.crtc.crtc_type = match h[0xA4] {
+                1 => CrtcType::Type1,
+                2 => CrtcType::Type2,
+                3 => CrtcType::Type3,
+                4 => CrtcType::Type4,
+                _ => CrtcType::Type0,
+            };
+            let flags = h[0xB0];
+            bus.crtc
+                .set_counters(h[0xA9], h[0xAC], h[0xAB], flags & 0x02 != 0, flags & 0x01 != 0);
+            bus.gate_array.vsync_delay = h[0xB2];
+            bus.gate_array.int_counter = h[0xB3];
+            bus.gate_array.int_pending = h[0xB4] != 0;
+        }
+        Ok(())
+    }
+
+    pub fn save_sna(&self, version: u8) -> io::Result<Vec<u8>> {
+        if !(1..=3).contains(&version) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "SNA versions are 1 to 3",
+            ));
+        }
+        let ram = &self.cpu.bus.memory.ram;
+        if version < 3 && ram.len() > 0x20000 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only SNA version 3 holds more than 128 KiB",
+            ));
+        }
+        let mut h = vec![0_u8; HEADER_SIZE];
+        h[..HEADER.len()].copy_from_slice(HEADER);
+        h[0x10] = version;
+
+        let reg = &self.cpu.reg;
+        let pairs = [
+            (0x11, reg.get_af()),
+            (0x13, reg.get_bc()),
+            (0x15, reg.get_de()),
+            (0x17, reg.get_hl()),
+            (0x1D, reg.get_ix()),
+            (0x1F, reg.get_iy()),
+            (0x21, reg.sp),
+            (0x23, reg.pc),
+            (0x26, reg.eaf),
+            (0x28, reg.ebc),
+            (0x2A, reg.ede),
+            (0x2C, reg.ehl),
+        ];
+        for (offset, value) in pairs {
+            h[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        }
+        h[0x19] = reg.r;
+        h[0x1A] = reg.i;
+        h[0x1B] = self.cpu.iff1 as u8;
+        h[0x1C] = self.cpu.iff2 as u8;
+        h[0x25] = match self.cpu.im {
+            InterruptMode::IM_0 => 0,
+            InterruptMode::IM_1 => 1,
+            InterruptMode::IM_2 => 2,
+        };
+
+        let bus = &self.cpu.bus;
+        h[0x2E] = bus.gate_array.selected_pen as u8;
+        h[0x2F..0x40].copy_from_slice(&bus.gate_array.pens);
+        h[0x40] = bus.gate_array.config();
+        h[0x41] = bus.memory.ram_config & 0x3F;
+        h[0x42] = bus.crtc.selected as u8;
+        h[0x43..0x55].copy_from_slice(&bus.crtc.regs);
+        h[0x55] = bus.memory.upper_rom_select;
+        h[0x56] = bus.ppi.port_a;
+        h[0x57] = bus.ppi.port_b;
+        h[0x58] = bus.ppi.port_c;
+        h[0x59] = bus.ppi.control;
+        h[0x5A] = bus.psg.selected as u8;
+        h[0x5B..0x6B].copy_from_slice(&bus.psg.regs);
+        if version >= 2 {
+            // CPC 6128
+            h[0x6D] = 2;
+        }
+
+        let mut sna = h;
+        match version {
+            3 => {
+                sna[0x9C] = bus.fdc.motor as u8;
+                for (i, drive) in bus.fdc.drives.iter().enumerate() {
+                    sna[0x9D + i] = drive.cylinder;
+                }
+                sna[0xA4] = bus.crtc.crtc_type as u8;
+                sna[0xA9] = bus.crtc.hcc;
+                sna[0xAB] = bus.crtc.vcc;
+                sna[0xAC] = bus.crtc.vlc;
+                sna[0xB0] = bus.crtc.vsync as u8 | ((bus.crtc.hsync as u8) << 1);
+                sna[0xB2] = bus.gate_array.vsync_delay;
+                sna[0xB3] = bus.gate_array.int_counter;
+                sna[0xB4] = bus.gate_array.int_pending as u8;
+                for (bank, memory) in ram.chunks(CHUNK_SIZE).enumerate() {
+                    // A chunk of 64 KiB is read back uncompressed
+                    let mut data = compress(memory);
+                    if data.len() >= CHUNK_SIZE {
+                        data = memory.to_vec();
+                    }
+                    sna.extend_from_slice(format!("MEM{}", bank).as_bytes());
+                    sna.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                    sna.extend(data);
+                }
+            }
+            _ => {
+                let size = ram.len().min(0x20000);
+                sna[0x6B..0x6D].copy_from_slice(&((size / 1024) as u16).to_le_bytes());
+                sna.extend_from_slice(&ram[..size]);
+            }
+        }
+        Ok(sna)
+    }
+}
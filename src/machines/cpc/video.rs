@@ -0,0 +1,107 @@
+// Frame buffer built from the CRTC and Gate Array outputs, one character (16 pixels of
+// mode 2) per microsecond
+
+use super::gate_array::{GateArray, BORDER};
+use super::memory::CpcMemory;
+use crate::devices::crtc6845::Crtc6845;
+use crate::machines::image;
+use std::io;
+use std::path::Path;
+
+// 48 characters by 272 lines, including the borders
+pub const WIDTH: usize = 768;
+pub const HEIGHT: usize = 272;
+// Lines not shown after the start of the VSYNC
+const TOP_LINES: usize = 32;
+
+pub struct Video {
+    // 0x00RRGGBB pixels
+    pub frame: Vec<u32>,
+    // Position of the beam in characters and lines, from the end of the last HSYNC
+    // and the start of the last VSYNC
+    x: usize,
+    y: usize,
+}
+
+impl Default for Video {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Video {
+    pub fn new() -> Self {
+        Self {
+            frame: vec![0; WIDTH * HEIGHT],
+            x: 0,
+            y: 0,
+        }
+    }
+
+    pub fn hsync_end(&mut self) {
+        self.x = 0;
+        self.y += 1;
+    }
+
+    pub fn vsync_start(&mut self) {
+        self.y = 0;
+    }
+
+    // Draws the character at the current position of the beam
+    pub fn render(&mut self, crtc: &Crtc6845, gate_array: &GateArray, memory: &CpcMemory) {
+        let (x, y) = (self.x, self.y);
+        self.x += 1;
+        if x >= WIDTH / 16 || !(TOP_LINES..TOP_LINES + HEIGHT).contains(&y) {
+            return;
+        }
+        let start = (y - TOP_LINES) * WIDTH + x * 16;
+        let pixels = &mut self.frame[start..start + 16];
+        if crtc.hsync || crtc.vsync {
+            pixels.fill(0);
+            return;
+        }
+        if !crtc.disp_en {
+            pixels.fill(gate_array.pen_rgb(BORDER));
+            return;
+        }
+        // Bits 13-12 of MA select the 16 KiB page, RA the 2 KiB block of the line
+        let ma = crtc.ma as usize;
+        let addr = ((ma & 0x3000) << 2) | ((crtc.ra as usize & 0x07) << 11) | ((ma & 0x03FF) << 1);
+        for (i, byte) in [addr, addr + 1].iter().enumerate() {
+            let data = memory.video_read(*byte as u16);
+            let out = &mut pixels[i * 8..i * 8 + 8];
+            match gate_array.mode {
+                0 | 3 => {
+                    for p in 0..2 {
+                        let d = data << p;
+                        let mut pen = ((d >> 7) & 1) | ((d >> 2) & 2) | ((d >> 3) & 4) | ((d << 2) & 8);
+                        // Mode 3 only has 4 pens
+                        if gate_array.mode == 3 {
+                            pen &= 0x03;
+                        }
+                        out[p * 4..p * 4 + 4].fill(gate_array.pen_rgb(pen as usize));
+                    }
+                }
+                1 => {
+                    for p in 0..4 {
+                        let pen = ((data >> (7 - p)) & 1) | (((data >> (3 - p)) & 1) << 1);
+                        out[p * 2..p * 2 + 2].fill(gate_array.pen_rgb(pen as usize));
+                    }
+                }
+                _ => {
+                    for (p, pixel) in out.iter_mut().enumerate() {
+                        *pixel = gate_array.pen_rgb(((data >> (7 - p)) & 1) as usize);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn save_ppm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        image::save_ppm(path, WIDTH, HEIGHT, &self.frame)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        image::save_png(path, WIDTH, HEIGHT, &self.frame)
+    }
+}
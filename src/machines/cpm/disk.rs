@@ -0,0 +1,195 @@
+// Raw CP/M disk images (.img, .dsk): the 128 byte sectors of each track one after the
+// other, and the disk parameter blocks describing their formats
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const SECTOR_SIZE: usize = 128;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DiskFormat {
+    pub name: &'static str,
+    pub tracks: usize,
+    // 128 byte sectors per track, numbered from `first_sector`
+    pub sectors: usize,
+    pub first_sector: usize,
+    // Logical to physical sector interleave, 0 when the sectors are not translated
+    pub skew: usize,
+    pub block_size: usize,
+    pub dir_entries: usize,
+    // System tracks holding the CCP and the BDOS
+    pub reserved_tracks: usize,
+    // The directory of removable disks is checksummed to detect a disk change
+    pub removable: bool,
+}
+
+// 8" single sided single density, the standard CP/M 2.2 distribution format
+pub const IBM_3740: DiskFormat = DiskFormat {
+    name: "IBM 3740",
+    tracks: 77,
+    sectors: 26,
+    first_sector: 1,
+    skew: 6,
+    block_size: 1024,
+    dir_entries: 64,
+    reserved_tracks: 2,
+    removable: true,
+};
+
+// 4 MiB hard disk as used by z80pack and other emulators
+pub const HD_4MB: DiskFormat = DiskFormat {
+    name: "4 MiB hard disk",
+    tracks: 255,
+    sectors: 128,
+    first_sector: 0,
+    skew: 0,
+    block_size: 2048,
+    dir_entries: 1024,
+    reserved_tracks: 0,
+    removable: false,
+};
+
+pub const FORMATS: [DiskFormat; 2] = [IBM_3740, HD_4MB];
+
+impl DiskFormat {
+    pub fn size(&self) -> usize {
+        self.tracks * self.sectors * SECTOR_SIZE
+    }
+
+    // Format of an image from its size
+    pub fn from_size(size: usize) -> Option<DiskFormat> {
+        FORMATS.iter().find(|f| f.size() == size).copied()
+    }
+
+    // Physical sector of each logical sector, empty without skew. Each sector is put
+    // `skew` sectors after the previous one, or after it when already used.
+    pub fn translation(&self) -> Vec<u8> {
+        if self.skew == 0 {
+            return Vec::new();
+        }
+        let mut table: Vec<u8> = Vec::with_capacity(self.sectors);
+        let mut used = vec![false; self.sectors];
+        let mut sector = 0;
+        for _ in 0..self.sectors {
+            while used[sector] {
+                sector = (sector + 1) % self.sectors;
+            }
+            used[sector] = true;
+            table.push((sector + self.first_sector) as u8);
+            sector = (sector + self.skew) % self.sectors;
+        }
+        table
+    }
+
+    // Highest block number
+    pub fn dsm(&self) -> usize {
+        (self.tracks - self.reserved_tracks) * self.sectors * SECTOR_SIZE / self.block_size - 1
+    }
+
+    // Bytes of the checksum vector and of the allocation vector
+    pub fn cks(&self) -> usize {
+        match self.removable {
+            true => self.dir_entries / 4,
+            false => 0,
+        }
+    }
+
+    pub fn alv_size(&self) -> usize {
+        self.dsm() / 8 + 1
+    }
+
+    // Disk parameter block: SPT, BSH, BLM, EXM, DSM, DRM, AL0, AL1, CKS, OFF
+    pub fn dpb(&self) -> [u8; 15] {
+        let bsh = self.block_size.trailing_zeros() - 7;
+        let blm = self.block_size / SECTOR_SIZE - 1;
+        let dsm = self.dsm();
+        // Extents of 16 KiB per directory entry, less when the block numbers take 2 bytes
+        let exm = match dsm {
+            0..=255 => self.block_size / 1024 - 1,
+            _ => self.block_size / 2048 - 1,
+        };
+        let dir_blocks = (self.dir_entries * 32).div_ceil(self.block_size);
+        let al = (0xFFFF_u32 << (16 - dir_blocks.min(16))) as u16;
+        let mut dpb = [0_u8; 15];
+        dpb[0..2].copy_from_slice(&(self.sectors as u16).to_le_bytes());
+        dpb[2] = bsh as u8;
+        dpb[3] = blm as u8;
+        dpb[4] = exm as u8;
+        dpb[5..7].copy_from_slice(&(dsm as u16).to_le_bytes());
+        dpb[7..9].copy_from_slice(&(self.dir_entries as u16 - 1).to_le_bytes());
+        dpb[9..11].copy_from_slice(&al.to_be_bytes());
+        dpb[11..13].copy_from_slice(&(self.cks() as u16).to_le_bytes());
+        dpb[13..15].copy_from_slice(&(self.reserved_tracks as u16).to_le_bytes());
+        dpb
+    }
+}
+
+pub struct CpmDisk {
+    pub format: DiskFormat,
+    pub data: Vec<u8>,
+    pub write_protected: bool,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl CpmDisk {
+    // Blank formatted disk
+    pub fn new(format: DiskFormat) -> Self {
+        Self {
+            format,
+            data: vec![0xE5; format.size()],
+            write_protected: false,
+        }
+    }
+
+    pub fn from_bytes(data: &[u8], format: DiskFormat) -> io::Result<Self> {
+        if data.len() != format.size() {
+            return Err(invalid("the size of the image does not match its format"));
+        }
+        Ok(Self {
+            format,
+            data: data.to_vec(),
+            write_protected: false,
+        })
+    }
+
+    // The format is found from the size of the image
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let format =
+            DiskFormat::from_size(data.len()).ok_or_else(|| invalid("unknown CP/M disk format"))?;
+        Self::from_bytes(&data, format)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, &self.data)
+    }
+
+    fn offset(&self, track: usize, sector: usize) -> Option<usize> {
+        let format = &self.format;
+        let sector = sector.checked_sub(format.first_sector)?;
+        if track >= format.tracks || sector >= format.sectors {
+            return None;
+        }
+        Some((track * format.sectors + sector) * SECTOR_SIZE)
+    }
+
+    pub fn read_sector(&self, track: usize, sector: usize) -> Option<&[u8]> {
+        let offset = self.offset(track, sector)?;
+        Some(&self.data[offset..offset + SECTOR_SIZE])
+    }
+
+    // Returns false if the sector does not exist or the disk is write protected
+    pub fn write_sector(&mut self, track: usize, sector: usize, data: &[u8]) -> bool {
+        match self.offset(track, sector) {
+            Some(offset) if !self.write_protected => {
+                self.data[offset..offset + SECTOR_SIZE].copy_from_slice(&data[..SECTOR_SIZE]);
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,417 @@
+// Generic CP/M 2.2 machine: 64 KiB of RAM, the CCP and the BDOS of a real system image and
+// a BIOS whose entry points are trapped to host code for the console and the disks
+pub mod disk;
+
+use crate::z80::*;
+use disk::{CpmDisk, SECTOR_SIZE};
+use std::collections::VecDeque;
+use std::io;
+
+pub const DRIVES: usize = 16;
+// The CCP and the BDOS take 5.5 KiB, the BIOS follows them
+pub const SYSTEM_SIZE: usize = 0x1600;
+const BDOS_OFFSET: usize = 0x0800;
+// Sectors on the system tracks: the cold boot loader, then the CCP and the BDOS
+const BOOT_SECTORS: usize = 1;
+
+// BIOS entry points, in the order of the jump table
+const BOOT: u16 = 0;
+const WBOOT: u16 = 1;
+const CONST: u16 = 2;
+const CONIN: u16 = 3;
+const CONOUT: u16 = 4;
+const LIST: u16 = 5;
+const PUNCH: u16 = 6;
+const READER: u16 = 7;
+const HOME: u16 = 8;
+const SELDSK: u16 = 9;
+const SETTRK: u16 = 10;
+const SETSEC: u16 = 11;
+const SETDMA: u16 = 12;
+const READ: u16 = 13;
+const WRITE: u16 = 14;
+const LISTST: u16 = 15;
+const SECTRAN: u16 = 16;
+const ENTRIES: u16 = 17;
+
+// Layout of the BIOS: the jump table, the RET each entry jumps to (where the traps are),
+// the directory buffer shared by the drives, then the tables of each drive
+const STUBS: u16 = 0x40;
+const DIRBUF: u16 = 0x60;
+const TABLES: u16 = 0xE0;
+
+// 64 KiB of RAM, no IO devices
+pub struct CpmBus {
+    pub memory: Vec<u8>,
+}
+
+impl Default for CpmBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CpmBus {
+    pub fn new() -> Self {
+        Self {
+            memory: vec![0_u8; 0x10000],
+        }
+    }
+}
+
+impl Bus for CpmBus {
+    fn read(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.memory[addr as usize] = data;
+    }
+
+    fn read_io(&mut self, _addr: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_io(&mut self, _addr: u16, _data: u8) {}
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Address of the CCP of a CCP + BDOS image, found from the jump at the BDOS entry
+// (BDOS + 6) into the BDOS, which starts on a page
+pub fn system_base(system: &[u8]) -> Option<u16> {
+    if system.len() < SYSTEM_SIZE || system[0] != 0xC3 || system[BDOS_OFFSET + 6] != 0xC3 {
+        return None;
+    }
+    let entry = u16::from_le_bytes([system[BDOS_OFFSET + 7], system[BDOS_OFFSET + 8]]);
+    let ccp = (entry & 0xFF00).checked_sub(BDOS_OFFSET as u16)?;
+    // Room for the BIOS above
+    match ccp as usize + SYSTEM_SIZE + TABLES as usize <= 0x10000 {
+        true => Some(ccp),
+        false => None,
+    }
+}
+
+pub struct Cpm {
+    pub cpu: Z80<CpmBus>,
+    pub disks: Vec<Option<CpmDisk>>,
+    // Console, read by CONIN and written by CONOUT
+    pub console_in: VecDeque<u8>,
+    pub console_out: Vec<u8>,
+    // LIST, PUNCH and READER devices
+    pub printer: Vec<u8>,
+    pub punch: Vec<u8>,
+    pub reader: VecDeque<u8>,
+    // Set when CONIN waits for a key
+    pub waiting: bool,
+    // Set when the BIOS could not be rebuilt by BOOT, the CPU is then halted
+    pub boot_error: Option<io::Error>,
+    // CCP and BDOS, copied again at each warm boot
+    system: Vec<u8>,
+    ccp: u16,
+    // Disk parameter header of each drive, 0 without a disk
+    dph: [u16; DRIVES],
+    drive: usize,
+    track: u16,
+    sector: u16,
+    dma: u16,
+}
+
+impl Default for Cpm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cpm {
+    pub fn new() -> Self {
+        Self {
+            cpu: Z80::with_bus(CpmBus::new()),
+            disks: (0..DRIVES).map(|_| None).collect(),
+            console_in: VecDeque::new(),
+            console_out: Vec::new(),
+            printer: Vec::new(),
+            punch: Vec::new(),
+            reader: VecDeque::new(),
+            waiting: false,
+            boot_error: None,
+            system: Vec::new(),
+            ccp: 0,
+            dph: [0; DRIVES],
+            drive: 0,
+            track: 0,
+            sector: 0,
+            dma: 0x0080,
+        }
+    }
+
+    // Address of the CCP, of the BDOS and of the BIOS once booted
+    pub fn ccp(&self) -> u16 {
+        self.ccp
+    }
+
+    pub fn bdos(&self) -> u16 {
+        self.ccp + BDOS_OFFSET as u16
+    }
+
+    pub fn bios(&self) -> u16 {
+        self.ccp + SYSTEM_SIZE as u16
+    }
+
+    // Drive 0 is A:. The disk tables of the BIOS are rebuilt once booted.
+    // The previous disk is put back when the tables of the new one do not fit.
+    pub fn insert_disk(&mut self, drive: usize, disk: CpmDisk) -> io::Result<()> {
+        let slot = self
+            .disks
+            .get_mut(drive)
+            .ok_or_else(|| invalid("no such drive"))?;
+        let previous = slot.replace(disk);
+        if let Err(error) = self.rebuild() {
+            self.disks[drive] = previous;
+            self.rebuild()?;
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    pub fn eject_disk(&mut self, drive: usize) -> io::Result<Option<CpmDisk>> {
+        let disk = self
+            .disks
+            .get_mut(drive)
+            .ok_or_else(|| invalid("no such drive"))?
+            .take();
+        self.rebuild()?;
+        Ok(disk)
+    }
+
+    fn rebuild(&mut self) -> io::Result<()> {
+        match self.system.is_empty() {
+            true => Ok(()),
+            false => self.build_bios(),
+        }
+    }
+
+    // Cold boot from the system tracks of the disk in A:, as the boot loader in its
+    // first sector would
+    pub fn boot(&mut self) -> io::Result<()> {
+        let disk = self.disks[0]
+            .as_ref()
+            .ok_or_else(|| invalid("no disk in drive A"))?;
+        let format = disk.format;
+        let sectors = SYSTEM_SIZE / SECTOR_SIZE;
+        if format.reserved_tracks * format.sectors < BOOT_SECTORS + sectors {
+            return Err(invalid("the disk has no system tracks"));
+        }
+        let mut system = Vec::with_capacity(SYSTEM_SIZE);
+        for n in BOOT_SECTORS..BOOT_SECTORS + sectors {
+            let (track, sector) = (n / format.sectors, n % format.sectors);
+            // Always some, within the reserved tracks
+            if let Some(data) = disk.read_sector(track, sector + format.first_sector) {
+                system.extend_from_slice(data);
+            }
+        }
+        self.load_system(&system)
+    }
+
+    // Cold boot with a CCP + BDOS image, e.g. the CPM.SYS of a distribution
+    pub fn load_system(&mut self, system: &[u8]) -> io::Result<()> {
+        self.ccp = system_base(system).ok_or_else(|| invalid("not a CP/M 2.2 system image"))?;
+        self.system = system[..SYSTEM_SIZE].to_vec();
+        self.cpu.reset();
+        self.cpu.traps = (0..ENTRIES).map(|n| self.bios() + STUBS + n).collect();
+        self.build_bios()?;
+        self.start(true);
+        Ok(())
+    }
+
+    // Jump table, directory buffer, and for each drive its disk parameter header, disk
+    // parameter block, sector translation table, checksum and allocation vectors
+    fn build_bios(&mut self) -> io::Result<()> {
+        let bios = self.bios();
+        for n in 0..ENTRIES {
+            let [lo, hi] = (bios + STUBS + n).to_le_bytes();
+            self.poke(bios + 3 * n, &[0xC3, lo, hi]);
+            self.poke(bios + STUBS + n, &[0xC9]);
+        }
+        let mut free = (bios + TABLES) as usize;
+        for drive in 0..DRIVES {
+            self.dph[drive] = 0;
+            let Some(format) = self.disks[drive].as_ref().map(|d| d.format) else {
+                continue;
+            };
+            let xlt = format.translation();
+            let dph = free;
+            let dpb = dph + 16;
+            let xlt_addr = dpb + 15;
+            let csv = xlt_addr + xlt.len();
+            let alv = csv + format.cks();
+            free = alv + format.alv_size();
+            if free > 0x10000 {
+                return Err(invalid("the disk tables do not fit above the BIOS"));
+            }
+            let xlt_addr = if xlt.is_empty() { 0 } else { xlt_addr };
+            let mut header = Vec::with_capacity(16);
+            for word in [xlt_addr, 0, 0, 0, (bios + DIRBUF) as usize, dpb, csv, alv] {
+                header.extend_from_slice(&(word as u16).to_le_bytes());
+            }
+            self.poke(dph as u16, &header);
+            self.poke(dpb as u16, &format.dpb());
+            self.poke(xlt_addr as u16, &xlt);
+            self.dph[drive] = dph as u16;
+        }
+        Ok(())
+    }
+
+    fn poke(&mut self, addr: u16, data: &[u8]) {
+        for (i, &byte) in data.iter().enumerate() {
+            self.cpu.bus.write(addr.wrapping_add(i as u16), byte);
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.cpu.bus.read(addr)
+    }
+
+    // Copies the CCP and the BDOS, sets up page zero and jumps to the CCP with the
+    // current drive in C
+    fn start(&mut self, cold: bool) {
+        let system = std::mem::take(&mut self.system);
+        self.poke(self.ccp, &system);
+        self.system = system;
+        let [lo, hi] = (self.bios() + 3).to_le_bytes();
+        self.poke(0x0000, &[0xC3, lo, hi]);
+        if cold {
+            // IOBYTE, drive and user
+            self.poke(0x0003, &[0x00, 0x00]);
+        }
+        let [lo, hi] = (self.bdos() + 6).to_le_bytes();
+        self.poke(0x0005, &[0xC3, lo, hi]);
+        self.dma = 0x0080;
+        self.cpu.reg.c = self.peek(0x0004);
+        self.cpu.reg.sp = 0x0080;
+        self.cpu.reg.pc = self.ccp;
+        self.cpu.n_halt = true;
+    }
+
+    // Runs one instruction, or the BIOS routine the CPU has reached.
+    // Returns the number of T-states used.
+    pub fn step(&mut self) -> u32 {
+        let start = self.cpu.clock;
+        self.waiting = false;
+        self.cpu.execute();
+        if let Some(addr) = self.cpu.trapped {
+            let stubs = self.bios() + STUBS;
+            if (stubs..stubs + ENTRIES).contains(&addr) {
+                self.cpu.trapped = None;
+                self.bios_call(addr - stubs);
+            }
+        }
+        (self.cpu.clock - start) as u32
+    }
+
+    // Runs up to `instructions` instructions, less if CONIN waits for a key or the CPU
+    // halts. Returns the number run.
+    pub fn run(&mut self, instructions: usize) -> usize {
+        for n in 0..instructions {
+            self.step();
+            if self.waiting || !self.cpu.n_halt {
+                return n + 1;
+            }
+        }
+        instructions
+    }
+
+    // Types the text on the console, the line feeds as carriage returns
+    pub fn type_text(&mut self, text: &str) {
+        let keys = text.bytes().map(|b| if b == b'\n' { b'\r' } else { b });
+        self.console_in.extend(keys);
+    }
+
+    // Takes what was written on the console so far
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.console_out)
+    }
+
+    fn ret(&mut self) {
+        let sp = self.cpu.reg.sp;
+        self.cpu.reg.pc = u16::from_le_bytes([self.peek(sp), self.peek(sp.wrapping_add(1))]);
+        self.cpu.reg.sp = sp.wrapping_add(2);
+    }
+
+    fn bios_call(&mut self, entry: u16) {
+        let reg = &mut self.cpu.reg;
+        let c = reg.c;
+        let bc = reg.get_bc();
+        match entry {
+            BOOT => {
+                if let Err(error) = self.build_bios() {
+                    self.boot_error = Some(error);
+                    self.cpu.n_halt = false;
+                    return;
+                }
+                return self.start(true);
+            }
+            WBOOT => return self.start(false),
+            CONST => reg.a = if self.console_in.is_empty() { 0x00 } else { 0xFF },
+            CONIN => match self.console_in.pop_front() {
+                Some(key) => reg.a = key & 0x7F,
+                // Called again at the next step
+                None => {
+                    self.waiting = true;
+                    return;
+                }
+            },
+            CONOUT => self.console_out.push(c),
+            LIST => self.printer.push(c),
+            PUNCH => self.punch.push(c),
+            READER => reg.a = self.reader.pop_front().unwrap_or(0x1A),
+            HOME => self.track = 0,
+            SELDSK => {
+                let dph = self.dph.get(c as usize).copied().unwrap_or(0);
+                if dph != 0 {
+                    self.drive = c as usize;
+                }
+                reg.set_hl(dph);
+            }
+            SETTRK => self.track = bc,
+            SETSEC => self.sector = bc,
+            SETDMA => self.dma = bc,
+            READ => {
+                let data = self.disks[self.drive]
+                    .as_ref()
+                    .and_then(|d| d.read_sector(self.track as usize, self.sector as usize))
+                    .map(|d| d.to_vec());
+                self.cpu.reg.a = match data {
+                    Some(data) => {
+                        self.poke(self.dma, &data);
+                        0
+                    }
+                    None => 1,
+                };
+            }
+            WRITE => {
+                let data: Vec<u8> = (0..SECTOR_SIZE as u16)
+                    .map(|i| self.peek(self.dma.wrapping_add(i)))
+                    .collect();
+                let (track, sector) = (self.track as usize, self.sector as usize);
+                let written = self.disks[self.drive]
+                    .as_mut()
+                    .is_some_and(|d| d.write_sector(track, sector, &data));
+                self.cpu.reg.a = if written { 0 } else { 1 };
+            }
+            LISTST => reg.a = 0xFF,
+            SECTRAN => {
+                let table = reg.get_de();
+                let sector = match table {
+                    0 => bc,
+                    _ => self.peek(table.wrapping_add(bc)) as u16,
+                };
+                self.cpu.reg.set_hl(sector);
+            }
+            _ => {}
+        }
+        self.ret();
+    }
+}
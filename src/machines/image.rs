@@ -0,0 +1,88 @@
+// Screenshots of the frame buffers, pixels are 0x00RRGGBB
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub fn write_ppm<W: Write>(writer: &mut W, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", width, height)?;
+    for &pixel in &pixels[..width * height] {
+        writer.write_all(&pixel.to_be_bytes()[1..])?;
+    }
+    Ok(())
+}
+
+pub fn save_ppm<P: AsRef<Path>>(path: P, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_ppm(&mut writer, width, height, pixels)?;
+    writer.flush()
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for &d in data {
+        a = (a + d as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut chunk = kind.to_vec();
+    chunk.extend_from_slice(data);
+    writer.write_all(&chunk)?;
+    writer.write_all(&crc32(&chunk).to_be_bytes())
+}
+
+// 8-bit RGB PNG, the image data is stored without compression
+pub fn write_png<W: Write>(writer: &mut W, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+    writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Bit depth 8, truecolour, deflate, no filter, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(writer, b"IHDR", &header)?;
+
+    let mut raw = Vec::with_capacity(height * (1 + width * 3));
+    for line in pixels[..width * height].chunks(width) {
+        // Filter type none
+        raw.push(0);
+        for &pixel in line {
+            raw.extend_from_slice(&pixel.to_be_bytes()[1..]);
+        }
+    }
+    // zlib stream of stored deflate blocks
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(0xFFFF).count();
+    for (i, block) in raw.chunks(0xFFFF).enumerate() {
+        zlib.push((i + 1 == blocks) as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+    write_chunk(writer, b"IDAT", &zlib)?;
+    write_chunk(writer, b"IEND", &[])
+}
+
+pub fn save_png<P: AsRef<Path>>(path: P, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_png(&mut writer, width, height, pixels)?;
+    writer.flush()
+}
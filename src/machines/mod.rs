@@ -0,0 +1,5 @@
+pub mod cpc;
+pub mod cpm;
+pub mod image;
+pub mod spectrum128;
+pub mod spectrum48;
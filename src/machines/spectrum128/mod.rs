@@ -0,0 +1,200 @@
+// Sinclair ZX Spectrum 128K and +2: the 48K ULA with 128 KiB of paged RAM, two ROMs and
+// the AY-3-8912
+pub mod snapshot;
+
+use crate::devices::ay38910::Ay38910;
+use crate::m_cycles::MCycle;
+use crate::machines::spectrum48::ula::{SCREEN_SIZE, TIMING_128K};
+use crate::machines::spectrum48::{Spectrum, SpectrumBus, SpectrumCore};
+use crate::z80::*;
+use std::io;
+use std::path::Path;
+
+pub const CPU_CLOCK: u32 = 3_546_900;
+// The PSG runs at half the CPU clock
+pub const PSG_CLOCK: u32 = CPU_CLOCK / 2;
+pub const ROM_SIZE: usize = 0x4000;
+pub const BANK_SIZE: usize = 0x4000;
+pub const BANKS: usize = 8;
+
+pub struct Spectrum128Bus {
+    // ROM 0 holds the 128K editor, ROM 1 the 48K BASIC
+    pub roms: [Vec<u8>; 2],
+    // Banks 0-7 of 16 KiB. Bank 5 is at 0x4000, bank 2 at 0x8000.
+    pub ram: Vec<u8>,
+    // Last value written to &7FFD. Bits 2-0: bank at 0xC000, 3: screen in bank 7,
+    // 4: ROM 1, 5: paging locked until reset.
+    pub paging: u8,
+    pub core: SpectrumCore,
+    pub psg: Ay38910,
+}
+
+impl Spectrum128Bus {
+    pub fn new(roms: &[u8]) -> Self {
+        Self {
+            roms: [
+                roms[..ROM_SIZE].to_vec(),
+                roms[ROM_SIZE..2 * ROM_SIZE].to_vec(),
+            ],
+            ram: vec![0_u8; BANK_SIZE * BANKS],
+            paging: 0x00,
+            core: SpectrumCore::new(TIMING_128K, CPU_CLOCK),
+            psg: Ay38910::with_clock(PSG_CLOCK),
+        }
+    }
+
+    // Bank of RAM mapped at 0x4000-0xFFFF, by 16 KiB page
+    pub fn bank(&self, page: usize) -> usize {
+        match page {
+            1 => 5,
+            2 => 2,
+            _ => (self.paging & 0x07) as usize,
+        }
+    }
+
+    pub fn rom_selected(&self) -> usize {
+        ((self.paging >> 4) & 0x01) as usize
+    }
+
+    pub fn paging_locked(&self) -> bool {
+        self.paging & 0x20 == 0x20
+    }
+
+    // Ignored once locked
+    pub fn write_paging(&mut self, data: u8) {
+        if !self.paging_locked() {
+            self.sync(self.core.clock);
+            self.paging = data;
+        }
+    }
+
+    // Bank 5, or bank 7 for the shadow screen
+    pub fn screen_bank(&self) -> usize {
+        match self.paging & 0x08 {
+            0 => 5,
+            _ => 7,
+        }
+    }
+
+    pub fn screen(&self) -> &[u8] {
+        let start = self.screen_bank() * BANK_SIZE;
+        &self.ram[start..start + SCREEN_SIZE]
+    }
+
+    // Banks 1, 3, 5 and 7 are shared with the ULA
+    fn contended(&self, addr: u16) -> bool {
+        let page = addr as usize >> 14;
+        page != 0 && self.bank(page) & 0x01 == 0x01
+    }
+}
+
+impl SpectrumBus for Spectrum128Bus {
+    fn core(&self) -> &SpectrumCore {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut SpectrumCore {
+        &mut self.core
+    }
+
+    // The PSG too
+    fn sync(&mut self, t: u64) {
+        if t > self.core.synced {
+            self.psg.run((t / 2 - self.core.synced / 2) as u32);
+        }
+        let start = self.screen_bank() * BANK_SIZE;
+        self.core.sync(t, &self.ram[start..start + SCREEN_SIZE]);
+    }
+
+    // LD-BYTES is in ROM 1
+    fn ld_bytes_paged(&self) -> bool {
+        self.rom_selected() == 1
+    }
+}
+
+impl Bus for Spectrum128Bus {
+    fn read(&self, addr: u16) -> u8 {
+        let offset = addr as usize & (BANK_SIZE - 1);
+        match addr as usize >> 14 {
+            0 => self.roms[self.rom_selected()][offset],
+            page => self.ram[self.bank(page) * BANK_SIZE + offset],
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        let page = addr as usize >> 14;
+        if page != 0 {
+            let offset = self.bank(page) * BANK_SIZE + (addr as usize & (BANK_SIZE - 1));
+            self.ram[offset] = data;
+        }
+    }
+
+    // The ULA answers to the even ports, the PSG to &FFFD
+    fn read_io(&mut self, addr: u16) -> u8 {
+        let start = self.screen_bank() * BANK_SIZE;
+        let screen = &self.ram[start..start + SCREEN_SIZE];
+        if addr & 0x0001 == 0 {
+            return self.core.read_ula(addr, screen);
+        }
+        if addr & 0xC002 == 0xC000 {
+            return self.psg.read();
+        }
+        self.core.floating_bus(screen)
+    }
+
+    // Paging &7FFD (A15 and A1 low), PSG register select &FFFD and write &BFFD
+    fn write_io(&mut self, addr: u16, data: u8) {
+        if addr & 0x0001 == 0 {
+            let start = self.screen_bank() * BANK_SIZE;
+            self.core
+                .write_ula(data, &self.ram[start..start + SCREEN_SIZE]);
+        }
+        if addr & 0x8002 == 0 {
+            self.write_paging(data);
+        }
+        match addr & 0xC002 {
+            0xC000 => self.psg.select(data),
+            0x8000 => {
+                self.sync(self.core.clock);
+                self.psg.write(data);
+            }
+            _ => {}
+        }
+    }
+
+    fn wait_states(&mut self, m: &MCycle, t_state: u64) -> u8 {
+        let contended = self.contended(m.addr);
+        self.core.wait_states(m, t_state, contended)
+    }
+
+    fn n_int(&mut self) -> bool {
+        self.core.n_int()
+    }
+
+    // Paging is unlocked and ROM 0 selected
+    fn reset(&mut self) {
+        self.paging = 0x00;
+        self.core.reset();
+        self.psg.reset();
+    }
+}
+
+pub type Spectrum128 = Spectrum<Spectrum128Bus>;
+
+impl Spectrum128 {
+    // `roms` holds ROM 0 followed by ROM 1 (32 KiB), from a 128K or a +2
+    pub fn new(roms: &[u8]) -> io::Result<Self> {
+        if roms.len() != 2 * ROM_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the ROM image must hold ROM 0 and ROM 1 (32 KiB)",
+            ));
+        }
+        Ok(Self::with_bus(Spectrum128Bus::new(roms)))
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let roms = std::fs::read(path)?;
+        Self::new(&roms)
+    }
+}
@@ -0,0 +1,262 @@
+// Spectrum SNA and Z80 snapshots. 48K snapshots are run in 48K mode: ROM 1 selected, bank 0
+// at 0xC000 and paging locked.
+
+use super::{Spectrum128, BANK_SIZE, BANKS};
+use crate::z80::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const SNA_HEADER: usize = 27;
+const SNA_48K: usize = SNA_HEADER + 3 * BANK_SIZE;
+const PAGING_48K: u8 = 0x30;
+// Banks at 0x4000, 0x8000 and 0xC000 in 48K mode
+const BANKS_48K: [usize; 3] = [5, 2, 0];
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn word(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+// Z80 memory blocks are compressed: ED ED count byte repeats the byte. Version 1 ends
+// with 00 ED ED 00.
+fn decompress(data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(size);
+    let mut i = 0;
+    while i < data.len() && out.len() < size {
+        if data[i..].starts_with(&[0xED, 0xED]) {
+            let run = data
+                .get(i + 2..i + 4)
+                .ok_or_else(|| invalid("Z80 block truncated"))?;
+            out.extend(std::iter::repeat_n(run[1], run[0] as usize));
+            i += 4;
+        } else {
+            out.push(data[i]);
+            i += 1;
+        }
+    }
+    if out.len() != size {
+        return Err(invalid("Z80 block does not decompress to its size"));
+    }
+    Ok(out)
+}
+
+// Registers, paging and border read from a snapshot, applied once the whole file is
+// checked
+struct State {
+    ram: Vec<u8>,
+    paging: u8,
+    border: u8,
+    psg: Option<(u8, [u8; 16])>,
+}
+
+impl Spectrum128 {
+    pub fn load_sna_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.load_sna(&fs::read(path)?)
+    }
+
+    pub fn load_z80_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.load_z80(&fs::read(path)?)
+    }
+
+    // 48K SNA (49179 bytes) or 128K SNA (131103 or 147487 bytes when the paged bank is
+    // 2 or 5). The snapshot is checked before the machine is changed.
+    pub fn load_sna(&mut self, sna: &[u8]) -> io::Result<()> {
+        let mut ram = vec![0_u8; BANK_SIZE * BANKS];
+        let h = sna.get(..SNA_HEADER).ok_or_else(|| invalid("not a SNA snapshot"))?;
+        let paging = match sna.len() {
+            SNA_48K => PAGING_48K,
+            len if len > SNA_48K + 4 => sna[SNA_48K + 2],
+            _ => return Err(invalid("not a 48K or 128K SNA snapshot")),
+        };
+        // 0x4000-0xFFFF, then for 128K snapshots the banks not saved yet, in order
+        let paged = (paging & 0x07) as usize;
+        for (i, bank) in [5, 2, paged].into_iter().enumerate() {
+            let start = SNA_HEADER + i * BANK_SIZE;
+            ram[bank * BANK_SIZE..(bank + 1) * BANK_SIZE]
+                .copy_from_slice(&sna[start..start + BANK_SIZE]);
+        }
+        if sna.len() > SNA_48K {
+            let others: Vec<usize> = (0..BANKS)
+                .filter(|&b| b != 5 && b != 2 && b != paged)
+                .collect();
+            if sna.len() != SNA_48K + 4 + others.len() * BANK_SIZE {
+                return Err(invalid("128K SNA snapshot of the wrong size"));
+            }
+            for (i, bank) in others.into_iter().enumerate() {
+                let start = SNA_48K + 4 + i * BANK_SIZE;
+                ram[bank * BANK_SIZE..(bank + 1) * BANK_SIZE]
+                    .copy_from_slice(&sna[start..start + BANK_SIZE]);
+            }
+        }
+
+        let reg = &mut self.cpu.reg;
+        reg.i = h[0];
+        reg.ehl = word(h, 1);
+        reg.ede = word(h, 3);
+        reg.ebc = word(h, 5);
+        reg.eaf = word(h, 7);
+        reg.set_hl(word(h, 9));
+        reg.set_de(word(h, 11));
+        reg.set_bc(word(h, 13));
+        reg.set_iy(word(h, 15));
+        reg.set_ix(word(h, 17));
+        reg.r = h[20];
+        reg.set_af(word(h, 21));
+        reg.sp = word(h, 23);
+        self.cpu.iff2 = h[19] & 0x04 == 0x04;
+        self.cpu.iff1 = self.cpu.iff2;
+        self.cpu.im = match h[25] & 0x03 {
+            1 => InterruptMode::IM_1,
+            2 => InterruptMode::IM_2,
+            _ => InterruptMode::IM_0,
+        };
+        self.apply(State {
+            ram,
+            paging,
+            border: h[26],
+            psg: None,
+        });
+        // The PC of 48K snapshots is on the stack, as after a RETN
+        if sna.len() == SNA_48K {
+            let sp = self.cpu.reg.sp;
+            self.cpu.reg.pc = u16::from_le_bytes([
+                self.cpu.bus.read(sp),
+                self.cpu.bus.read(sp.wrapping_add(1)),
+            ]);
+            self.cpu.reg.sp = sp.wrapping_add(2);
+        } else {
+            self.cpu.reg.pc = word(sna, SNA_48K);
+        }
+        Ok(())
+    }
+
+    // Z80 snapshots versions 1 to 3, 48K and 128K/+2 hardware
+    pub fn load_z80(&mut self, z80: &[u8]) -> io::Result<()> {
+        let h = z80.get(..30).ok_or_else(|| invalid("not a Z80 snapshot"))?;
+        let flags = match h[12] {
+            0xFF => 0x01,
+            flags => flags,
+        };
+        let mut ram = vec![0_u8; BANK_SIZE * BANKS];
+        let mut pc = word(h, 6);
+        let mut paging = PAGING_48K;
+        let mut psg = None;
+        if pc != 0 {
+            // Version 1: 48 KiB, compressed if bit 5 of the flags is set
+            let data = &z80[30..];
+            let memory = match flags & 0x20 {
+                0 => data
+                    .get(..3 * BANK_SIZE)
+                    .ok_or_else(|| invalid("Z80 memory truncated"))?
+                    .to_vec(),
+                _ => decompress(data, 3 * BANK_SIZE)?,
+            };
+            for (block, bank) in memory.chunks(BANK_SIZE).zip(BANKS_48K) {
+                ram[bank * BANK_SIZE..(bank + 1) * BANK_SIZE].copy_from_slice(block);
+            }
+        } else {
+            let length = *z80.get(30).ok_or_else(|| invalid("Z80 header truncated"))? as usize;
+            let x = z80
+                .get(32..32 + length)
+                .ok_or_else(|| invalid("Z80 header truncated"))?;
+            if !matches!(length, 23 | 54 | 55) {
+                return Err(invalid("unknown Z80 version"));
+            }
+            pc = word(x, 0);
+            let is_128k = match (length, x[2]) {
+                (_, 0 | 1) | (54 | 55, 3) => false,
+                (23, 3 | 4) | (54 | 55, 4..=6 | 12) => true,
+                _ => return Err(invalid("Z80 snapshot of an unsupported machine")),
+            };
+            if is_128k {
+                paging = x[3];
+                let mut regs = [0_u8; 16];
+                regs.copy_from_slice(&x[7..23]);
+                psg = Some((x[6], regs));
+            }
+            // Blocks: length (0xFFFF when not compressed), page, data
+            let mut pos = 32 + length;
+            while pos < z80.len() {
+                let header = z80
+                    .get(pos..pos + 3)
+                    .ok_or_else(|| invalid("Z80 block header truncated"))?;
+                let (size, page) = (word(header, 0) as usize, header[2] as usize);
+                let stored = match size {
+                    0xFFFF => BANK_SIZE,
+                    size => size,
+                };
+                let data = z80
+                    .get(pos + 3..pos + 3 + stored)
+                    .ok_or_else(|| invalid("Z80 block truncated"))?;
+                let memory = match size {
+                    0xFFFF => data.to_vec(),
+                    _ => decompress(data, BANK_SIZE)?,
+                };
+                // Pages 3-10 are the 128K banks, 48K snapshots use 4, 5 and 8
+                let bank = match (is_128k, page) {
+                    (true, 3..=10) => Some(page - 3),
+                    (false, 4) => Some(2),
+                    (false, 5) => Some(0),
+                    (false, 8) => Some(5),
+                    _ => None,
+                };
+                if let Some(bank) = bank {
+                    ram[bank * BANK_SIZE..(bank + 1) * BANK_SIZE].copy_from_slice(&memory);
+                }
+                pos += 3 + stored;
+            }
+        }
+
+        let reg = &mut self.cpu.reg;
+        reg.set_af(u16::from_le_bytes([h[1], h[0]]));
+        reg.set_bc(word(h, 2));
+        reg.set_hl(word(h, 4));
+        reg.pc = pc;
+        reg.sp = word(h, 8);
+        reg.i = h[10];
+        reg.r = (h[11] & 0x7F) | ((flags & 0x01) << 7);
+        reg.set_de(word(h, 13));
+        reg.ebc = word(h, 15);
+        reg.ede = word(h, 17);
+        reg.ehl = word(h, 19);
+        reg.eaf = u16::from_le_bytes([h[22], h[21]]);
+        reg.set_iy(word(h, 23));
+        reg.set_ix(word(h, 25));
+        self.cpu.iff1 = h[27] != 0;
+        self.cpu.iff2 = h[28] != 0;
+        self.cpu.im = match h[29] & 0x03 {
+            1 => InterruptMode::IM_1,
+            2 => InterruptMode::IM_2,
+            _ => InterruptMode::IM_0,
+        };
+        self.apply(State {
+            ram,
+            paging,
+            border: (flags >> 1) & 0x07,
+            psg,
+        });
+        Ok(())
+    }
+
+    fn apply(&mut self, state: State) {
+        self.cpu.n_halt = true;
+        self.cpu.p_inst = 0;
+        let bus = &mut self.cpu.bus;
+        bus.ram = state.ram;
+        bus.paging = state.paging;
+        bus.core.ula.write(state.border & 0x07);
+        bus.core.beeper.level = 0.0;
+        bus.psg.reset();
+        if let Some((selected, regs)) = state.psg {
+            for (reg, value) in regs.into_iter().enumerate() {
+                bus.psg.select(reg as u8);
+                bus.psg.write(value);
+            }
+            bus.psg.select(selected);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+// ZX Spectrum keyboard: 8 half-rows of 5 keys, read on port &FE. Each of the lines
+// A8-A15 low selects a half-row.
+
+// Keys in matrix order, the value is half-row * 5 + bit
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[repr(u8)]
+pub enum Key {
+    CapsShift, Z, X, C, V,
+    A, S, D, F, G,
+    Q, W, E, R, T,
+    Key1, Key2, Key3, Key4, Key5,
+    Key0, Key9, Key8, Key7, Key6,
+    P, O, I, U, Y,
+    Enter, L, K, J, H,
+    Space, SymbolShift, M, N, B,
+}
+
+use Key::*;
+
+// Keys by matrix position, with their names
+pub const KEYS: [(Key, &str); 40] = [
+    (CapsShift, "CAPS SHIFT"), (Z, "Z"), (X, "X"), (C, "C"), (V, "V"),
+    (A, "A"), (S, "S"), (D, "D"), (F, "F"), (G, "G"),
+    (Q, "Q"), (W, "W"), (E, "E"), (R, "R"), (T, "T"),
+    (Key1, "1"), (Key2, "2"), (Key3, "3"), (Key4, "4"), (Key5, "5"),
+    (Key0, "0"), (Key9, "9"), (Key8, "8"), (Key7, "7"), (Key6, "6"),
+    (P, "P"), (O, "O"), (I, "I"), (U, "U"), (Y, "Y"),
+    (Enter, "ENTER"), (L, "L"), (K, "K"), (J, "J"), (H, "H"),
+    (Space, "SPACE"), (SymbolShift, "SYMBOL SHIFT"), (M, "M"), (N, "N"), (B, "B"),
+];
+
+impl Key {
+    pub fn row(self) -> usize {
+        self as usize / 5
+    }
+
+    pub fn mask(self) -> u8 {
+        1 << (self as u8 % 5)
+    }
+
+    pub fn name(self) -> &'static str {
+        KEYS[self as usize].1
+    }
+
+    // Name as printed on the key, case insensitive
+    pub fn from_name(name: &str) -> Option<Key> {
+        KEYS.iter()
+            .find(|(_, n)| n.eq_ignore_ascii_case(name))
+            .map(|&(key, _)| key)
+    }
+
+    // Key to press to type a letter, a digit, a space or a new line, and whether CAPS
+    // SHIFT is needed
+    pub fn from_char(c: char) -> Option<(Key, bool)> {
+        match c {
+            ' ' => Some((Space, false)),
+            '\n' => Some((Enter, false)),
+            _ if c.is_ascii_alphanumeric() => {
+                let key = Key::from_name(&c.to_ascii_uppercase().to_string())?;
+                Some((key, c.is_ascii_uppercase()))
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,346 @@
+// Sinclair ZX Spectrum 48K, and the core of the machine the 128K shares with it
+pub mod keyboard;
+pub mod tape;
+pub mod ula;
+
+use crate::devices::beeper::Beeper;
+use crate::devices::tape::{Tape, TapeDeck};
+use crate::m_cycles::{MCycle, MCycleKind};
+use crate::z80::*;
+use keyboard::Key;
+use std::io;
+use std::path::Path;
+use ula::{Ula, UlaTiming, SCREEN_SIZE, TIMING_48K};
+
+pub const CPU_CLOCK: u32 = 3_500_000;
+pub const ROM_SIZE: usize = 0x4000;
+const SCREEN: usize = 0x4000;
+
+// The part of the bus the 48K and the 128K share: the ULA, the speaker, the tape deck and
+// the keyboard, and the frames they run in
+pub struct SpectrumCore {
+    pub ula: Ula,
+    pub beeper: Beeper,
+    // There is no motor control, the tape plays while PLAY is pressed. MIC is recorded.
+    pub tape: TapeDeck,
+    // Half-rows of the keyboard, a key pressed reads as 0
+    pub keyboard: [u8; 8],
+    // Level of the EAR input, played by the tape
+    pub ear_in: bool,
+    // T-state of the start of the current frame
+    pub frame_start: u64,
+    // T-state of the last bus cycle, once contended, or of the end of the last
+    // instruction
+    pub clock: u64,
+    // Set at the end of each frame
+    pub frame_done: bool,
+    // T-state up to which the screen and the speaker have been run
+    pub(crate) synced: u64,
+}
+
+impl SpectrumCore {
+    pub fn new(timing: UlaTiming, cpu_clock: u32) -> Self {
+        Self {
+            ula: Ula::new(timing),
+            beeper: Beeper::new(cpu_clock),
+            tape: TapeDeck::new(cpu_clock as u64),
+            keyboard: [0x1F; 8],
+            ear_in: false,
+            frame_start: 0,
+            clock: 0,
+            frame_done: false,
+            synced: 0,
+        }
+    }
+
+    // T-state of the current frame
+    pub fn frame_t(&self, t: u64) -> u32 {
+        (t - self.frame_start) as u32
+    }
+
+    // Runs the screen and the speaker up to T-state `t`, ending the frames it reaches.
+    // `screen` is the memory the ULA displays.
+    pub fn sync(&mut self, t: u64, screen: &[u8]) {
+        let frame_t = self.ula.timing.frame_t() as u64;
+        while t >= self.frame_start + frame_t {
+            self.sync_frame(self.frame_start + frame_t, screen);
+            self.ula.end_frame(screen);
+            self.frame_start += frame_t;
+            self.frame_done = true;
+        }
+        self.sync_frame(t, screen);
+    }
+
+    fn sync_frame(&mut self, t: u64, screen: &[u8]) {
+        if t <= self.synced {
+            return;
+        }
+        let elapsed = t - self.synced;
+        self.beeper.run(elapsed as u32);
+        self.tape.motor = true;
+        if self.tape.running() {
+            self.tape.run(elapsed);
+            self.ear_in = self.tape.level;
+        }
+        self.tape.record(self.ula.mic, elapsed);
+        self.synced = t;
+        let frame_t = self.frame_t(t);
+        self.ula.render(frame_t, screen);
+    }
+
+    // Keys of the half-rows selected by the low lines of the high byte of the port
+    fn read_keyboard(&self, port: u16) -> u8 {
+        (0..8)
+            .filter(|row| port & (0x100 << row) == 0)
+            .fold(0x1F, |keys, row| keys & self.keyboard[row])
+    }
+
+    // Even ports. Bits 4-0: keys, 6: EAR input, which also sees the EAR output as on
+    // issue 3 boards.
+    pub fn read_ula(&mut self, port: u16, screen: &[u8]) -> u8 {
+        self.sync(self.clock, screen);
+        let ear = self.ear_in || self.ula.ear;
+        0xA0 | self.read_keyboard(port) | ((ear as u8) << 6)
+    }
+
+    pub fn write_ula(&mut self, data: u8, screen: &[u8]) {
+        self.sync(self.clock, screen);
+        self.ula.write(data);
+        self.beeper.level = self.ula.speaker_level();
+    }
+
+    // Byte read from a port nothing answers to
+    pub fn floating_bus(&self, screen: &[u8]) -> u8 {
+        self.ula.floating_bus(self.frame_t(self.clock), screen)
+    }
+
+    // Accesses to the memory shared with the ULA and IO cycles wait while the ULA reads
+    // the screen, as do the internal T-states which leave such an address on the bus.
+    // `contended` is true when the address of `m` is in that memory.
+    pub fn wait_states(&mut self, m: &MCycle, t_state: u64, contended: bool) -> u8 {
+        let t = self.frame_t(t_state);
+        let delay = match m.kind {
+            MCycleKind::OpcodeFetch | MCycleKind::MemoryRead | MCycleKind::MemoryWrite
+                if contended =>
+            {
+                self.ula.contention(t)
+            }
+            MCycleKind::IoRead | MCycleKind::IoWrite => {
+                self.ula.io_contention(m.addr, contended, t)
+            }
+            MCycleKind::Internal => self.ula.internal_contention(contended, t, m.t_states),
+            _ => 0,
+        };
+        self.clock = t_state + delay as u64;
+        delay
+    }
+
+    // /INT is low at the start of each frame
+    pub fn n_int(&self) -> bool {
+        self.frame_t(self.clock) >= self.ula.timing.int_length
+    }
+
+    pub fn reset(&mut self) {
+        self.ula.write(0x00);
+        self.beeper.level = 0.0;
+    }
+}
+
+// Bus of a Spectrum model, built around the shared core
+pub trait SpectrumBus: Bus {
+    fn core(&self) -> &SpectrumCore;
+    fn core_mut(&mut self) -> &mut SpectrumCore;
+    // Runs the screen and the sound up to T-state `t`
+    fn sync(&mut self, t: u64);
+    // The ROM holding LD-BYTES is paged in
+    fn ld_bytes_paged(&self) -> bool {
+        true
+    }
+}
+
+pub struct Spectrum48Bus {
+    // ROM in 0x0000-0x3FFF, RAM above
+    pub memory: Vec<u8>,
+    pub core: SpectrumCore,
+}
+
+// The ULA shares the bus of the RAM at 0x4000-0x7FFF with the CPU
+fn contended(addr: u16) -> bool {
+    addr & 0xC000 == 0x4000
+}
+
+impl Spectrum48Bus {
+    pub fn new(rom: &[u8]) -> Self {
+        let mut memory = vec![0_u8; 0x10000];
+        memory[..ROM_SIZE].copy_from_slice(&rom[..ROM_SIZE]);
+        Self {
+            memory,
+            core: SpectrumCore::new(TIMING_48K, CPU_CLOCK),
+        }
+    }
+
+    pub fn screen(&self) -> &[u8] {
+        &self.memory[SCREEN..SCREEN + SCREEN_SIZE]
+    }
+}
+
+impl SpectrumBus for Spectrum48Bus {
+    fn core(&self) -> &SpectrumCore {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut SpectrumCore {
+        &mut self.core
+    }
+
+    fn sync(&mut self, t: u64) {
+        self.core
+            .sync(t, &self.memory[SCREEN..SCREEN + SCREEN_SIZE]);
+    }
+}
+
+impl Bus for Spectrum48Bus {
+    fn read(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        if addr as usize >= ROM_SIZE {
+            self.memory[addr as usize] = data;
+        }
+    }
+
+    // The ULA answers to the even ports
+    fn read_io(&mut self, addr: u16) -> u8 {
+        let screen = &self.memory[SCREEN..SCREEN + SCREEN_SIZE];
+        match addr & 0x0001 {
+            0 => self.core.read_ula(addr, screen),
+            _ => self.core.floating_bus(screen),
+        }
+    }
+
+    fn write_io(&mut self, addr: u16, data: u8) {
+        if addr & 0x0001 == 0 {
+            self.core
+                .write_ula(data, &self.memory[SCREEN..SCREEN + SCREEN_SIZE]);
+        }
+    }
+
+    fn wait_states(&mut self, m: &MCycle, t_state: u64) -> u8 {
+        self.core.wait_states(m, t_state, contended(m.addr))
+    }
+
+    fn n_int(&mut self) -> bool {
+        self.core.n_int()
+    }
+
+    fn reset(&mut self) {
+        self.core.reset();
+    }
+}
+
+pub struct Spectrum<B: SpectrumBus> {
+    pub cpu: Z80<B>,
+    // Frames run since power on
+    pub frames: u64,
+    // Blocks are loaded at once when the ROM calls LD-BYTES, instead of being played
+    pub fast_load: bool,
+}
+
+pub type Spectrum48 = Spectrum<Spectrum48Bus>;
+
+impl<B: SpectrumBus> Spectrum<B> {
+    pub(crate) fn with_bus(bus: B) -> Self {
+        let mut spectrum = Self {
+            cpu: Z80::with_bus(bus),
+            frames: 0,
+            fast_load: true,
+        };
+        spectrum.cpu.traps.push(tape::LD_BYTES);
+        spectrum.reset();
+        spectrum
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+    }
+
+    // Inserts the tape and presses PLAY
+    pub fn insert_tape(&mut self, tape: Tape) {
+        let deck = &mut self.cpu.bus.core_mut().tape;
+        deck.insert(tape);
+        deck.playing = true;
+    }
+
+    // Loads the next block of the tape for LD-BYTES. Returns false to let the ROM
+    // play it.
+    fn load_block(&mut self) -> bool {
+        if !self.fast_load || !self.cpu.bus.ld_bytes_paged() {
+            return false;
+        }
+        let deck = &mut self.cpu.bus.core_mut().tape;
+        let Some(block) = tape::next_block(deck).cloned() else {
+            return false;
+        };
+        deck.seek(block.end);
+        tape::ld_bytes(&mut self.cpu, &block.data);
+        true
+    }
+
+    // Runs one instruction and the ULA for the time it took.
+    // Returns the number of T-states used.
+    pub fn step(&mut self) -> u32 {
+        let start = self.cpu.clock;
+        self.cpu.execute();
+        if self.cpu.trapped == Some(tape::LD_BYTES) && self.load_block() {
+            self.cpu.trapped = None;
+        }
+        self.cpu.bus.core_mut().clock = self.cpu.clock;
+        self.cpu.bus.sync(self.cpu.clock);
+        (self.cpu.clock - start) as u32
+    }
+
+    // Runs until the end of the frame
+    pub fn run_frame(&mut self) {
+        self.frames += 1;
+        self.cpu.bus.core_mut().frame_done = false;
+        while !self.cpu.bus.core().frame_done {
+            self.step();
+        }
+    }
+
+    pub fn press_key(&mut self, key: Key) {
+        self.cpu.bus.core_mut().keyboard[key.row()] &= !key.mask();
+    }
+
+    pub fn release_key(&mut self, key: Key) {
+        self.cpu.bus.core_mut().keyboard[key.row()] |= key.mask();
+    }
+
+    pub fn release_all_keys(&mut self) {
+        self.cpu.bus.core_mut().keyboard = [0x1F; 8];
+    }
+
+    // Last frame, ula::WIDTH by ula::HEIGHT pixels
+    pub fn frame(&self) -> &[u32] {
+        &self.cpu.bus.core().ula.frame
+    }
+}
+
+impl Spectrum48 {
+    // `rom` is the 16 KiB ROM
+    pub fn new(rom: &[u8]) -> io::Result<Self> {
+        if rom.len() != ROM_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the ROM image must be 16 KiB",
+            ));
+        }
+        Ok(Self::with_bus(Spectrum48Bus::new(rom)))
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let rom = std::fs::read(path)?;
+        Self::new(&rom)
+    }
+}
@@ -0,0 +1,50 @@
+// Fast loading of the tape blocks through a trap on LD-BYTES in the 48K ROM, also used by
+// the 128K with ROM 1 paged
+
+use crate::devices::tape::{StandardBlock, TapeDeck};
+use crate::z80::*;
+
+// Entry of LD-BYTES: A holds the flag byte, IX the address, DE the length, the carry is
+// set to load and clear to verify
+pub const LD_BYTES: u16 = 0x0556;
+// SA/LD-RET restores the border, enables the interrupts and returns with the carry set
+// when the block was loaded
+const SA_LD_RET: u16 = 0x053F;
+
+// Block the ROM loader would read next: the first one not played to its end
+pub fn next_block(deck: &TapeDeck) -> Option<&StandardBlock> {
+    let tape = deck.tape.as_ref()?;
+    tape.blocks.iter().find(|b| b.end > deck.position())
+}
+
+// Does what LD-BYTES does with the bytes of the block, then leaves through SA/LD-RET
+pub fn ld_bytes<B: Bus>(cpu: &mut Z80<B>, data: &[u8]) {
+    let load = cpu.reg.flags.c;
+    let mut ix = cpu.reg.get_ix();
+    let mut de = cpu.reg.get_de();
+    let mut ok = data.first() == Some(&cpu.reg.a);
+    if ok {
+        // The XOR of the flag, the bytes and the checksum is 0
+        let mut parity = data[0];
+        for &byte in data[1..].iter().take(de as usize) {
+            parity ^= byte;
+            if load {
+                cpu.bus.write(ix, byte);
+            } else if cpu.bus.read(ix) != byte {
+                ok = false;
+                break;
+            }
+            ix = ix.wrapping_add(1);
+            de -= 1;
+        }
+        // The checksum follows the bytes asked for
+        match data.get(1 + cpu.reg.get_de() as usize) {
+            Some(&checksum) if ok => ok = parity ^ checksum == 0,
+            _ => ok = false,
+        }
+    }
+    cpu.reg.set_ix(ix);
+    cpu.reg.set_de(de);
+    cpu.reg.flags.c = ok;
+    cpu.reg.pc = SA_LD_RET;
+}
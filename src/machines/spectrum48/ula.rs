@@ -0,0 +1,241 @@
+// Sinclair ULA: video, border, speaker and the contention of the CPU
+
+use crate::machines::image;
+use std::io;
+use std::path::Path;
+
+pub struct UlaTiming {
+    // T-states per line and lines per frame
+    pub line_t: u32,
+    pub lines: u32,
+    // T-state of the first pixel of the screen, counted from the start of the interrupt
+    pub first_pixel: u32,
+    // First T-state delayed by the contention
+    pub contention_start: u32,
+    // T-states /INT is held low
+    pub int_length: u32,
+}
+
+pub const TIMING_48K: UlaTiming = UlaTiming {
+    line_t: 224,
+    lines: 312,
+    first_pixel: 14336,
+    contention_start: 14335,
+    int_length: 32,
+};
+
+// 128K and +2
+pub const TIMING_128K: UlaTiming = UlaTiming {
+    line_t: 228,
+    lines: 311,
+    first_pixel: 14362,
+    contention_start: 14361,
+    int_length: 36,
+};
+
+impl UlaTiming {
+    pub fn frame_t(&self) -> u32 {
+        self.line_t * self.lines
+    }
+}
+
+// 256 x 192 screen with 48 pixels of border on each side, 56 lines below
+pub const WIDTH: usize = 352;
+pub const HEIGHT: usize = 296;
+const BORDER_LEFT: usize = 48;
+const BORDER_TOP: usize = 48;
+// Bitmap of 6144 bytes followed by the attributes
+pub const SCREEN_SIZE: usize = 6912;
+const ATTRIBUTES: usize = 6144;
+
+// Bit 0 is blue, 1 red, 2 green, 3 bright
+pub const PALETTE: [u32; 16] = [
+    0x000000, 0x0000D7, 0xD70000, 0xD700D7, 0x00D700, 0x00D7D7, 0xD7D700, 0xD7D7D7,
+    0x000000, 0x0000FF, 0xFF0000, 0xFF00FF, 0x00FF00, 0x00FFFF, 0xFFFF00, 0xFFFFFF,
+];
+
+// Extra T-states of a contended access by position in a group of 8
+const CONTENTION: [u8; 8] = [6, 5, 4, 3, 2, 1, 0, 0];
+
+pub struct Ula {
+    pub timing: UlaTiming,
+    // Written to port &FE: bits 2-0 border, 3 MIC, 4 EAR
+    pub border: u8,
+    pub mic: bool,
+    pub ear: bool,
+    // 0x00RRGGBB pixels
+    pub frame: Vec<u32>,
+    // Frames drawn since power on, FLASH swaps ink and paper every 16 frames
+    pub frames: u64,
+    // Next T-state of the frame to draw
+    drawn: u32,
+}
+
+// Offset in the screen of the bitmap byte of a line and a column of 8 pixels
+fn bitmap_offset(y: usize, col: usize) -> usize {
+    ((y & 0xC0) << 5) | ((y & 0x07) << 8) | ((y & 0x38) << 2) | col
+}
+
+fn attribute_offset(y: usize, col: usize) -> usize {
+    ATTRIBUTES + (y / 8) * 32 + col
+}
+
+impl Ula {
+    pub fn new(timing: UlaTiming) -> Self {
+        Self {
+            timing,
+            border: 0,
+            mic: false,
+            ear: false,
+            frame: vec![0; WIDTH * HEIGHT],
+            frames: 0,
+            drawn: 0,
+        }
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.border = data & 0x07;
+        self.mic = data & 0x08 == 0x08;
+        self.ear = data & 0x10 == 0x10;
+    }
+
+    // EAR drives the speaker, MIC is heard faintly
+    pub fn speaker_level(&self) -> f32 {
+        match (self.ear, self.mic) {
+            (true, _) => 1.0,
+            (false, true) => 0.1,
+            (false, false) => 0.0,
+        }
+    }
+
+    // T-states a contended memory access starting at T-state `t` of the frame waits
+    // while the ULA fetches the screen
+    pub fn contention(&self, t: u32) -> u8 {
+        let timing = &self.timing;
+        let t = t % timing.frame_t();
+        if t < timing.contention_start {
+            return 0;
+        }
+        let t = t - timing.contention_start;
+        let column = t % timing.line_t;
+        if t / timing.line_t >= 192 || column >= 128 {
+            return 0;
+        }
+        CONTENTION[column as usize % 8]
+    }
+
+    // T-states an IO cycle starting at T-state `t` is stretched by. `contended` is true
+    // when the high byte of the port is in contended memory. The cycle is split in parts
+    // of 1 and 3 T-states (or 4 times 1), each waiting for the ULA when contended:
+    //   not contended, odd port:  N:4
+    //   not contended, even port: N:1 C:3
+    //   contended, even port:     C:1 C:3
+    //   contended, odd port:      C:1 C:1 C:1 C:1
+    pub fn io_contention(&self, port: u16, contended: bool, t: u32) -> u8 {
+        let pattern: &[(bool, u32)] = match (contended, port & 0x0001 == 0) {
+            (false, false) => &[(false, 4)],
+            (false, true) => &[(false, 1), (true, 3)],
+            (true, true) => &[(true, 1), (true, 3)],
+            (true, false) => &[(true, 1); 4],
+        };
+        let mut now = t;
+        for &(contended, length) in pattern {
+            if contended {
+                now += self.contention(now) as u32;
+            }
+            now += length;
+        }
+        (now - t - 4) as u8
+    }
+
+    // T-states a run of internal T-states starting at T-state `t` is stretched by.
+    // The address stays on the bus and each T-state waits for the ULA when contended,
+    // e.g. hl:1 of INC (HL) or pc+1:1 x5 of JR.
+    pub fn internal_contention(&self, contended: bool, t: u32, t_states: u8) -> u8 {
+        if !contended {
+            return 0;
+        }
+        let mut now = t;
+        for _ in 0..t_states {
+            now += self.contention(now) as u32 + 1;
+        }
+        (now - t - t_states as u32) as u8
+    }
+
+    // Byte seen on the data bus when reading a port nothing answers to: the bitmap and
+    // attribute bytes fetched by the ULA, 0xFF outside of the screen
+    pub fn floating_bus(&self, t: u32, screen: &[u8]) -> u8 {
+        let timing = &self.timing;
+        let t = t % timing.frame_t();
+        if t < timing.first_pixel {
+            return 0xFF;
+        }
+        let t = t - timing.first_pixel;
+        let (y, column) = ((t / timing.line_t) as usize, (t % timing.line_t) as usize);
+        if y >= 192 || column >= 128 {
+            return 0xFF;
+        }
+        let col = column / 8 * 2;
+        match column % 8 {
+            0 => screen[bitmap_offset(y, col)],
+            1 => screen[attribute_offset(y, col)],
+            2 => screen[bitmap_offset(y, col + 1)],
+            3 => screen[attribute_offset(y, col + 1)],
+            _ => 0xFF,
+        }
+    }
+
+    // Draws the frame up to T-state `t`, two pixels per T-state
+    pub fn render(&mut self, t: u32, screen: &[u8]) {
+        let timing = &self.timing;
+        let end = t.min(timing.frame_t());
+        // T-state of the top left pixel of the border
+        let origin = timing.first_pixel
+            - BORDER_TOP as u32 * timing.line_t
+            - BORDER_LEFT as u32 / 2;
+        let flash = (self.frames / 16) % 2 == 1;
+        for t in self.drawn.max(origin)..end {
+            let t = t - origin;
+            let (y, x) = ((t / timing.line_t) as usize, (t % timing.line_t) as usize * 2);
+            if y >= HEIGHT || x >= WIDTH {
+                continue;
+            }
+            let pixels = &mut self.frame[y * WIDTH + x..y * WIDTH + x + 2];
+            let (sx, sy) = (x.wrapping_sub(BORDER_LEFT), y.wrapping_sub(BORDER_TOP));
+            if sx >= 256 || sy >= 192 {
+                pixels.fill(PALETTE[self.border as usize]);
+                continue;
+            }
+            let bitmap = screen[bitmap_offset(sy, sx / 8)];
+            let attribute = screen[attribute_offset(sy, sx / 8)];
+            let bright = (attribute >> 3) & 0x08;
+            let mut ink = PALETTE[((attribute & 0x07) | bright) as usize];
+            let mut paper = PALETTE[(((attribute >> 3) & 0x07) | bright) as usize];
+            if flash && attribute & 0x80 == 0x80 {
+                std::mem::swap(&mut ink, &mut paper);
+            }
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = match bitmap & (0x80 >> (sx % 8 + i)) {
+                    0 => paper,
+                    _ => ink,
+                };
+            }
+        }
+        self.drawn = self.drawn.max(end);
+    }
+
+    // Draws the rest of the frame and starts the next one
+    pub fn end_frame(&mut self, screen: &[u8]) {
+        self.render(self.timing.frame_t(), screen);
+        self.drawn = 0;
+        self.frames += 1;
+    }
+
+    pub fn save_ppm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        image::save_ppm(path, WIDTH, HEIGHT, &self.frame)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        image::save_png(path, WIDTH, HEIGHT, &self.frame)
+    }
+}
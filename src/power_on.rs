@@ -0,0 +1,66 @@
+use crate::z80::*;
+
+// Content of the registers or of the RAM when the power is switched on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerOnFill {
+    Value(u8),
+    // Pseudo random bytes generated from a seed
+    Random(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerOnState {
+    pub registers: PowerOnFill,
+    pub memory: PowerOnFill,
+}
+
+impl Default for PowerOnState {
+    fn default() -> Self {
+        Self {
+            registers: PowerOnFill::Value(0xFF),
+            memory: PowerOnFill::Value(0x00),
+        }
+    }
+}
+
+impl PowerOnFill {
+    pub fn generator(self) -> impl FnMut() -> u8 {
+        // xorshift64*, the state must not be zero
+        let mut state = match self {
+            PowerOnFill::Value(_) => 0,
+            PowerOnFill::Random(seed) => seed | 1,
+        };
+        move || match self {
+            PowerOnFill::Value(v) => v,
+            PowerOnFill::Random(_) => {
+                state ^= state >> 12;
+                state ^= state << 25;
+                state ^= state >> 27;
+                (state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+            }
+        }
+    }
+}
+
+impl<B: Bus> Z80<B> {
+    // Power on the CPU and its RAM. Apart from PC, I and R which are cleared as on
+    // reset, registers and memory are filled as requested.
+    pub fn power_on(&mut self, state: &PowerOnState) {
+        let mut fill = state.registers.generator();
+        self.reg.power_on(&mut fill);
+        let mut fill = state.memory.generator();
+        self.bus.power_on(&mut fill);
+        self.clock = 0;
+        self.reset();
+    }
+
+    // While /RESET is low the CPU does nothing and is reset on each clock cycle
+    pub(crate) fn hold_in_reset(&mut self) -> bool {
+        if self.n_reset {
+            return false;
+        }
+        self.reset();
+        self.clock += 1;
+        true
+    }
+}
@@ -24,6 +24,12 @@ pub struct Registers {
     pub ehl: u16,
 }
 
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Registers {
     pub fn new() -> Self {
         Self {
@@ -38,7 +44,7 @@ impl Registers {
             ixl: 0xFF,
             iyh: 0xFF,
             iyl: 0xFF,
-            i: 0xFF,
+            i: 0x00,
             r: 0x00,
             sp: 0xFFFF,
             pc: 0x0000,
@@ -124,26 +130,31 @@ impl Registers {
         self.sp = self.sp.wrapping_sub(1);
     }
 
+    // Only the lower 7 bits count, bit 7 keeps the value set by LD R,A
     pub fn inc_r(&mut self) {
-        self.r = self.r & 0x80 + self.r.wrapping_add(1) & 0x7F;
+        self.r = (self.r & 0x80) | (self.r.wrapping_add(1) & 0x7F);
     }
 
+    // /RESET only clears PC, I and R
     pub fn reset(&mut self) {
-        self.a = 0xFF;
-        self.b = 0xFF;
-        self.c = 0xFF;
-        self.d = 0xFF;
-        self.e = 0xFF;
-        self.h = 0xFF;
-        self.l = 0xFF;
-        self.ixh = 0xFF;
-        self.ixl = 0xFF;
-        self.iyh = 0xFF;
-        self.iyl = 0xFF;
-        self.i = 0xFF;
+        self.i = 0x00;
         self.r = 0x00;
-        self.sp = 0xFFFF;
         self.pc = 0x0000;
-        self.flags.reset();
+    }
+
+    // Power on: registers other than PC, I and R hold whatever `fill` gives
+    pub fn power_on(&mut self, fill: &mut dyn FnMut() -> u8) {
+        self.set_af(u16::from_le_bytes([fill(), fill()]));
+        self.set_bc(u16::from_le_bytes([fill(), fill()]));
+        self.set_de(u16::from_le_bytes([fill(), fill()]));
+        self.set_hl(u16::from_le_bytes([fill(), fill()]));
+        self.set_ix(u16::from_le_bytes([fill(), fill()]));
+        self.set_iy(u16::from_le_bytes([fill(), fill()]));
+        self.sp = u16::from_le_bytes([fill(), fill()]);
+        self.eaf = u16::from_le_bytes([fill(), fill()]);
+        self.ebc = u16::from_le_bytes([fill(), fill()]);
+        self.ede = u16::from_le_bytes([fill(), fill()]);
+        self.ehl = u16::from_le_bytes([fill(), fill()]);
+        self.reset();
     }
 }
@@ -1,4 +1,5 @@
-use crate::bus::Bus;
+pub use crate::bus::{Bus, FlatBus};
+use crate::m_cycles::MCycle;
 use crate::registers::Registers;
 
 #[allow(nonstandard_style)]
@@ -9,11 +10,13 @@ pub enum InterruptMode {
 }
 
 // Structure of the Z80 processor
-pub struct Z80 {
+pub struct Z80<B: Bus = FlatBus> {
     // Registers
     pub reg: Registers,
     // Address bus and Data bus
-    pub bus: Bus,
+    pub bus: B,
+    pub addr_bus: u16,
+    pub data_bus: u8,
     // System control pins
     pub n_m1: bool,
     pub n_mreq: bool,
@@ -30,20 +33,49 @@ pub struct Z80 {
     pub iff1: bool,
     pub iff2: bool,
     pub im: InterruptMode,
+    // Data put on the bus by the interrupting device during the acknowledge
+    pub int_vector: u8,
     // CPU bus control
     pub n_busrq: bool,
     pub n_busack: bool,
     // Previous instruction
     pub p_inst: u8,
-    // Added the clock pin but might not be used
-    pub _clock: u64,
+    // T-states elapsed since power on
+    pub clock: u64,
+    // Addresses where execute() stops before fetching an opcode, so that the machine can
+    // run host code instead. The address hit is put in `trapped`: if it is left there,
+    // the next execute() runs the instruction.
+    pub traps: Vec<u16>,
+    pub trapped: Option<u16>,
+    // Machine cycles of the current instruction, played back by tick()
+    pub m_cycles: Vec<MCycle>,
+    pub(crate) m_index: usize,
+    pub(crate) t_index: u32,
+    pub(crate) t_offset: u32,
+    pub(crate) deferred: bool,
+    // Last sampled level of /NMI which is edge triggered
+    pub(crate) nmi_latch: bool,
+}
+
+impl Default for Z80 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Z80 {
     pub fn new() -> Self {
+        Self::with_bus(FlatBus::new())
+    }
+}
+
+impl<B: Bus> Z80<B> {
+    pub fn with_bus(bus: B) -> Self {
         Self {
             reg: Registers::new(),
-            bus: Bus::new(),
+            bus,
+            addr_bus: 0x0000,
+            data_bus: 0xFF,
             n_m1: true,
             n_mreq: true,
             n_iorq: true,
@@ -58,34 +90,46 @@ impl Z80 {
             iff1: false,
             iff2: false,
             im: InterruptMode::IM_0,
+            int_vector: 0xFF,
             n_busrq: true,
             n_busack: true,
             p_inst: 0,
-            _clock: 0_u64,
+            clock: 0_u64,
+            traps: Vec::new(),
+            trapped: None,
+            m_cycles: Vec::new(),
+            m_index: 0,
+            t_index: 0,
+            t_offset: 0,
+            deferred: false,
+            nmi_latch: true,
         }
     }
 
+    // Same as a pulse on /RESET: only PC, I, R, the interrupt flip-flops and the
+    // interrupt mode are cleared. Memory and the other registers are left untouched.
+    // The input pins are driven from outside and keep their level.
     pub fn reset(&mut self) {
         self.reg.reset();
         self.bus.reset();
         self.n_busack = true;
-        self.n_busrq = true;
         self.n_halt = true;
-        self.n_int = true;
         self.n_iorq = true;
         self.n_m1 = true;
         self.n_mreq = true;
-        self.n_nmi = true;
         self.n_rd = true;
-        self.n_reset = true;
         self.iff1 = false;
         self.iff2 = false;
         self.im = InterruptMode::IM_0;
         self.n_rfsh = true;
-        self.n_wait = true;
         self.n_wr = true;
-        self.p_inst;
-        self._clock = 0;
+        self.p_inst = 0;
+        self.trapped = None;
+        self.m_cycles.clear();
+        self.m_index = 0;
+        self.t_index = 0;
+        self.t_offset = 0;
+        self.nmi_latch = self.n_nmi;
     }
 
     pub fn display_regs(&self) {